@@ -19,11 +19,23 @@ public class SurfmanInstrumentedTest {
     private static native void testContextCreation();
     private static native void testCrossDeviceSurfaceTextureBlitFramebuffer();
     private static native void testCrossThreadSurfaceTextureBlitFramebuffer();
+    private static native void testDebugContext();
+    private static native void testDepthFormats();
     private static native void testDeviceAccessors();
+    private static native void testAdapterEnumeration();
     private static native void testDeviceCreation();
     private static native void testGenericSurfaceCreation();
     private static native void testGL();
+    private static native void testFences();
+    private static native void testPresentGenericSurface();
+    private static native void testGenericSurfaceBufferAge();
+    private static native void testMultisampledSurface();
     private static native void testNewlyCreatedContextsAreCurrent();
+    private static native void testReadSurfacePixels();
+    private static native void testRobustContext();
+    private static native void testSurfaceDataLock();
+    private static native void testSurfaceFormats();
+    private static native void testSurfaceReadback();
     private static native void testSurfaceTextureBlitFramebuffer();
     private static native void testSurfaceTextureRightSideUp();
 
@@ -54,11 +66,26 @@ public class SurfmanInstrumentedTest {
         testCrossThreadSurfaceTextureBlitFramebuffer();
     }
 
+    @Test
+    public void debugContext() {
+        testDebugContext();
+    }
+
+    @Test
+    public void depthFormats() {
+        testDepthFormats();
+    }
+
     @Test
     public void deviceAccessors() {
         testDeviceAccessors();
     }
 
+    @Test
+    public void adapterEnumeration() {
+        testAdapterEnumeration();
+    }
+
     @Test
     public void deviceCreation() {
         testDeviceCreation();
@@ -74,11 +101,56 @@ public class SurfmanInstrumentedTest {
         testGL();
     }
 
+    @Test
+    public void fences() {
+        testFences();
+    }
+
+    @Test
+    public void presentGenericSurface() {
+        testPresentGenericSurface();
+    }
+
+    @Test
+    public void genericSurfaceBufferAge() {
+        testGenericSurfaceBufferAge();
+    }
+
+    @Test
+    public void multisampledSurface() {
+        testMultisampledSurface();
+    }
+
     @Test
     public void newlyCreatedContextsAreCurrent() {
         testNewlyCreatedContextsAreCurrent();
     }
 
+    @Test
+    public void readSurfacePixels() {
+        testReadSurfacePixels();
+    }
+
+    @Test
+    public void robustContext() {
+        testRobustContext();
+    }
+
+    @Test
+    public void surfaceDataLock() {
+        testSurfaceDataLock();
+    }
+
+    @Test
+    public void surfaceFormats() {
+        testSurfaceFormats();
+    }
+
+    @Test
+    public void surfaceReadback() {
+        testSurfaceReadback();
+    }
+
     @Test
     public void surfaceTextureBlitFramebuffer() {
         testSurfaceTextureBlitFramebuffer();
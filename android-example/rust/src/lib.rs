@@ -95,6 +95,22 @@ pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInst
     tests::test_cross_thread_surface_texture_blit_framebuffer();
 }
 
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDebugContext(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_debug_context();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDepthFormats(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_depth_formats();
+}
+
 #[no_mangle]
 pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDeviceAccessors(
     _env: JNIEnv,
@@ -103,6 +119,14 @@ pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInst
     tests::test_device_accessors();
 }
 
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testAdapterEnumeration(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_adapter_enumeration();
+}
+
 #[no_mangle]
 pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testDeviceCreation(
     _env: JNIEnv,
@@ -127,6 +151,38 @@ pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInst
     tests::test_gl();
 }
 
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testFences(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_fences();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testPresentGenericSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_present_generic_surface();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testGenericSurfaceBufferAge(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_generic_surface_buffer_age();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testMultisampledSurface(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_multisampled_surface();
+}
+
 #[no_mangle]
 pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testNewlyCreatedContextsAreCurrent(
     _env: JNIEnv,
@@ -135,6 +191,46 @@ pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInst
     tests::test_newly_created_contexts_are_current();
 }
 
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testReadSurfacePixels(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_read_surface_pixels();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testRobustContext(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_robust_context();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceDataLock(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_data_lock();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceFormats(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_formats();
+}
+
+#[no_mangle]
+pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceReadback(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    tests::test_surface_readback();
+}
+
 #[no_mangle]
 pub unsafe extern "system" fn Java_org_mozilla_surfmanthreadsexample_SurfmanInstrumentedTest_testSurfaceTextureBlitFramebuffer(
     _env: JNIEnv,
@@ -22,6 +22,7 @@ fn main() {
         // Native displays.
         x11_platform: { all(free_unix, feature = "sm-x11") },
         wayland_platform: { all(free_unix) },
+        gbm_platform: { all(free_unix, feature = "sm-gbm") },
 
         // Features:
         // Here we collect the features that are only valid on certain platforms and
@@ -13,7 +13,7 @@ use std::mem;
 use std::path::Path;
 use std::slice;
 use surfman::{Connection, ContextAttributeFlags, ContextAttributes, GLApi, GLVersion};
-use surfman::{SurfaceAccess, SurfaceType};
+use surfman::{DepthFormat, SurfaceAccess, SurfaceFormat, SurfaceType};
 
 mod common;
 
@@ -101,6 +101,9 @@ fn main() {
     let context_attributes = ContextAttributes {
         version: GLVersion::new(3, 3),
         flags: ContextAttributeFlags::empty(),
+        samples: 0,
+        surface_format: SurfaceFormat::RGBA8,
+        depth_format: DepthFormat::Depth24,
     };
     let context_descriptor = device
         .create_context_descriptor(&context_attributes)
@@ -17,7 +17,7 @@ use surfman::{Adapter, Connection, Context, ContextDescriptor, Device, GLApi, Su
 use self::common::FilesystemResourceLoader;
 
 #[cfg(not(target_os = "android"))]
-use surfman::{ContextAttributeFlags, ContextAttributes, GLVersion};
+use surfman::{ContextAttributeFlags, ContextAttributes, DepthFormat, GLVersion, SurfaceFormat};
 #[cfg(not(target_os = "android"))]
 use winit::{
     dpi::PhysicalSize,
@@ -143,6 +143,9 @@ fn main() {
     let context_attributes = ContextAttributes {
         version: GLVersion::new(3, 0),
         flags: ContextAttributeFlags::ALPHA,
+        samples: 0,
+        surface_format: SurfaceFormat::RGBA8,
+        depth_format: DepthFormat::Depth24,
     };
     let context_descriptor = device
         .create_context_descriptor(&context_attributes)
@@ -8,8 +8,7 @@
 use super::device::{Adapter, Device, NativeDevice, VendorPreference};
 use super::surface::NativeWidget;
 use crate::egl::types::{EGLDisplay, EGLNativeWindowType};
-use crate::Error;
-use crate::GLApi;
+use crate::{AdapterInfo, Error, GLApi};
 
 use euclid::default::Size2D;
 
@@ -95,6 +94,12 @@ impl Connection {
         Adapter::new(D3D_DRIVER_TYPE_WARP, VendorPreference::None)
     }
 
+    /// Returns an empty list, since this backend can't enumerate adapters.
+    #[inline]
+    pub fn enumerate_adapters(&self) -> Vec<AdapterInfo<Adapter>> {
+        vec![]
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
     /// Device handles are local to a single thread.
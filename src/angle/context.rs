@@ -2,6 +2,7 @@
 
 use super::surface::Surface;
 pub use crate::base::egl::context::{ContextDescriptor, NativeContext};
+use crate::base::egl::fence::EGLFence;
 use crate::base::egl::surface::ExternalEGLSurfaces;
 use crate::context::ContextID;
 use crate::egl;
@@ -42,3 +43,11 @@ impl Drop for Context {
         }
     }
 }
+
+/// Represents a GPU fence, which is signaled once the GPU finishes all commands issued before it.
+///
+/// Fences can be waited on from any context on a device that shares the same connection, on any
+/// thread.
+///
+/// A fence must be explicitly destroyed with `destroy_fence()`, or a panic will occur.
+pub struct Fence(pub(crate) EGLFence);
@@ -1,32 +1,36 @@
 //! A thread-local handle to the device.
 
 use crate::angle::connection::Connection;
-use crate::angle::context::{Context, ContextDescriptor, NativeContext};
+use crate::angle::context::{Context, ContextDescriptor, Fence, NativeContext};
 use crate::angle::surface::{
     NativeWidget, Surface, SurfaceDataGuard, SurfaceTexture, Synchronization, Win32Objects,
 };
-use crate::base::egl::context::{self, CurrentContextGuard};
+use crate::base::egl::context::{self, CurrentContextGuard, GenericSurfaceAttributes};
 use crate::base::egl::device::EGL_FUNCTIONS;
 use crate::base::egl::error::ToWindowingApiError;
+use crate::base::egl::fence::EGLFence;
 use crate::base::egl::ffi::{
     EGL_D3D11_DEVICE_ANGLE, EGL_D3D_TEXTURE_2D_SHARE_HANDLE_ANGLE, EGL_D3D_TEXTURE_ANGLE,
     EGL_DEVICE_EXT, EGL_DXGI_KEYED_MUTEX_ANGLE, EGL_EXTENSION_FUNCTIONS, EGL_NO_DEVICE_EXT,
     EGL_PLATFORM_DEVICE_EXT,
 };
-use crate::base::egl::surface::ExternalEGLSurfaces;
+use crate::base::egl::surface::{self, ExternalEGLSurfaces};
 use crate::context::{ContextID, CREATE_CONTEXT_MUTEX};
 use crate::egl::types::{EGLAttrib, EGLConfig, EGLDeviceEXT, EGLDisplay, EGLSurface, EGLint};
+use crate::readback::ReadbackHandle;
 use crate::surface::Framebuffer;
 use crate::{
-    egl, gl, ContextAttributes, Error, GLApi, Gl, SurfaceAccess, SurfaceInfo, SurfaceType,
+    egl, gl, gl_utils, ContextAttributes, ContextResetStatus, Error, GLApi, Gl, PixelFormat,
+    SurfaceAccess, SurfaceFormat, SurfaceInfo, SurfaceType,
 };
-use euclid::default::Size2D;
+use euclid::default::{Rect, Size2D};
 use glow::HasContext;
 use std::cell::{RefCell, RefMut};
 use std::marker::PhantomData;
 use std::mem;
 use std::os::raw::c_void;
 use std::ptr;
+use std::time::Duration;
 use winapi::shared::dxgi::IDXGIKeyedMutex;
 use winapi::shared::dxgi::{self, IDXGIAdapter, IDXGIDevice, IDXGIFactory1};
 use winapi::shared::minwindef::UINT;
@@ -350,9 +354,15 @@ impl Device {
         &self,
         attributes: &ContextAttributes,
     ) -> Result<ContextDescriptor, Error> {
+        // Surfaces on this backend are always RGBA8.
+        if attributes.surface_format != SurfaceFormat::RGBA8 {
+            return Err(Error::UnsupportedSurfaceFormat);
+        }
+
         unsafe {
             ContextDescriptor::new(
                 self.egl_display,
+                self.gl_api(),
                 attributes,
                 &[
                     egl::BIND_TO_TEXTURE_RGBA as EGLint,
@@ -406,12 +416,17 @@ impl Device {
             })?;
         }
 
+        let mut gl = unsafe { Gl::from_loader_function(context::get_proc_address) };
+        if descriptor.debug {
+            gl_utils::install_debug_message_callback(&mut gl, id);
+        }
+
         let context = Context {
             egl_context,
             id,
             framebuffer: Framebuffer::None,
             context_is_owned: true,
-            gl: unsafe { Gl::from_loader_function(context::get_proc_address) },
+            gl,
         };
         Ok(context)
     }
@@ -477,7 +492,13 @@ impl Device {
     /// Returns the descriptor that this context was created with.
     pub fn context_descriptor(&self, context: &Context) -> ContextDescriptor {
         unsafe {
-            ContextDescriptor::from_egl_context(&context.gl, self.egl_display, context.egl_context)
+            ContextDescriptor::from_egl_context(
+                &context.gl,
+                self.egl_display,
+                context.egl_context,
+                // Surfaces on this backend are always single-sampled RGBA8.
+                GenericSurfaceAttributes::default(),
+            )
         }
     }
 
@@ -538,6 +559,60 @@ impl Device {
         unsafe { context_descriptor.attributes(self.egl_display) }
     }
 
+    /// Returns the largest number of samples per pixel that generic surfaces created with this
+    /// context can use.
+    ///
+    /// Multisampled generic surfaces aren't supported on this backend, so this always returns 0.
+    #[inline]
+    pub fn max_surface_samples(&self, _: &Context) -> Result<u32, Error> {
+        Ok(0)
+    }
+
+    /// Returns whether the context has been lost because of a GPU reset, and if so, whether this
+    /// context caused it.
+    pub fn context_reset_status(&self, context: &Context) -> Result<ContextResetStatus, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        gl_utils::context_reset_status(&context.gl, context::get_proc_address)
+    }
+
+    /// Inserts a fence into the command stream of the given context and flushes it.
+    ///
+    /// The fence is signaled once the GPU finishes all commands issued before it. Returns
+    /// `RequiredExtensionUnavailable` if the display doesn't support `EGL_KHR_fence_sync`.
+    pub fn create_fence(&self, context: &Context) -> Result<Fence, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        unsafe { EGLFence::new(&context.gl, self.egl_display).map(Fence) }
+    }
+
+    /// Blocks the calling thread until the fence is signaled, or until the timeout expires if
+    /// one is given.
+    ///
+    /// Returns true if the fence was signaled and false if the timeout expired first.
+    pub fn client_wait_fence(
+        &self,
+        fence: &Fence,
+        timeout: Option<Duration>,
+    ) -> Result<bool, Error> {
+        unsafe { fence.0.client_wait(self.egl_display, timeout) }
+    }
+
+    /// Makes the GPU wait until the fence is signaled before executing any further commands
+    /// issued by the given context.
+    ///
+    /// Returns `RequiredExtensionUnavailable` if the display doesn't support `EGL_KHR_wait_sync`.
+    pub fn server_wait_fence(&self, context: &Context, fence: &Fence) -> Result<(), Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        unsafe { fence.0.server_wait(self.egl_display) }
+    }
+
+    /// Destroys a fence.
+    pub fn destroy_fence(&self, fence: &mut Fence) -> Result<(), Error> {
+        unsafe {
+            fence.0.destroy(self.egl_display);
+        }
+        Ok(())
+    }
+
     /// Fetches the address of an OpenGL function associated with this context.
     ///
     /// OpenGL functions are local to a context. You should not use OpenGL functions on one context
@@ -656,7 +731,7 @@ impl Device {
     /// show up in their associated widgets until this method is called.
     pub fn present_bound_surface(&self, context: &mut Context) -> Result<(), Error> {
         match &context.framebuffer {
-            Framebuffer::Surface(surface) => surface.present(self),
+            Framebuffer::Surface(surface) => surface.present(self, &[]),
             _ => Ok(()),
         }
     }
@@ -1114,6 +1189,158 @@ impl Device {
         Err(Error::Unimplemented)
     }
 
+    /// Reads back a rectangle of pixels from a generic surface.
+    ///
+    /// The rectangle is relative to the top left corner of the surface, and the returned rows are
+    /// ordered from top to bottom in the requested pixel format.
+    ///
+    /// The supplied context must be the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned. Widget surfaces return a `WidgetAttached` error.
+    pub fn read_surface_pixels(
+        &self,
+        context: &mut Context,
+        surface: &Surface,
+        rect: Rect<i32>,
+        format: PixelFormat,
+    ) -> Result<Vec<u8>, Error> {
+        self.with_surface_as_read_target(context, surface, || {
+            gl_utils::read_framebuffer_pixels(&context.gl, None, surface.size, rect, format)
+        })
+    }
+
+    /// Starts copying the contents of a generic surface into CPU-visible memory.
+    ///
+    /// This does not wait for the GPU. Use `poll_surface_readback()` to check whether the copy
+    /// has finished and `map_surface_readback()` to retrieve the pixels.
+    ///
+    /// The supplied context must be the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned. Widget surfaces return a `WidgetAttached` error.
+    pub fn begin_surface_readback(
+        &self,
+        context: &mut Context,
+        surface: &Surface,
+    ) -> Result<ReadbackHandle, Error> {
+        self.with_surface_as_read_target(context, surface, || {
+            ReadbackHandle::new(&context.gl, context.id, None, surface.size)
+        })
+    }
+
+    /// Returns true if the GPU has finished a readback, so that `map_surface_readback()` will not
+    /// block.
+    ///
+    /// The supplied context must be the context the readback was started with, or an
+    /// `IncompatibleContext` error is returned.
+    pub fn poll_surface_readback(
+        &self,
+        context: &Context,
+        readback: &ReadbackHandle,
+    ) -> Result<bool, Error> {
+        if readback.context_id != context.id {
+            return Err(Error::IncompatibleContext);
+        }
+        let _guard = self.temporarily_make_context_current(context)?;
+        Ok(readback.poll(&context.gl))
+    }
+
+    /// Returns the pixels of a readback in RGBA format, with rows ordered from top to bottom, and
+    /// frees the readback.
+    ///
+    /// If the GPU has not yet finished the copy, this waits for it. The supplied context must be
+    /// the context the readback was started with, or an `IncompatibleContext` error is returned.
+    /// On failure, the readback is returned alongside the error, so that it can be mapped again
+    /// or destroyed.
+    pub fn map_surface_readback(
+        &self,
+        context: &mut Context,
+        readback: ReadbackHandle,
+    ) -> Result<Vec<u8>, (Error, ReadbackHandle)> {
+        if readback.context_id != context.id {
+            return Err((Error::IncompatibleContext, readback));
+        }
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, readback)),
+        };
+        readback.map(&context.gl)
+    }
+
+    /// Frees a readback without mapping it.
+    ///
+    /// The supplied context must be the context the readback was started with, or an
+    /// `IncompatibleContext` error is returned alongside the readback.
+    ///
+    /// You must call either this method or `map_surface_readback()` to dispose of a readback.
+    /// Otherwise, a panic occurs in the `drop` method.
+    pub fn destroy_surface_readback(
+        &self,
+        context: &mut Context,
+        mut readback: ReadbackHandle,
+    ) -> Result<(), (Error, ReadbackHandle)> {
+        if readback.context_id != context.id {
+            return Err((Error::IncompatibleContext, readback));
+        }
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, readback)),
+        };
+        readback.destroy(&context.gl);
+        Ok(())
+    }
+
+    // Pbuffers can only be read from by making them the current read surface, so this temporarily
+    // makes the context current with the surface bound and runs `read` against the default
+    // framebuffer.
+    fn with_surface_as_read_target<T, F>(
+        &self,
+        context: &Context,
+        surface: &Surface,
+        read: F,
+    ) -> Result<T, Error>
+    where
+        F: FnOnce() -> Result<T, Error>,
+    {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let keyed_mutex = match surface.win32_objects {
+            Win32Objects::Window => return Err(Error::WidgetAttached),
+            Win32Objects::Pbuffer {
+                synchronization: Synchronization::KeyedMutex(ref keyed_mutex),
+                ..
+            } => Some(keyed_mutex),
+            Win32Objects::Pbuffer { .. } => None,
+        };
+
+        let _guard = CurrentContextGuard::new();
+        unsafe {
+            if let Some(keyed_mutex) = keyed_mutex {
+                let result = keyed_mutex.AcquireSync(0, INFINITE);
+                assert_eq!(result, S_OK);
+            }
+
+            let result = EGL_FUNCTIONS.with(|egl| {
+                let result = egl.MakeCurrent(
+                    self.egl_display,
+                    surface.egl_surface,
+                    surface.egl_surface,
+                    context.egl_context,
+                );
+                if result == egl::FALSE {
+                    let err = egl.GetError().to_windowing_api_error();
+                    return Err(Error::MakeCurrentFailed(err));
+                }
+                Ok(())
+            });
+            let result = result.and_then(|()| read());
+
+            if let Some(keyed_mutex) = keyed_mutex {
+                let release_result = keyed_mutex.ReleaseSync(0);
+                assert_eq!(release_result, S_OK);
+            }
+            result
+        }
+    }
+
     /// Displays the contents of a widget surface on screen.
     ///
     /// Widget surfaces are internally double-buffered, so changes to them don't show up in their
@@ -1122,7 +1349,101 @@ impl Device {
     /// The supplied context must match the context the surface was created with, or an
     /// `IncompatibleSurface` error is returned.
     pub fn present_surface(&self, _: &Context, surface: &mut Surface) -> Result<(), Error> {
-        surface.present(self)
+        surface.present(self, &[])
+    }
+
+    /// Displays the contents of a widget surface on screen, telling the compositor that only the
+    /// given rectangles have changed since the surface was last presented.
+    ///
+    /// The rectangles are relative to the top left corner of the surface. An empty list damages
+    /// the whole surface. If the display supports neither `EGL_KHR_swap_buffers_with_damage` nor
+    /// `EGL_EXT_swap_buffers_with_damage`, this presents the whole surface like
+    /// `present_surface()`.
+    pub fn present_surface_with_damage(
+        &self,
+        _: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        surface.present(self, damage)
+    }
+
+    /// Returns how many frames old the contents of the back buffer of a widget surface are.
+    ///
+    /// An age of 1 means that the back buffer holds the contents that were presented last, 2
+    /// means the contents of the frame before that, and so on. An age of 0 means that the
+    /// contents are undefined, and the whole surface must be redrawn. Returns
+    /// `RequiredExtensionUnavailable` if the display supports neither `EGL_EXT_buffer_age` nor
+    /// `EGL_KHR_partial_update`, or `NoWidgetAttached` for generic surfaces.
+    pub fn surface_buffer_age(&self, context: &Context, surface: &Surface) -> Result<u32, Error> {
+        match surface.win32_objects {
+            Win32Objects::Window => {}
+            _ => return Err(Error::NoWidgetAttached),
+        }
+        unsafe {
+            surface::with_window_surface_current(
+                self.egl_display,
+                surface.egl_surface,
+                context.egl_context,
+                |egl| surface::query_buffer_age(egl, self.egl_display, surface.egl_surface),
+            )
+        }
+    }
+
+    /// Tells the driver that only the given rectangles of a widget surface will be rendered to
+    /// before it's next presented, so that it can avoid loading the rest of the back buffer.
+    ///
+    /// This must be called at most once per frame, before rendering to the surface. The
+    /// rectangles are relative to the top left corner of the surface, and an empty list damages
+    /// the whole surface. Returns `RequiredExtensionUnavailable` if the display doesn't support
+    /// `EGL_KHR_partial_update`, in which case the whole surface can be rendered to as usual.
+    pub fn set_damage_region(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        match surface.win32_objects {
+            Win32Objects::Window => {}
+            _ => return Err(Error::NoWidgetAttached),
+        }
+        unsafe {
+            surface::with_window_surface_current(
+                self.egl_display,
+                surface.egl_surface,
+                context.egl_context,
+                |egl| {
+                    surface::set_damage_region(egl, self.egl_display, surface.egl_surface, damage)
+                },
+            )
+        }
+    }
+
+    /// Sets the minimum number of display refreshes between presentations of a widget surface.
+    ///
+    /// An interval of 0 presents immediately without waiting for vertical sync, and 1 waits for
+    /// one refresh. Returns `UnsupportedSwapInterval` if the surface's EGL config doesn't support
+    /// the interval, or `NoWidgetAttached` for generic surfaces.
+    pub fn set_swap_interval(
+        &self,
+        context: &Context,
+        surface: &Surface,
+        interval: i32,
+    ) -> Result<(), Error> {
+        match surface.win32_objects {
+            Win32Objects::Window => {}
+            _ => return Err(Error::NoWidgetAttached),
+        }
+        unsafe {
+            surface::with_window_surface_current(
+                self.egl_display,
+                surface.egl_surface,
+                context.egl_context,
+                |egl| {
+                    surface::set_swap_interval(egl, self.egl_display, surface.egl_surface, interval)
+                },
+            )
+        }
     }
 
     /// Resizes a widget surface.
@@ -1146,9 +1467,12 @@ impl Device {
     pub fn surface_info(&self, surface: &Surface) -> SurfaceInfo {
         SurfaceInfo {
             size: surface.size,
+            valid_size: surface.size,
             id: surface.id(),
             context_id: surface.context_id,
             framebuffer_object: None,
+            format: Some(SurfaceFormat::RGBA8),
+            depth_format: None,
         }
     }
 
@@ -3,13 +3,14 @@
 use super::context::ContextDescriptor;
 use super::device::Device;
 use crate::base::egl::device::EGL_FUNCTIONS;
+use crate::base::egl::surface::swap_buffers_with_damage;
 use crate::context::ContextID;
 use crate::egl::types::EGLNativeWindowType;
 use crate::egl::types::EGLSurface;
 use crate::egl::{self};
 use crate::{Error, SurfaceID};
 
-use euclid::default::Size2D;
+use euclid::default::{Rect, Size2D};
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
 use std::thread;
@@ -132,14 +133,14 @@ impl Surface {
         }
     }
 
-    pub(crate) fn present(&self, device: &Device) -> Result<(), Error> {
+    pub(crate) fn present(&self, device: &Device, damage: &[Rect<i32>]) -> Result<(), Error> {
         match self.win32_objects {
             Win32Objects::Window { .. } => {}
             _ => return Err(Error::NoWidgetAttached),
         }
 
         EGL_FUNCTIONS.with(|egl| unsafe {
-            let ok = egl.SwapBuffers(device.egl_display, self.egl_surface);
+            let ok = swap_buffers_with_damage(egl, device.egl_display, self.egl_surface, damage);
             assert_ne!(ok, egl::FALSE);
             Ok(())
         })
@@ -154,3 +155,18 @@ impl Surface {
 pub struct SurfaceDataGuard<'a> {
     phantom: PhantomData<&'a ()>,
 }
+
+// CPU access is not yet supported on this backend, so this guard is never constructed.
+impl<'a> SurfaceDataGuard<'a> {
+    /// Returns the number of bytes per row of the surface.
+    #[inline]
+    pub fn stride(&self) -> usize {
+        0
+    }
+
+    /// Returns a mutable slice of the pixel data in this surface.
+    #[inline]
+    pub fn data(&mut self) -> &mut [u8] {
+        &mut []
+    }
+}
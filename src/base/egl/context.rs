@@ -2,19 +2,24 @@
 
 use super::device::EGL_FUNCTIONS;
 use super::error::ToWindowingApiError;
-use super::ffi::EGL_CONTEXT_OPENGL_PROFILE_MASK;
-use super::ffi::{EGL_CONTEXT_MINOR_VERSION_KHR, EGL_CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT};
+use super::ffi::{
+    EGL_CONTEXT_FLAGS_KHR, EGL_CONTEXT_MINOR_VERSION_KHR,
+    EGL_CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT, EGL_CONTEXT_OPENGL_DEBUG_BIT_KHR,
+    EGL_CONTEXT_OPENGL_PROFILE_MASK, EGL_CONTEXT_OPENGL_RESET_NOTIFICATION_STRATEGY_EXT,
+    EGL_CONTEXT_OPENGL_RESET_NOTIFICATION_STRATEGY_KHR, EGL_CONTEXT_OPENGL_ROBUST_ACCESS_BIT_KHR,
+    EGL_CONTEXT_OPENGL_ROBUST_ACCESS_EXT, EGL_LOSE_CONTEXT_ON_RESET_EXT,
+};
 use super::surface::{EGLBackedSurface, ExternalEGLSurfaces};
 use crate::context::{self, CREATE_CONTEXT_MUTEX};
 use crate::egl;
 use crate::egl::types::{EGLConfig, EGLContext, EGLDisplay, EGLSurface, EGLint};
 use crate::surface::Framebuffer;
 use crate::{ContextAttributeFlags, ContextAttributes, ContextID, Error, GLApi, GLVersion};
-use crate::{Gl, SurfaceInfo};
+use crate::{DepthFormat, Gl, SurfaceFormat, SurfaceInfo};
 use euclid::default::Size2D;
 use glow::HasContext;
 
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::mem;
 use std::os::raw::c_void;
 use std::ptr;
@@ -27,6 +32,7 @@ const RGB_CHANNEL_BIT_DEPTH: EGLint = 8;
 pub(crate) struct EGLBackedContext {
     pub(crate) egl_context: EGLContext,
     pub(crate) id: ContextID,
+    pub(crate) surface_attributes: GenericSurfaceAttributes,
     pbuffer: EGLSurface,
     pub(crate) framebuffer: Framebuffer<EGLBackedSurface, ExternalEGLSurfaces>,
     context_is_owned: bool,
@@ -51,6 +57,18 @@ pub struct ContextDescriptor {
     pub(crate) egl_config_id: EGLint,
     pub(crate) gl_version: GLVersion,
     pub(crate) compatibility_profile: bool,
+    pub(crate) robust_access: bool,
+    pub(crate) debug: bool,
+    pub(crate) surface_attributes: GenericSurfaceAttributes,
+}
+
+// Properties of generic surfaces that aren't part of the EGL config, so they're carried along with
+// the context descriptor and the context instead.
+#[derive(Clone, Copy)]
+pub(crate) struct GenericSurfaceAttributes {
+    pub(crate) samples: u32,
+    pub(crate) format: SurfaceFormat,
+    pub(crate) depth_format: DepthFormat,
 }
 
 #[must_use]
@@ -133,6 +151,7 @@ impl EGLBackedContext {
         let context = EGLBackedContext {
             egl_context,
             id: *next_context_id,
+            surface_attributes: descriptor.surface_attributes,
             framebuffer: Framebuffer::None,
             context_is_owned: true,
             pbuffer,
@@ -146,6 +165,7 @@ impl EGLBackedContext {
         let context = EGLBackedContext {
             egl_context: native_context.egl_context,
             id: *next_context_id,
+            surface_attributes: GenericSurfaceAttributes::default(),
             framebuffer: Framebuffer::External(ExternalEGLSurfaces {
                 draw: native_context.egl_draw_surface,
                 read: native_context.egl_read_surface,
@@ -233,6 +253,9 @@ impl EGLBackedContext {
         if self.id != surface.context_id {
             return Err((Error::IncompatibleSurface, surface));
         }
+        if let Err(err) = surface.check_renderable() {
+            return Err((err, surface));
+        }
 
         match self.framebuffer {
             Framebuffer::None => self.framebuffer = Framebuffer::Surface(surface),
@@ -253,6 +276,19 @@ impl EGLBackedContext {
         gl: &Gl,
         egl_display: EGLDisplay,
     ) -> Result<Option<EGLBackedSurface>, Error> {
+        // Resolve multisampled surfaces so that their textures are up to date once they're
+        // unbound. This needs the context to be current.
+        if let Framebuffer::Surface(ref surface) = self.framebuffer {
+            if surface.is_multisampled() {
+                let _guard = CurrentContextGuard::new();
+                if !self.is_current() {
+                    self.make_current(egl_display)?;
+                }
+                surface.resolve(gl);
+                gl.flush();
+            }
+        }
+
         // Flush to avoid races on Mesa/Intel and possibly other GPUs.
         gl.flush();
 
@@ -275,7 +311,7 @@ impl EGLBackedContext {
 
     pub(crate) fn present_bound_surface(&self, egl_display: EGLDisplay) -> Result<(), Error> {
         match &self.framebuffer {
-            Framebuffer::Surface(surface) => surface.present(egl_display, self.egl_context),
+            Framebuffer::Surface(surface) => surface.present(egl_display, self.egl_context, &[]),
             Framebuffer::None | Framebuffer::External(_) => Ok(()),
         }
     }
@@ -316,9 +352,21 @@ impl NativeContext {
     }
 }
 
+impl Default for GenericSurfaceAttributes {
+    #[inline]
+    fn default() -> GenericSurfaceAttributes {
+        GenericSurfaceAttributes {
+            samples: 0,
+            format: SurfaceFormat::RGBA8,
+            depth_format: DepthFormat::Depth24,
+        }
+    }
+}
+
 impl ContextDescriptor {
     pub(crate) unsafe fn new(
         egl_display: EGLDisplay,
+        gl_api: GLApi,
         attributes: &ContextAttributes,
         extra_config_attributes: &[EGLint],
     ) -> Result<ContextDescriptor, Error> {
@@ -356,6 +404,22 @@ impl ContextDescriptor {
             return Err(Error::UnsupportedGLProfile);
         }
 
+        // Robustness is requested through `EGL_EXT_create_context_robustness` for OpenGL ES, but
+        // that extension doesn't apply to desktop OpenGL, which uses `EGL_KHR_create_context`.
+        let robust_access = flags.contains(ContextAttributeFlags::ROBUST_ACCESS);
+        let robustness_extension = match gl_api {
+            GLApi::GL => "EGL_KHR_create_context",
+            GLApi::GLES => "EGL_EXT_create_context_robustness",
+        };
+        if robust_access && !display_supports_extension(egl_display, robustness_extension) {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+
+        let debug = flags.contains(ContextAttributeFlags::DEBUG);
+        if debug && !display_supports_extension(egl_display, "EGL_KHR_create_context") {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+
         // Create required config attributes.
         //
         // We check these separately because `eglChooseConfig` on its own might give us 32-bit
@@ -434,6 +498,13 @@ impl ContextDescriptor {
                 egl_config_id,
                 gl_version,
                 compatibility_profile,
+                robust_access,
+                debug,
+                surface_attributes: GenericSurfaceAttributes {
+                    samples: attributes.samples,
+                    format: attributes.surface_format,
+                    depth_format: attributes.depth_format,
+                },
             })
         })
     }
@@ -442,15 +513,21 @@ impl ContextDescriptor {
         gl: &Gl,
         egl_display: EGLDisplay,
         egl_context: EGLContext,
+        surface_attributes: GenericSurfaceAttributes,
     ) -> ContextDescriptor {
         let egl_config_id = get_context_attr(egl_display, egl_context, egl::CONFIG_ID as EGLint);
         let gl_version = GLVersion::current(&gl);
         let compatibility_profile = context::current_context_uses_compatibility_profile(&gl);
+        let robust_access = context::current_context_uses_robust_access(gl);
+        let debug = context::current_context_is_debug(gl);
 
         ContextDescriptor {
             egl_config_id,
             gl_version,
             compatibility_profile,
+            robust_access,
+            debug,
+            surface_attributes,
         }
     }
 
@@ -497,11 +574,16 @@ impl ContextDescriptor {
             ContextAttributeFlags::COMPATIBILITY_PROFILE,
             self.compatibility_profile,
         );
+        attribute_flags.set(ContextAttributeFlags::ROBUST_ACCESS, self.robust_access);
+        attribute_flags.set(ContextAttributeFlags::DEBUG, self.debug);
 
         // Create appropriate context attributes.
         ContextAttributes {
             flags: attribute_flags,
             version: self.gl_version,
+            samples: self.surface_attributes.samples,
+            surface_format: self.surface_attributes.format,
+            depth_format: self.surface_attributes.depth_format,
         }
     }
 }
@@ -538,6 +620,33 @@ pub(crate) unsafe fn create_context(
         ]);
     }
 
+    let mut context_flags = 0;
+    if descriptor.debug {
+        context_flags |= EGL_CONTEXT_OPENGL_DEBUG_BIT_KHR;
+    }
+
+    if descriptor.robust_access {
+        match gl_api {
+            GLApi::GL => {
+                context_flags |= EGL_CONTEXT_OPENGL_ROBUST_ACCESS_BIT_KHR;
+                egl_context_attributes.extend(&[
+                    EGL_CONTEXT_OPENGL_RESET_NOTIFICATION_STRATEGY_KHR as EGLint,
+                    EGL_LOSE_CONTEXT_ON_RESET_EXT as EGLint,
+                ]);
+            }
+            GLApi::GLES => egl_context_attributes.extend(&[
+                EGL_CONTEXT_OPENGL_ROBUST_ACCESS_EXT as EGLint,
+                egl::TRUE as EGLint,
+                EGL_CONTEXT_OPENGL_RESET_NOTIFICATION_STRATEGY_EXT as EGLint,
+                EGL_LOSE_CONTEXT_ON_RESET_EXT as EGLint,
+            ]),
+        }
+    }
+
+    if context_flags != 0 {
+        egl_context_attributes.extend(&[EGL_CONTEXT_FLAGS_KHR as EGLint, context_flags]);
+    }
+
     // Include some extra zeroes to work around broken implementations.
     //
     // FIXME(pcwalton): Which implementations are those? (This is copied from Gecko.)
@@ -576,6 +685,19 @@ pub(crate) unsafe fn make_no_context_current(egl_display: EGLDisplay) -> Result<
     })
 }
 
+pub(crate) unsafe fn display_supports_extension(egl_display: EGLDisplay, name: &str) -> bool {
+    EGL_FUNCTIONS.with(|egl| {
+        let extensions = egl.QueryString(egl_display, egl::EXTENSIONS as EGLint);
+        if extensions.is_null() {
+            return false;
+        }
+        CStr::from_ptr(extensions)
+            .to_string_lossy()
+            .split(' ')
+            .any(|extension| extension == name)
+    })
+}
+
 pub(crate) unsafe fn get_config_attr(
     egl_display: EGLDisplay,
     egl_config: EGLConfig,
@@ -1,12 +1,20 @@
 //! Functionality common to backends using EGL displays.
 
-use crate::egl::Egl;
+use super::context;
+use super::ffi::{EGL_DEVICE_EXT, EGL_DRM_RENDER_NODE_FILE_EXT, EGL_EXTENSION_FUNCTIONS};
+use super::ffi::{EGL_NO_DEVICE_EXT, EGL_PLATFORM_DEVICE_EXT, EGL_RENDERER_EXT};
+use crate::egl::types::{EGLAttrib, EGLDeviceEXT, EGLDisplay, EGLenum, EGLint};
+use crate::egl::{self, Egl};
+use crate::info::AdapterInfo;
+use crate::Error;
 
 #[cfg(not(target_os = "windows"))]
 use libc::{dlopen, dlsym, RTLD_LAZY};
 use std::ffi::{CStr, CString};
 use std::mem;
 use std::os::raw::c_void;
+use std::path::PathBuf;
+use std::ptr;
 use std::sync::LazyLock;
 #[cfg(target_os = "windows")]
 use winapi::shared::minwindef::HMODULE;
@@ -71,3 +79,103 @@ fn get_proc_address(symbol_name: &str) -> *const c_void {
 pub(crate) unsafe fn lookup_egl_extension(name: &CStr) -> *mut c_void {
     EGL_FUNCTIONS.with(|egl| mem::transmute(egl.GetProcAddress(name.as_ptr())))
 }
+
+// Returns all the EGL devices on the system, along with whatever `EGL_EXT_device_query` and its
+// companion extensions can tell us about them. Returns an empty list if
+// `EGL_EXT_device_enumeration` isn't supported.
+pub(crate) fn enumerate_devices() -> Vec<AdapterInfo<EGLDeviceEXT>> {
+    let query_devices = match EGL_EXTENSION_FUNCTIONS.QueryDevicesEXT {
+        None => return vec![],
+        Some(query_devices) => query_devices,
+    };
+
+    let mut device_count = 0;
+    if query_devices(0, ptr::null_mut(), &mut device_count) == egl::FALSE || device_count <= 0 {
+        return vec![];
+    }
+    let mut devices = vec![EGL_NO_DEVICE_EXT; device_count as usize];
+    if query_devices(device_count, devices.as_mut_ptr(), &mut device_count) == egl::FALSE {
+        return vec![];
+    }
+    devices.truncate(device_count as usize);
+
+    devices.into_iter().map(device_info).collect()
+}
+
+// Returns the EGL device that a display renders on, along with what is known about it. Returns
+// `None` if `EGL_EXT_device_query` isn't supported, or the display doesn't say.
+pub(crate) unsafe fn display_device(egl_display: EGLDisplay) -> Option<AdapterInfo<EGLDeviceEXT>> {
+    let query_display_attrib = EGL_EXTENSION_FUNCTIONS.QueryDisplayAttribEXT?;
+    let mut device: EGLAttrib = 0;
+    if query_display_attrib(egl_display, EGL_DEVICE_EXT as EGLint, &mut device) == egl::FALSE {
+        return None;
+    }
+    let device = device as EGLDeviceEXT;
+    if device == EGL_NO_DEVICE_EXT {
+        return None;
+    }
+    Some(device_info(device))
+}
+
+// Returns true if displays can be opened directly on EGL devices, with `EGL_EXT_platform_device`.
+pub(crate) fn supports_device_displays() -> bool {
+    unsafe { context::display_supports_extension(egl::NO_DISPLAY, "EGL_EXT_platform_device") }
+}
+
+// Opens and initializes an `EGLDisplay` on an EGL device.
+//
+// EGL hands out the same display to everyone who opens the same device, so the display must not
+// be terminated while any of them might still be using it.
+pub(crate) unsafe fn open_device_display(device: EGLDeviceEXT) -> Result<EGLDisplay, Error> {
+    EGL_FUNCTIONS.with(|egl| {
+        let egl_display_attributes = [egl::NONE as EGLAttrib];
+        let egl_display = egl.GetPlatformDisplay(
+            EGL_PLATFORM_DEVICE_EXT,
+            device as *mut c_void,
+            egl_display_attributes.as_ptr(),
+        );
+        if egl_display == egl::NO_DISPLAY {
+            return Err(Error::DeviceOpenFailed);
+        }
+
+        let (mut egl_major_version, mut egl_minor_version) = (0, 0);
+        let ok = egl.Initialize(egl_display, &mut egl_major_version, &mut egl_minor_version);
+        if ok == egl::FALSE {
+            return Err(Error::DeviceOpenFailed);
+        }
+
+        Ok(egl_display)
+    })
+}
+
+fn device_info(device: EGLDeviceEXT) -> AdapterInfo<EGLDeviceEXT> {
+    let extensions = query_device_string(device, egl::EXTENSIONS).unwrap_or_default();
+    let has_extension = |name| extensions.split(' ').any(|extension| extension == name);
+    let query_if_supported = |extension, name| {
+        if has_extension(extension) {
+            query_device_string(device, name)
+        } else {
+            None
+        }
+    };
+    AdapterInfo {
+        adapter: device,
+        drm_render_node: query_if_supported(
+            "EGL_EXT_device_drm_render_node",
+            EGL_DRM_RENDER_NODE_FILE_EXT,
+        )
+        .map(PathBuf::from),
+        vendor: query_if_supported("EGL_EXT_device_query_name", egl::VENDOR),
+        renderer: query_if_supported("EGL_EXT_device_query_name", EGL_RENDERER_EXT),
+        software: has_extension("EGL_MESA_device_software"),
+    }
+}
+
+fn query_device_string(device: EGLDeviceEXT, name: EGLenum) -> Option<String> {
+    let query_device_string = EGL_EXTENSION_FUNCTIONS.QueryDeviceStringEXT?;
+    let string = query_device_string(device, name as EGLint);
+    if string.is_null() {
+        return None;
+    }
+    unsafe { Some(CStr::from_ptr(string).to_string_lossy().into_owned()) }
+}
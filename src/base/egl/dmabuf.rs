@@ -0,0 +1,373 @@
+//! Wrapping Linux dma-bufs in EGL images.
+
+use super::context;
+use super::device::EGL_FUNCTIONS;
+use super::ffi::{EGLImageKHR, EGL_EXTENSION_FUNCTIONS, EGL_LINUX_DMA_BUF_EXT};
+use super::ffi::{EGLuint64KHR, EGL_LINUX_DRM_FOURCC_EXT, EGL_NO_IMAGE_KHR};
+use super::ffi::{EGL_DMA_BUF_PLANE0_FD_EXT, EGL_DMA_BUF_PLANE0_MODIFIER_HI_EXT};
+use super::ffi::{EGL_DMA_BUF_PLANE0_MODIFIER_LO_EXT, EGL_DMA_BUF_PLANE0_OFFSET_EXT};
+use super::ffi::{EGL_DMA_BUF_PLANE0_PITCH_EXT, EGL_DMA_BUF_PLANE1_FD_EXT};
+use super::ffi::{EGL_DMA_BUF_PLANE1_MODIFIER_HI_EXT, EGL_DMA_BUF_PLANE1_MODIFIER_LO_EXT};
+use super::ffi::{EGL_DMA_BUF_PLANE1_OFFSET_EXT, EGL_DMA_BUF_PLANE1_PITCH_EXT};
+use super::ffi::{EGL_DMA_BUF_PLANE2_FD_EXT, EGL_DMA_BUF_PLANE2_MODIFIER_HI_EXT};
+use super::ffi::{EGL_DMA_BUF_PLANE2_MODIFIER_LO_EXT, EGL_DMA_BUF_PLANE2_OFFSET_EXT};
+use super::ffi::{EGL_DMA_BUF_PLANE2_PITCH_EXT, EGL_DMA_BUF_PLANE3_FD_EXT};
+use super::ffi::{EGL_DMA_BUF_PLANE3_MODIFIER_HI_EXT, EGL_DMA_BUF_PLANE3_MODIFIER_LO_EXT};
+use super::ffi::{EGL_DMA_BUF_PLANE3_OFFSET_EXT, EGL_DMA_BUF_PLANE3_PITCH_EXT};
+use crate::base::egl::error::ToWindowingApiError;
+use crate::egl;
+use crate::egl::types::{EGLDisplay, EGLenum, EGLint};
+use crate::WindowingApiError;
+use crate::{DmaBufDescriptor, DmaBufFormat, DmaBufModifier, Error, SurfaceFormat};
+
+use euclid::default::Size2D;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::ptr;
+
+// DRM fourcc codes, from `drm_fourcc.h`. The names list the channels starting from the most
+// significant bits of a little-endian pixel, so `ABGR8888` stores red in the first byte.
+pub(crate) const DRM_FORMAT_ARGB8888: u32 = fourcc(b"AR24");
+pub(crate) const DRM_FORMAT_ABGR8888: u32 = fourcc(b"AB24");
+pub(crate) const DRM_FORMAT_ABGR2101010: u32 = fourcc(b"AB30");
+pub(crate) const DRM_FORMAT_ABGR16161616F: u32 = fourcc(b"AB4H");
+
+// The modifier that means "the layout is implied by the driver".
+pub(crate) const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+
+// The attributes that describe each plane, in the order fd, offset, pitch, modifier low bits, and
+// modifier high bits.
+static PLANE_ATTRIBUTES: [[EGLenum; 5]; 4] = [
+    [
+        EGL_DMA_BUF_PLANE0_FD_EXT,
+        EGL_DMA_BUF_PLANE0_OFFSET_EXT,
+        EGL_DMA_BUF_PLANE0_PITCH_EXT,
+        EGL_DMA_BUF_PLANE0_MODIFIER_LO_EXT,
+        EGL_DMA_BUF_PLANE0_MODIFIER_HI_EXT,
+    ],
+    [
+        EGL_DMA_BUF_PLANE1_FD_EXT,
+        EGL_DMA_BUF_PLANE1_OFFSET_EXT,
+        EGL_DMA_BUF_PLANE1_PITCH_EXT,
+        EGL_DMA_BUF_PLANE1_MODIFIER_LO_EXT,
+        EGL_DMA_BUF_PLANE1_MODIFIER_HI_EXT,
+    ],
+    [
+        EGL_DMA_BUF_PLANE2_FD_EXT,
+        EGL_DMA_BUF_PLANE2_OFFSET_EXT,
+        EGL_DMA_BUF_PLANE2_PITCH_EXT,
+        EGL_DMA_BUF_PLANE2_MODIFIER_LO_EXT,
+        EGL_DMA_BUF_PLANE2_MODIFIER_HI_EXT,
+    ],
+    [
+        EGL_DMA_BUF_PLANE3_FD_EXT,
+        EGL_DMA_BUF_PLANE3_OFFSET_EXT,
+        EGL_DMA_BUF_PLANE3_PITCH_EXT,
+        EGL_DMA_BUF_PLANE3_MODIFIER_LO_EXT,
+        EGL_DMA_BUF_PLANE3_MODIFIER_HI_EXT,
+    ],
+];
+
+// One plane of a dma-buf.
+pub(crate) struct DmaBufPlane {
+    pub(crate) fd: RawFd,
+    pub(crate) offset: u32,
+    pub(crate) stride: u32,
+}
+
+const fn fourcc(code: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*code)
+}
+
+// Returns the DRM format with the same memory layout as the given surface format, if there is one.
+pub(crate) fn drm_format(format: SurfaceFormat) -> Option<u32> {
+    match format {
+        SurfaceFormat::RGBA8 => Some(DRM_FORMAT_ABGR8888),
+        SurfaceFormat::BGRA8 => Some(DRM_FORMAT_ARGB8888),
+        SurfaceFormat::RGB10A2 => Some(DRM_FORMAT_ABGR2101010),
+        SurfaceFormat::RGBA16F => Some(DRM_FORMAT_ABGR16161616F),
+        // A dma-buf doesn't record whether its contents are sRGB-encoded.
+        SurfaceFormat::SRGBA8 => None,
+    }
+}
+
+// Returns the surface format with the same memory layout as the given DRM format, if there is one.
+pub(crate) fn surface_format(drm_format: u32) -> Option<SurfaceFormat> {
+    match drm_format {
+        DRM_FORMAT_ABGR8888 => Some(SurfaceFormat::RGBA8),
+        DRM_FORMAT_ARGB8888 => Some(SurfaceFormat::BGRA8),
+        DRM_FORMAT_ABGR2101010 => Some(SurfaceFormat::RGB10A2),
+        DRM_FORMAT_ABGR16161616F => Some(SurfaceFormat::RGBA16F),
+        _ => None,
+    }
+}
+
+// Wraps the planes of a dma-buf in an EGL image, using `EGL_EXT_image_dma_buf_import`.
+//
+// EGL duplicates the file descriptors, so the caller still owns them afterward.
+pub(crate) unsafe fn create_egl_image(
+    egl_display: EGLDisplay,
+    size: &Size2D<i32>,
+    drm_format: u32,
+    modifier: u64,
+    planes: &[DmaBufPlane],
+) -> Result<EGLImageKHR, Error> {
+    if planes.is_empty() || planes.len() > PLANE_ATTRIBUTES.len() {
+        return Err(Error::Failed);
+    }
+    if !context::display_supports_extension(egl_display, "EGL_EXT_image_dma_buf_import") {
+        return Err(Error::RequiredExtensionUnavailable);
+    }
+    let has_modifier = modifier != DRM_FORMAT_MOD_INVALID;
+    if has_modifier
+        && !context::display_supports_extension(
+            egl_display,
+            "EGL_EXT_image_dma_buf_import_modifiers",
+        )
+    {
+        return Err(Error::RequiredExtensionUnavailable);
+    }
+
+    let mut attributes = vec![
+        egl::WIDTH as EGLint,
+        size.width,
+        egl::HEIGHT as EGLint,
+        size.height,
+        EGL_LINUX_DRM_FOURCC_EXT as EGLint,
+        drm_format as EGLint,
+    ];
+    for (plane, names) in planes.iter().zip(PLANE_ATTRIBUTES.iter()) {
+        attributes.extend_from_slice(&[
+            names[0] as EGLint,
+            plane.fd,
+            names[1] as EGLint,
+            plane.offset as EGLint,
+            names[2] as EGLint,
+            plane.stride as EGLint,
+        ]);
+        if has_modifier {
+            attributes.extend_from_slice(&[
+                names[3] as EGLint,
+                modifier as u32 as EGLint,
+                names[4] as EGLint,
+                (modifier >> 32) as u32 as EGLint,
+            ]);
+        }
+    }
+    attributes.push(egl::NONE as EGLint);
+
+    let egl_image = (EGL_EXTENSION_FUNCTIONS.CreateImageKHR)(
+        egl_display,
+        egl::NO_CONTEXT,
+        EGL_LINUX_DMA_BUF_EXT,
+        ptr::null_mut(),
+        attributes.as_ptr(),
+    );
+    if egl_image == EGL_NO_IMAGE_KHR {
+        let err = EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+        return Err(Error::SurfaceImportFailed(err));
+    }
+    Ok(egl_image)
+}
+
+// Wraps the dma-buf that a descriptor describes in an EGL image.
+pub(crate) unsafe fn import_egl_image(
+    egl_display: EGLDisplay,
+    descriptor: &DmaBufDescriptor,
+) -> Result<EGLImageKHR, Error> {
+    if descriptor.strides.len() != descriptor.fds.len()
+        || descriptor.offsets.len() != descriptor.fds.len()
+    {
+        return Err(Error::SurfaceImportFailed(WindowingApiError::BadParameter));
+    }
+    let planes: Vec<DmaBufPlane> = descriptor
+        .fds
+        .iter()
+        .zip(descriptor.strides.iter())
+        .zip(descriptor.offsets.iter())
+        .map(|((fd, &stride), &offset)| DmaBufPlane {
+            fd: fd.as_raw_fd(),
+            offset,
+            stride,
+        })
+        .collect();
+    create_egl_image(
+        egl_display,
+        &descriptor.size,
+        descriptor.fourcc,
+        descriptor.modifier,
+        &planes,
+    )
+}
+
+// Returns the dma-buf formats and modifiers that the display can import, using
+// `EGL_EXT_image_dma_buf_import_modifiers`.
+pub(crate) unsafe fn query_formats(egl_display: EGLDisplay) -> Result<Vec<DmaBufFormat>, Error> {
+    let (query_formats, query_modifiers) = match (
+        EGL_EXTENSION_FUNCTIONS.QueryDmaBufFormatsEXT,
+        EGL_EXTENSION_FUNCTIONS.QueryDmaBufModifiersEXT,
+    ) {
+        (Some(query_formats), Some(query_modifiers))
+            if context::display_supports_extension(
+                egl_display,
+                "EGL_EXT_image_dma_buf_import_modifiers",
+            ) =>
+        {
+            (query_formats, query_modifiers)
+        }
+        _ => return Err(Error::RequiredExtensionUnavailable),
+    };
+
+    // Each query is made twice: once to find out the count, and once to fetch the values.
+    let mut format_count = 0;
+    if query_formats(egl_display, 0, ptr::null_mut(), &mut format_count) == egl::FALSE {
+        return Err(Error::Failed);
+    }
+    let mut fourccs: Vec<EGLint> = vec![0; format_count as usize];
+    if query_formats(
+        egl_display,
+        format_count,
+        fourccs.as_mut_ptr(),
+        &mut format_count,
+    ) == egl::FALSE
+    {
+        return Err(Error::Failed);
+    }
+    fourccs.truncate(format_count as usize);
+
+    let mut formats = Vec::with_capacity(fourccs.len());
+    for fourcc in fourccs {
+        let mut modifier_count = 0;
+        let ok = query_modifiers(
+            egl_display,
+            fourcc,
+            0,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            &mut modifier_count,
+        );
+        if ok == egl::FALSE {
+            return Err(Error::Failed);
+        }
+        let mut modifiers: Vec<EGLuint64KHR> = vec![0; modifier_count as usize];
+        let mut external_only = vec![egl::FALSE; modifier_count as usize];
+        let ok = query_modifiers(
+            egl_display,
+            fourcc,
+            modifier_count,
+            modifiers.as_mut_ptr(),
+            external_only.as_mut_ptr(),
+            &mut modifier_count,
+        );
+        if ok == egl::FALSE {
+            return Err(Error::Failed);
+        }
+
+        formats.push(DmaBufFormat {
+            fourcc: fourcc as u32,
+            modifiers: modifiers
+                .into_iter()
+                .zip(external_only)
+                .take(modifier_count as usize)
+                .map(|(modifier, external_only)| DmaBufModifier {
+                    modifier,
+                    external_only: external_only != egl::FALSE,
+                })
+                .collect(),
+        });
+    }
+    Ok(formats)
+}
+
+// Exports the dma-buf behind an EGL image, using `EGL_MESA_image_dma_buf_export`.
+//
+// The returned descriptor owns new file descriptors for the buffer.
+pub(crate) unsafe fn export_egl_image(
+    egl_display: EGLDisplay,
+    egl_image: EGLImageKHR,
+    size: Size2D<i32>,
+) -> Result<DmaBufDescriptor, Error> {
+    let (export_query, export) = match (
+        EGL_EXTENSION_FUNCTIONS.ExportDMABUFImageQueryMESA,
+        EGL_EXTENSION_FUNCTIONS.ExportDMABUFImageMESA,
+    ) {
+        (Some(export_query), Some(export))
+            if context::display_supports_extension(
+                egl_display,
+                "EGL_MESA_image_dma_buf_export",
+            ) =>
+        {
+            (export_query, export)
+        }
+        _ => return Err(Error::RequiredExtensionUnavailable),
+    };
+
+    // Query the plane count first, since the modifiers are returned per plane.
+    let (mut fourcc, mut plane_count) = (0, 0);
+    let ok = export_query(
+        egl_display,
+        egl_image,
+        &mut fourcc,
+        &mut plane_count,
+        ptr::null_mut(),
+    );
+    if ok == egl::FALSE {
+        let err = EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+        return Err(Error::SurfaceExportFailed(err));
+    }
+    if plane_count <= 0 || plane_count as usize > PLANE_ATTRIBUTES.len() {
+        return Err(Error::SurfaceExportFailed(WindowingApiError::BadMatch));
+    }
+
+    let mut modifiers: Vec<EGLuint64KHR> = vec![DRM_FORMAT_MOD_INVALID; plane_count as usize];
+    let ok = export_query(
+        egl_display,
+        egl_image,
+        &mut fourcc,
+        &mut plane_count,
+        modifiers.as_mut_ptr(),
+    );
+    if ok == egl::FALSE {
+        let err = EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+        return Err(Error::SurfaceExportFailed(err));
+    }
+
+    let plane_count = modifiers.len();
+
+    let mut raw_fds = vec![-1; plane_count];
+    let mut strides: Vec<EGLint> = vec![0; plane_count];
+    let mut offsets: Vec<EGLint> = vec![0; plane_count];
+    let ok = export(
+        egl_display,
+        egl_image,
+        raw_fds.as_mut_ptr(),
+        strides.as_mut_ptr(),
+        offsets.as_mut_ptr(),
+    );
+    if ok == egl::FALSE {
+        let err = EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+        return Err(Error::SurfaceExportFailed(err));
+    }
+
+    // Planes that live in the same buffer as an earlier plane may not get a file descriptor of
+    // their own. Give them a duplicate, so that there is one per plane.
+    let mut fds: Vec<OwnedFd> = Vec::with_capacity(plane_count);
+    for raw_fd in raw_fds {
+        if raw_fd >= 0 {
+            fds.push(OwnedFd::from_raw_fd(raw_fd));
+            continue;
+        }
+        match fds.last().map(OwnedFd::try_clone) {
+            Some(Ok(fd)) => fds.push(fd),
+            _ => return Err(Error::SurfaceExportFailed(WindowingApiError::BadAccess)),
+        }
+    }
+
+    Ok(DmaBufDescriptor {
+        size,
+        fourcc: fourcc as u32,
+        modifier: modifiers[0],
+        fds,
+        strides: strides.into_iter().map(|stride| stride as u32).collect(),
+        offsets: offsets.into_iter().map(|offset| offset as u32).collect(),
+    })
+}
@@ -0,0 +1,201 @@
+//! Fences built on EGL sync objects.
+
+use super::context;
+use super::device::EGL_FUNCTIONS;
+use super::ffi::{EGL_EXTENSION_FUNCTIONS, EGL_NO_SYNC_KHR, EGL_SYNC_NATIVE_FENCE_ANDROID};
+#[cfg(free_unix)]
+use super::ffi::{EGL_NO_NATIVE_FENCE_FD_ANDROID, EGL_SYNC_NATIVE_FENCE_FD_ANDROID};
+use crate::base::egl::error::ToWindowingApiError;
+use crate::egl;
+use crate::egl::types::{EGLDisplay, EGLSyncKHR, EGLenum, EGLint};
+use crate::{Error, Gl};
+
+use glow::HasContext;
+#[cfg(free_unix)]
+use std::os::unix::io::{FromRawFd, IntoRawFd, OwnedFd};
+use std::thread;
+use std::time::Duration;
+
+pub(crate) struct EGLFence {
+    pub(crate) egl_sync: EGLSyncKHR,
+    // Whether this is an `EGL_ANDROID_native_fence_sync` fence, which can be exported as a file
+    // descriptor.
+    native: bool,
+}
+
+unsafe impl Send for EGLFence {}
+unsafe impl Sync for EGLFence {}
+
+impl Drop for EGLFence {
+    #[inline]
+    fn drop(&mut self) {
+        if self.egl_sync != EGL_NO_SYNC_KHR && !thread::panicking() {
+            panic!("Fences must be destroyed explicitly with `destroy_fence`!")
+        }
+    }
+}
+
+impl EGLFence {
+    // Inserts a fence into the command stream of the current context.
+    //
+    // The fence is a native fence if the display supports `EGL_ANDROID_native_fence_sync`, so that
+    // it can be exported later. The commands are flushed so that other contexts waiting on the
+    // fence don't wait forever.
+    pub(crate) unsafe fn new(gl: &Gl, egl_display: EGLDisplay) -> Result<EGLFence, Error> {
+        let create_sync = match (
+            EGL_EXTENSION_FUNCTIONS.CreateSyncKHR,
+            EGL_EXTENSION_FUNCTIONS.DestroySyncKHR,
+        ) {
+            (Some(create_sync), Some(_))
+                if context::display_supports_extension(egl_display, "EGL_KHR_fence_sync") =>
+            {
+                create_sync
+            }
+            _ => return Err(Error::RequiredExtensionUnavailable),
+        };
+        let native = EGL_EXTENSION_FUNCTIONS.DupNativeFenceFDANDROID.is_some()
+            && context::display_supports_extension(egl_display, "EGL_ANDROID_native_fence_sync");
+        let sync_type = if native {
+            EGL_SYNC_NATIVE_FENCE_ANDROID
+        } else {
+            egl::SYNC_FENCE
+        };
+
+        let attributes = [egl::NONE as EGLint];
+        let egl_sync = create_sync(egl_display, sync_type, attributes.as_ptr());
+        if egl_sync == EGL_NO_SYNC_KHR {
+            let err = EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+            return Err(Error::FenceCreationFailed(err));
+        }
+        gl.flush();
+
+        Ok(EGLFence { egl_sync, native })
+    }
+
+    // Wraps a native fence file descriptor. EGL takes ownership of the file descriptor if this
+    // succeeds; otherwise it's closed.
+    //
+    // The context must be current.
+    #[cfg(free_unix)]
+    pub(crate) unsafe fn from_native_fd(
+        egl_display: EGLDisplay,
+        fd: OwnedFd,
+    ) -> Result<EGLFence, Error> {
+        let create_sync = match (
+            EGL_EXTENSION_FUNCTIONS.CreateSyncKHR,
+            EGL_EXTENSION_FUNCTIONS.DestroySyncKHR,
+        ) {
+            (Some(create_sync), Some(_))
+                if context::display_supports_extension(
+                    egl_display,
+                    "EGL_ANDROID_native_fence_sync",
+                ) =>
+            {
+                create_sync
+            }
+            _ => return Err(Error::RequiredExtensionUnavailable),
+        };
+
+        let fd = fd.into_raw_fd();
+        let attributes = [
+            EGL_SYNC_NATIVE_FENCE_FD_ANDROID as EGLint,
+            fd,
+            egl::NONE as EGLint,
+        ];
+        let egl_sync = create_sync(
+            egl_display,
+            EGL_SYNC_NATIVE_FENCE_ANDROID,
+            attributes.as_ptr(),
+        );
+        if egl_sync == EGL_NO_SYNC_KHR {
+            drop(OwnedFd::from_raw_fd(fd));
+            let err = EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+            return Err(Error::FenceCreationFailed(err));
+        }
+
+        Ok(EGLFence {
+            egl_sync,
+            native: true,
+        })
+    }
+
+    // Blocks the calling thread until the fence is signaled or the timeout expires. Returns true
+    // if the fence was signaled.
+    pub(crate) unsafe fn client_wait(
+        &self,
+        egl_display: EGLDisplay,
+        timeout: Option<Duration>,
+    ) -> Result<bool, Error> {
+        let client_wait_sync = EGL_EXTENSION_FUNCTIONS
+            .ClientWaitSyncKHR
+            .ok_or(Error::RequiredExtensionUnavailable)?;
+        let timeout = match timeout {
+            None => egl::FOREVER,
+            Some(timeout) => u64::try_from(timeout.as_nanos()).unwrap_or(egl::FOREVER),
+        };
+
+        let result = client_wait_sync(
+            egl_display,
+            self.egl_sync,
+            egl::SYNC_FLUSH_COMMANDS_BIT as EGLint,
+            timeout,
+        );
+        match result as EGLenum {
+            egl::CONDITION_SATISFIED => Ok(true),
+            egl::TIMEOUT_EXPIRED => Ok(false),
+            _ => {
+                let err = EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+                Err(Error::FenceWaitFailed(err))
+            }
+        }
+    }
+
+    // Makes the GPU wait for the fence before executing further commands from the current
+    // context. Returns immediately.
+    pub(crate) unsafe fn server_wait(&self, egl_display: EGLDisplay) -> Result<(), Error> {
+        let wait_sync = match EGL_EXTENSION_FUNCTIONS.WaitSyncKHR {
+            Some(wait_sync)
+                if context::display_supports_extension(egl_display, "EGL_KHR_wait_sync") =>
+            {
+                wait_sync
+            }
+            _ => return Err(Error::RequiredExtensionUnavailable),
+        };
+        if wait_sync(egl_display, self.egl_sync, 0) == egl::FALSE as EGLint {
+            let err = EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+            return Err(Error::FenceWaitFailed(err));
+        }
+        Ok(())
+    }
+
+    // Returns a new file descriptor for a native fence.
+    #[cfg(free_unix)]
+    pub(crate) unsafe fn export_native_fd(
+        &self,
+        egl_display: EGLDisplay,
+    ) -> Result<OwnedFd, Error> {
+        let dup_native_fence_fd = match EGL_EXTENSION_FUNCTIONS.DupNativeFenceFDANDROID {
+            Some(dup_native_fence_fd) if self.native => dup_native_fence_fd,
+            _ => return Err(Error::RequiredExtensionUnavailable),
+        };
+        let fd = dup_native_fence_fd(egl_display, self.egl_sync);
+        if fd == EGL_NO_NATIVE_FENCE_FD_ANDROID {
+            let err = EGL_FUNCTIONS.with(|egl| egl.GetError().to_windowing_api_error());
+            return Err(Error::FenceExportFailed(err));
+        }
+        Ok(OwnedFd::from_raw_fd(fd))
+    }
+
+    pub(crate) unsafe fn destroy(&mut self, egl_display: EGLDisplay) {
+        if self.egl_sync == EGL_NO_SYNC_KHR {
+            return;
+        }
+        // Fences are only created if `eglDestroySyncKHR` is available.
+        let destroy_sync = EGL_EXTENSION_FUNCTIONS
+            .DestroySyncKHR
+            .expect("Fences can't be created without `eglDestroySyncKHR`!");
+        let result = destroy_sync(egl_display, self.egl_sync);
+        assert_ne!(result, egl::FALSE);
+        self.egl_sync = EGL_NO_SYNC_KHR;
+    }
+}
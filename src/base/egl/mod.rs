@@ -4,6 +4,9 @@
 
 pub(crate) mod context;
 pub(crate) mod device;
+#[cfg(free_unix)]
+pub(crate) mod dmabuf;
 pub(crate) mod error;
+pub(crate) mod fence;
 pub(crate) mod ffi;
 pub(crate) mod surface;
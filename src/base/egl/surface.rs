@@ -1,28 +1,43 @@
 //! Functionality common to backends using EGL surfaces.
 
-use super::context::CurrentContextGuard;
+use super::context::{self, CurrentContextGuard};
 use super::device::EGL_FUNCTIONS;
+#[cfg(free_unix)]
+use super::dmabuf;
 use crate::base::egl::error::ToWindowingApiError;
 use crate::base::egl::ffi::{
-    EGLClientBuffer, EGLImageKHR, EGL_EXTENSION_FUNCTIONS, EGL_GL_TEXTURE_2D_KHR,
-    EGL_IMAGE_PRESERVED_KHR, EGL_NO_IMAGE_KHR,
+    EGLClientBuffer, EGLImageKHR, EGL_BUFFER_AGE_EXT, EGL_EXTENSION_FUNCTIONS,
+    EGL_GL_TEXTURE_2D_KHR, EGL_IMAGE_PRESERVED_KHR, EGL_NO_IMAGE_KHR,
 };
-use crate::egl;
-use crate::egl::types::{EGLAttrib, EGLConfig, EGLContext, EGLDisplay, EGLSurface, EGLint};
+use crate::egl::types::EGLint;
+use crate::egl::types::{EGLAttrib, EGLBoolean, EGLConfig, EGLContext, EGLDisplay, EGLSurface};
+use crate::egl::{self, Egl};
 use crate::gl;
 use crate::gl_utils;
-use crate::renderbuffers::Renderbuffers;
+use crate::readback::ReadbackHandle;
+use crate::renderbuffers::{self, Renderbuffers};
+#[cfg(free_unix)]
+use crate::DmaBufDescriptor;
 use crate::Gl;
-use crate::{ContextAttributes, ContextID, Error, SurfaceID, SurfaceInfo};
+use crate::{
+    ContextAttributes, ContextID, Error, PixelFormat, SurfaceAccess, SurfaceFormat, SurfaceID,
+    SurfaceInfo,
+};
 
-use euclid::default::Size2D;
-use glow::{Framebuffer, HasContext, PixelUnpackData, Texture};
+use euclid::default::{Rect, Size2D};
+use glow::{Framebuffer, HasContext, PixelPackData, PixelUnpackData, Renderbuffer, Texture};
+use std::cell::Cell;
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
 use std::mem;
 use std::os::raw::c_void;
 use std::ptr;
 
+// The texture target for EGL images that can only be sampled as external textures, and its
+// binding, from `GL_OES_EGL_image_external`.
+pub(crate) const GL_TEXTURE_EXTERNAL_OES: u32 = 0x8d65;
+const GL_TEXTURE_BINDING_EXTERNAL_OES: u32 = 0x8d67;
+
 #[allow(dead_code)]
 #[derive(Clone)]
 pub(crate) struct ExternalEGLSurfaces {
@@ -33,6 +48,8 @@ pub(crate) struct ExternalEGLSurfaces {
 pub struct EGLBackedSurface {
     pub(crate) context_id: ContextID,
     pub(crate) size: Size2D<i32>,
+    pub(crate) access: SurfaceAccess,
+    pub(crate) format: Option<SurfaceFormat>,
     pub(crate) objects: EGLSurfaceObjects,
     pub(crate) destroyed: bool,
 }
@@ -52,19 +69,56 @@ pub(crate) enum EGLSurfaceObjects {
         framebuffer_object: Option<Framebuffer>,
         texture_object: Option<Texture>,
         renderbuffers: Renderbuffers,
+        multisample: Option<EGLMultisampleObjects>,
     },
     Window {
         native_window: *const c_void,
         egl_surface: EGLSurface,
+        // The swap interval set with `set_swap_interval()`, if any.
+        swap_interval: Cell<Option<EGLint>>,
     },
 }
 
+// The extra objects backing a multisampled generic surface.
+//
+// In this case the surface's framebuffer object has a multisampled color renderbuffer instead of
+// the texture, and the texture is attached to a separate framebuffer object that the color buffer
+// is resolved into.
+pub(crate) struct EGLMultisampleObjects {
+    color_renderbuffer: Option<Renderbuffer>,
+    resolve_framebuffer_object: Option<Framebuffer>,
+}
+
 pub(crate) struct EGLSurfaceTexture {
     pub(crate) surface: EGLBackedSurface,
     pub(crate) texture_object: Option<Texture>,
+    // `GL_TEXTURE_2D`, or `GL_TEXTURE_EXTERNAL_OES` for imported images that can only be sampled
+    // as external textures.
+    pub(crate) texture_target: u32,
     pub(crate) phantom: PhantomData<*const ()>,
 }
 
+// The CPU view of a generic surface.
+//
+// The contents of the EGL image are copied into `data` when the surface is locked and copied back
+// when the guard is dropped, using a texture that aliases the EGL image in whichever context was
+// current at lock time.
+pub(crate) struct EGLSurfaceDataGuard<'a> {
+    phantom: PhantomData<&'a mut EGLBackedSurface>,
+    gl: Gl,
+    egl_display: EGLDisplay,
+    egl_context: EGLContext,
+    egl_surfaces: ExternalEGLSurfaces,
+    texture_object: Option<Texture>,
+    framebuffer_object: Option<Framebuffer>,
+    size: Size2D<i32>,
+    // The format of the pixel data that's written back to the texture, which matches the channel
+    // order of `data`.
+    upload_format: u32,
+    data: Vec<u8>,
+    dirty: bool,
+}
+
 impl Debug for EGLSurfaceTexture {
     fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
         write!(f, "SurfaceTexture({:?})", self.surface)
@@ -78,8 +132,9 @@ impl EGLBackedSurface {
         egl_context: EGLContext,
         context_id: ContextID,
         context_attributes: &ContextAttributes,
+        surface_access: SurfaceAccess,
         size: &Size2D<i32>,
-    ) -> EGLBackedSurface {
+    ) -> Result<EGLBackedSurface, Error> {
         let egl_image_attribs = [
             EGL_IMAGE_PRESERVED_KHR as EGLint,
             egl::FALSE as EGLint,
@@ -87,6 +142,12 @@ impl EGLBackedSurface {
             0,
         ];
 
+        let format = context_attributes.surface_format;
+        let gl_formats = match gl_utils::surface_gl_formats(gl, format) {
+            Some(gl_formats) => gl_formats,
+            None => return Err(Error::UnsupportedSurfaceFormat),
+        };
+
         unsafe {
             // Create our texture.
             let texture_object = gl.create_texture().ok();
@@ -103,12 +164,12 @@ impl EGLBackedSurface {
             gl.tex_image_2d(
                 gl::TEXTURE_2D,
                 0,
-                gl::RGBA as i32,
+                gl_formats.internal_format as i32,
                 size.width,
                 size.height,
                 0,
-                gl::RGBA,
-                gl::UNSIGNED_BYTE,
+                gl_formats.format,
+                gl_formats.data_type,
                 PixelUnpackData::Slice(None),
             );
             // Restore the old bindings
@@ -117,7 +178,7 @@ impl EGLBackedSurface {
                 gl.bind_buffer(gl::PIXEL_UNPACK_BUFFER, unpack_buffer);
             }
 
-            // Create our image.
+            // Create our image. This fails if the driver can't share textures in this format.
             let egl_client_buffer =
                 texture_object.map_or(0, |tex| tex.0.get()) as usize as EGLClientBuffer;
             let egl_image = (EGL_EXTENSION_FUNCTIONS.CreateImageKHR)(
@@ -127,32 +188,236 @@ impl EGLBackedSurface {
                 egl_client_buffer,
                 egl_image_attribs.as_ptr(),
             );
+            if egl_image == EGL_NO_IMAGE_KHR {
+                if let Some(texture_object) = texture_object {
+                    gl.delete_texture(texture_object);
+                }
+                return Err(Error::UnsupportedSurfaceFormat);
+            }
 
-            // Create the framebuffer, and bind the texture to it.
-            let framebuffer_object =
-                gl_utils::create_and_bind_framebuffer(gl, gl::TEXTURE_2D, texture_object);
+            EGLBackedSurface::from_texture_image(
+                gl,
+                egl_display,
+                egl_image,
+                texture_object,
+                context_id,
+                context_attributes,
+                surface_access,
+                size,
+            )
+        }
+    }
 
-            // Bind renderbuffers as appropriate.
-            let renderbuffers = Renderbuffers::new(gl, size, context_attributes);
-            renderbuffers.bind_to_current_framebuffer(gl);
+    // Creates a generic surface that renders into an existing EGL image, such as one wrapping a
+    // buffer allocated outside of OpenGL. The surface takes ownership of the image, which is
+    // destroyed if this fails.
+    //
+    // The context must be current.
+    pub(crate) fn new_generic_from_egl_image(
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        egl_image: EGLImageKHR,
+        context_id: ContextID,
+        context_attributes: &ContextAttributes,
+        surface_access: SurfaceAccess,
+        size: &Size2D<i32>,
+    ) -> Result<EGLBackedSurface, Error> {
+        unsafe {
+            if gl_utils::surface_gl_formats(gl, context_attributes.surface_format).is_none() {
+                let result = (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(egl_display, egl_image);
+                assert_ne!(result, egl::FALSE);
+                return Err(Error::UnsupportedSurfaceFormat);
+            }
 
-            debug_assert_eq!(
-                gl.check_framebuffer_status(gl::FRAMEBUFFER),
-                gl::FRAMEBUFFER_COMPLETE
-            );
+            let texture_object =
+                match try_bind_egl_image_to_gl_texture(gl, egl_image, gl::TEXTURE_2D) {
+                    Some(texture_object) => texture_object,
+                    None => {
+                        let result =
+                            (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(egl_display, egl_image);
+                        assert_ne!(result, egl::FALSE);
+                        return Err(Error::UnsupportedSurfaceFormat);
+                    }
+                };
+            EGLBackedSurface::from_texture_image(
+                gl,
+                egl_display,
+                egl_image,
+                Some(texture_object),
+                context_id,
+                context_attributes,
+                surface_access,
+                size,
+            )
+        }
+    }
 
-            EGLBackedSurface {
+    // Creates a generic surface that renders into an imported dma-buf.
+    //
+    // The context must be current.
+    #[cfg(free_unix)]
+    pub(crate) fn new_generic_from_dmabuf(
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        context_id: ContextID,
+        context_attributes: &ContextAttributes,
+        surface_access: SurfaceAccess,
+        descriptor: &DmaBufDescriptor,
+    ) -> Result<EGLBackedSurface, Error> {
+        let surface_format = match dmabuf::surface_format(descriptor.fourcc) {
+            Some(surface_format) => surface_format,
+            None => return Err(Error::UnsupportedSurfaceFormat),
+        };
+        let context_attributes = ContextAttributes {
+            surface_format,
+            ..*context_attributes
+        };
+        unsafe {
+            let egl_image = dmabuf::import_egl_image(egl_display, descriptor)?;
+            EGLBackedSurface::new_generic_from_egl_image(
+                gl,
+                egl_display,
+                egl_image,
                 context_id,
-                size: *size,
+                &context_attributes,
+                surface_access,
+                &descriptor.size,
+            )
+        }
+    }
+
+    // Wraps an imported dma-buf in a surface texture.
+    //
+    // The surface inside has no framebuffer object, since the buffer may be in a format that can
+    // only be sampled from, such as multi-planar YUV. It can't be bound to a context or read back,
+    // only destroyed. Images that the driver can't bind to a 2D texture are bound to an external
+    // texture instead.
+    //
+    // The context must be current.
+    #[cfg(free_unix)]
+    pub(crate) fn new_texture_from_dmabuf(
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        context_id: ContextID,
+        descriptor: &DmaBufDescriptor,
+    ) -> Result<EGLSurfaceTexture, Error> {
+        unsafe {
+            let egl_image = dmabuf::import_egl_image(egl_display, descriptor)?;
+            let bound = [gl::TEXTURE_2D, GL_TEXTURE_EXTERNAL_OES]
+                .into_iter()
+                .find_map(|target| {
+                    try_bind_egl_image_to_gl_texture(gl, egl_image, target)
+                        .map(|texture_object| (texture_object, target))
+                });
+            let (texture_object, texture_target) = match bound {
+                Some(bound) => bound,
+                None => {
+                    let result = (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(egl_display, egl_image);
+                    assert_ne!(result, egl::FALSE);
+                    return Err(Error::UnsupportedSurfaceFormat);
+                }
+            };
+
+            let surface = EGLBackedSurface {
+                context_id,
+                size: descriptor.size,
+                access: SurfaceAccess::GPUOnly,
+                format: dmabuf::surface_format(descriptor.fourcc),
                 objects: EGLSurfaceObjects::TextureImage {
                     egl_image,
-                    framebuffer_object: Some(framebuffer_object),
-                    texture_object,
-                    renderbuffers,
+                    framebuffer_object: None,
+                    texture_object: None,
+                    renderbuffers: Renderbuffers::none(),
+                    multisample: None,
                 },
                 destroyed: false,
+            };
+            Ok(EGLSurfaceTexture {
+                surface,
+                texture_object: Some(texture_object),
+                texture_target,
+                phantom: PhantomData,
+            })
+        }
+    }
+
+    // Wraps a texture and the EGL image that shares it in a generic surface, creating the
+    // framebuffer object and any depth, stencil, and multisample buffers.
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn from_texture_image(
+        gl: &Gl,
+        egl_display: EGLDisplay,
+        egl_image: EGLImageKHR,
+        texture_object: Option<Texture>,
+        context_id: ContextID,
+        context_attributes: &ContextAttributes,
+        surface_access: SurfaceAccess,
+        size: &Size2D<i32>,
+    ) -> Result<EGLBackedSurface, Error> {
+        let format = context_attributes.surface_format;
+        let gl_formats = match gl_utils::surface_gl_formats(gl, format) {
+            Some(gl_formats) => gl_formats,
+            None => return Err(Error::UnsupportedSurfaceFormat),
+        };
+
+        // Create the framebuffer, and bind the texture to it.
+        let mut framebuffer_object =
+            gl_utils::create_and_bind_framebuffer(gl, gl::TEXTURE_2D, texture_object);
+        let mut complete = gl.check_framebuffer_status(gl::FRAMEBUFFER) == gl::FRAMEBUFFER_COMPLETE;
+
+        // If multisampling was requested, render into a multisampled color buffer instead, and
+        // keep the texture's framebuffer around to resolve into.
+        let samples = context_attributes.samples.min(gl_utils::max_samples(gl));
+        let mut multisample = None;
+        let samples = match gl_formats.renderbuffer_format {
+            Some(renderbuffer_format) if samples > 1 => {
+                let color_renderbuffer =
+                    renderbuffers::create_renderbuffer(gl, renderbuffer_format, size, samples).ok();
+                let resolve_framebuffer_object = framebuffer_object;
+                framebuffer_object = gl.create_framebuffer().unwrap();
+                gl.bind_framebuffer(gl::FRAMEBUFFER, Some(framebuffer_object));
+                gl.framebuffer_renderbuffer(
+                    gl::FRAMEBUFFER,
+                    gl::COLOR_ATTACHMENT0,
+                    gl::RENDERBUFFER,
+                    color_renderbuffer,
+                );
+                multisample = Some(EGLMultisampleObjects {
+                    color_renderbuffer,
+                    resolve_framebuffer_object: Some(resolve_framebuffer_object),
+                });
+                samples
             }
+            _ => 0,
+        };
+
+        // Bind renderbuffers as appropriate.
+        let renderbuffers = Renderbuffers::new_multisampled(gl, size, context_attributes, samples);
+        renderbuffers.bind_to_current_framebuffer(gl);
+        complete &= gl.check_framebuffer_status(gl::FRAMEBUFFER) == gl::FRAMEBUFFER_COMPLETE;
+
+        let mut surface = EGLBackedSurface {
+            context_id,
+            size: *size,
+            access: surface_access,
+            format: Some(format),
+            objects: EGLSurfaceObjects::TextureImage {
+                egl_image,
+                framebuffer_object: Some(framebuffer_object),
+                texture_object,
+                renderbuffers,
+                multisample,
+            },
+            destroyed: false,
+        };
+
+        // The driver may accept the texture but not be able to render to it.
+        if !complete {
+            surface.destroy(gl, egl_display, context_id)?;
+            return Err(Error::UnsupportedSurfaceFormat);
         }
+
+        Ok(surface)
     }
 
     pub(crate) fn new_window(
@@ -175,9 +440,12 @@ impl EGLBackedSurface {
             EGLBackedSurface {
                 context_id,
                 size: *size,
+                access: SurfaceAccess::GPUOnly,
+                format: Some(SurfaceFormat::RGBA8),
                 objects: EGLSurfaceObjects::Window {
                     native_window,
                     egl_surface,
+                    swap_interval: Cell::new(None),
                 },
                 destroyed: false,
             }
@@ -197,6 +465,7 @@ impl EGLBackedSurface {
             Ok(EGLSurfaceTexture {
                 surface: self,
                 texture_object: Some(texture_object),
+                texture_target: gl::TEXTURE_2D,
                 phantom: PhantomData,
             })
         }
@@ -219,12 +488,16 @@ impl EGLBackedSurface {
                     ref mut framebuffer_object,
                     ref mut texture_object,
                     ref mut renderbuffers,
+                    ref mut multisample,
                 } => {
                     gl.bind_framebuffer(gl::FRAMEBUFFER, None);
                     if let Some(framebuffer) = framebuffer_object.take() {
                         gl.delete_framebuffer(framebuffer);
                     }
                     renderbuffers.destroy(gl);
+                    if let Some(multisample) = multisample {
+                        multisample.destroy(gl);
+                    }
 
                     let result = (EGL_EXTENSION_FUNCTIONS.DestroyImageKHR)(egl_display, *egl_image);
                     assert_ne!(result, egl::FALSE);
@@ -240,6 +513,7 @@ impl EGLBackedSurface {
                 EGLSurfaceObjects::Window {
                     ref mut egl_surface,
                     ref mut native_window,
+                    ..
                 } => {
                     EGL_FUNCTIONS.with(|egl| {
                         egl.DestroySurface(egl_display, *egl_surface);
@@ -253,45 +527,105 @@ impl EGLBackedSurface {
         }
     }
 
-    // TODO(pcwalton): Damage regions.
+    // Presents a window surface. See `swap_buffers_with_damage()` for the meaning of `damage`.
     pub(crate) fn present(
         &self,
         egl_display: EGLDisplay,
         egl_context: EGLContext,
+        damage: &[Rect<i32>],
     ) -> Result<(), Error> {
-        unsafe {
-            match self.objects {
-                EGLSurfaceObjects::Window { egl_surface, .. } => {
-                    // The surface must be bound to the current context in EGL 1.4. Temporarily
-                    // make this surface current to enforce this.
-                    let _guard = CurrentContextGuard::new();
-
-                    EGL_FUNCTIONS.with(|egl| {
-                        let result =
-                            egl.MakeCurrent(egl_display, egl_surface, egl_surface, egl_context);
-                        if result == egl::FALSE {
+        match self.objects {
+            EGLSurfaceObjects::Window {
+                egl_surface,
+                ref swap_interval,
+                ..
+            } => unsafe {
+                // The surface must be bound to the current context in EGL 1.4. Temporarily make
+                // this surface current to enforce this.
+                with_window_surface_current(egl_display, egl_surface, egl_context, |egl| {
+                    // Not every driver keeps the swap interval with the surface when the context
+                    // is made current with other surfaces in between, so reapply it.
+                    if let Some(interval) = swap_interval.get() {
+                        if egl.SwapInterval(egl_display, interval) == egl::FALSE {
                             let err = egl.GetError().to_windowing_api_error();
-                            return Err(Error::MakeCurrentFailed(err));
+                            return Err(Error::PresentFailed(err));
                         }
+                    }
+                    let ok = swap_buffers_with_damage(egl, egl_display, egl_surface, damage);
+                    if ok != egl::FALSE {
+                        Ok(())
+                    } else {
+                        Err(Error::PresentFailed(
+                            egl.GetError().to_windowing_api_error(),
+                        ))
+                    }
+                })
+            },
+            EGLSurfaceObjects::TextureImage { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
 
-                        let ok = egl.SwapBuffers(egl_display, egl_surface);
-                        if ok != egl::FALSE {
-                            Ok(())
-                        } else {
-                            Err(Error::PresentFailed(
-                                egl.GetError().to_windowing_api_error(),
-                            ))
-                        }
-                    })
-                }
-                EGLSurfaceObjects::TextureImage { .. } => Err(Error::NoWidgetAttached),
-            }
+    // Returns the age of the back buffer of a window surface. See `query_buffer_age()`.
+    pub(crate) fn buffer_age(
+        &self,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+    ) -> Result<u32, Error> {
+        match self.objects {
+            EGLSurfaceObjects::Window { egl_surface, .. } => unsafe {
+                with_window_surface_current(egl_display, egl_surface, egl_context, |egl| {
+                    query_buffer_age(egl, egl_display, egl_surface)
+                })
+            },
+            EGLSurfaceObjects::TextureImage { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    // Sets the damage region of a window surface for the current frame. See
+    // `set_damage_region()`.
+    pub(crate) fn set_damage_region(
+        &self,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        match self.objects {
+            EGLSurfaceObjects::Window { egl_surface, .. } => unsafe {
+                with_window_surface_current(egl_display, egl_surface, egl_context, |egl| {
+                    set_damage_region(egl, egl_display, egl_surface, damage)
+                })
+            },
+            EGLSurfaceObjects::TextureImage { .. } => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    // Sets the swap interval of a window surface. See `set_swap_interval()`.
+    pub(crate) fn set_swap_interval(
+        &self,
+        egl_display: EGLDisplay,
+        egl_context: EGLContext,
+        interval: i32,
+    ) -> Result<(), Error> {
+        match self.objects {
+            EGLSurfaceObjects::Window {
+                egl_surface,
+                ref swap_interval,
+                ..
+            } => unsafe {
+                with_window_surface_current(egl_display, egl_surface, egl_context, |egl| {
+                    set_swap_interval(egl, egl_display, egl_surface, interval)
+                })?;
+                swap_interval.set(Some(interval));
+                Ok(())
+            },
+            EGLSurfaceObjects::TextureImage { .. } => Err(Error::NoWidgetAttached),
         }
     }
 
     pub(crate) fn info(&self) -> SurfaceInfo {
         SurfaceInfo {
             size: self.size,
+            valid_size: self.size,
             id: self.id(),
             context_id: self.context_id,
             framebuffer_object: match self.objects {
@@ -300,6 +634,24 @@ impl EGLBackedSurface {
                 } => framebuffer_object,
                 EGLSurfaceObjects::Window { .. } => None,
             },
+            format: self.format,
+            depth_format: match self.objects {
+                EGLSurfaceObjects::TextureImage {
+                    ref renderbuffers, ..
+                } => renderbuffers.depth_format(),
+                EGLSurfaceObjects::Window { .. } => None,
+            },
+        }
+    }
+
+    // Exports the dma-buf behind a generic surface.
+    #[cfg(free_unix)]
+    pub(crate) fn export_dmabuf(&self, egl_display: EGLDisplay) -> Result<DmaBufDescriptor, Error> {
+        match self.objects {
+            EGLSurfaceObjects::TextureImage { egl_image, .. } => unsafe {
+                dmabuf::export_egl_image(egl_display, egl_image, self.size)
+            },
+            EGLSurfaceObjects::Window { .. } => Err(Error::WidgetAttached),
         }
     }
 
@@ -353,6 +705,325 @@ impl EGLBackedSurface {
     pub(crate) fn resize(&mut self, size: Size2D<i32>) {
         self.size = size;
     }
+
+    // Returns an error if the surface wraps a buffer that was imported only for sampling, which
+    // has no framebuffer object to render into or read from.
+    pub(crate) fn check_renderable(&self) -> Result<(), Error> {
+        match self.objects {
+            EGLSurfaceObjects::TextureImage {
+                framebuffer_object: None,
+                ..
+            } if !self.destroyed => Err(Error::IncompatibleSurface),
+            EGLSurfaceObjects::TextureImage { .. } | EGLSurfaceObjects::Window { .. } => Ok(()),
+        }
+    }
+
+    pub(crate) fn is_multisampled(&self) -> bool {
+        matches!(
+            self.objects,
+            EGLSurfaceObjects::TextureImage {
+                multisample: Some(_),
+                ..
+            }
+        )
+    }
+
+    // Resolves the multisampled color buffer of a generic surface into its texture. Does nothing
+    // if the surface isn't multisampled.
+    //
+    // The context that the surface was created with must be current.
+    pub(crate) fn resolve(&self, gl: &Gl) {
+        if let EGLSurfaceObjects::TextureImage {
+            framebuffer_object: Some(framebuffer_object),
+            multisample:
+                Some(EGLMultisampleObjects {
+                    resolve_framebuffer_object: Some(resolve_framebuffer_object),
+                    ..
+                }),
+            ..
+        } = self.objects
+        {
+            gl_utils::resolve_framebuffer(
+                gl,
+                framebuffer_object,
+                resolve_framebuffer_object,
+                self.size,
+            );
+        }
+    }
+
+    // OpenGL ES can only read half-float framebuffers back as floats, but readback produces
+    // 8-bit channels.
+    fn check_readable(&self, gl: &Gl) -> Result<(), Error> {
+        match self.format {
+            Some(SurfaceFormat::RGBA16F) if gl.version().is_embedded => {
+                Err(Error::UnsupportedSurfaceFormat)
+            }
+            Some(_) => Ok(()),
+            None => Err(Error::UnsupportedSurfaceFormat),
+        }
+    }
+
+    // Returns the framebuffer object that has the surface's texture attached, which differs from
+    // the one that is rendered to if the surface is multisampled.
+    fn resolved_framebuffer_object(&self) -> Option<Framebuffer> {
+        match self.objects {
+            EGLSurfaceObjects::TextureImage {
+                multisample: Some(ref multisample),
+                ..
+            } => multisample.resolve_framebuffer_object,
+            EGLSurfaceObjects::TextureImage {
+                framebuffer_object, ..
+            } => framebuffer_object,
+            EGLSurfaceObjects::Window { .. } => None,
+        }
+    }
+
+    // Reads back a region of a generic surface through its framebuffer object.
+    //
+    // The context that the surface was created with must be current.
+    pub(crate) fn read_pixels(
+        &self,
+        gl: &Gl,
+        context_id: ContextID,
+        rect: Rect<i32>,
+        format: PixelFormat,
+    ) -> Result<Vec<u8>, Error> {
+        if context_id != self.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        self.check_renderable()?;
+        self.check_readable(gl)?;
+        match self.objects {
+            EGLSurfaceObjects::TextureImage { .. } => gl_utils::read_framebuffer_pixels(
+                gl,
+                self.resolved_framebuffer_object(),
+                self.size,
+                rect,
+                format,
+            ),
+            EGLSurfaceObjects::Window { .. } => Err(Error::WidgetAttached),
+        }
+    }
+
+    // Starts an asynchronous readback of a generic surface through its framebuffer object.
+    //
+    // The context that the surface was created with must be current.
+    pub(crate) fn begin_readback(
+        &self,
+        gl: &Gl,
+        context_id: ContextID,
+    ) -> Result<ReadbackHandle, Error> {
+        if context_id != self.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        self.check_renderable()?;
+        self.check_readable(gl)?;
+        match self.objects {
+            EGLSurfaceObjects::TextureImage { .. } => ReadbackHandle::new(
+                gl,
+                context_id,
+                self.resolved_framebuffer_object(),
+                self.size,
+            ),
+            EGLSurfaceObjects::Window { .. } => Err(Error::WidgetAttached),
+        }
+    }
+
+    // Copies the contents of a generic surface into CPU memory.
+    //
+    // A context on `egl_display` must be current. The same context is used to write the data back
+    // when the returned guard is dropped.
+    pub(crate) fn lock_data(
+        &mut self,
+        egl_display: EGLDisplay,
+    ) -> Result<EGLSurfaceDataGuard<'_>, Error> {
+        if !self.access.cpu_access_allowed() {
+            return Err(Error::SurfaceDataInaccessible);
+        }
+        let format = match self.format {
+            Some(
+                format @ (SurfaceFormat::RGBA8 | SurfaceFormat::BGRA8 | SurfaceFormat::SRGBA8),
+            ) => format,
+            Some(SurfaceFormat::RGB10A2 | SurfaceFormat::RGBA16F) | None => {
+                return Err(Error::UnsupportedSurfaceFormat)
+            }
+        };
+        // Writes to the resolved image would be overwritten by the next resolve.
+        if self.is_multisampled() {
+            return Err(Error::MultisampledSurface);
+        }
+        let egl_image = match self.objects {
+            EGLSurfaceObjects::TextureImage { egl_image, .. } => egl_image,
+            EGLSurfaceObjects::Window { .. } => return Err(Error::WidgetAttached),
+        };
+
+        let (egl_context, egl_surfaces) = EGL_FUNCTIONS.with(|egl| unsafe {
+            (
+                egl.GetCurrentContext(),
+                ExternalEGLSurfaces {
+                    draw: egl.GetCurrentSurface(egl::DRAW as EGLint),
+                    read: egl.GetCurrentSurface(egl::READ as EGLint),
+                },
+            )
+        });
+        let current_egl_display = EGL_FUNCTIONS.with(|egl| unsafe { egl.GetCurrentDisplay() });
+        if egl_context == egl::NO_CONTEXT || current_egl_display != egl_display {
+            return Err(Error::NoCurrentContext);
+        }
+
+        unsafe {
+            let gl = Gl::from_loader_function(context::get_proc_address);
+            let upload_format = match gl_utils::surface_gl_formats(&gl, format) {
+                Some(gl_formats) => gl_formats.format,
+                None => return Err(Error::UnsupportedSurfaceFormat),
+            };
+            let texture_object = bind_egl_image_to_gl_texture(&gl, egl_image);
+
+            let old_read_framebuffer = gl.get_parameter_framebuffer(gl::READ_FRAMEBUFFER_BINDING);
+            let pack_buffer = gl.get_parameter_buffer(gl::PIXEL_PACK_BUFFER_BINDING);
+            let pack_alignment = gl.get_parameter_i32(gl::PACK_ALIGNMENT);
+            let framebuffer_object = gl.create_framebuffer().ok();
+            gl.bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffer_object);
+            gl.framebuffer_texture_2d(
+                gl::READ_FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                Some(texture_object),
+                0,
+            );
+            if pack_buffer.is_some() {
+                gl.bind_buffer(gl::PIXEL_PACK_BUFFER, None);
+            }
+            gl.pixel_store_i32(gl::PACK_ALIGNMENT, 1);
+
+            let stride = self.size.width as usize * 4;
+            let mut data = vec![0; stride * self.size.height as usize];
+            gl.read_pixels(
+                0,
+                0,
+                self.size.width,
+                self.size.height,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                PixelPackData::Slice(Some(&mut data)),
+            );
+            gl_utils::flip_rows(&mut data, stride);
+            if format == SurfaceFormat::BGRA8 {
+                gl_utils::swap_red_and_blue(&mut data);
+            }
+
+            // Restore the old bindings.
+            gl.pixel_store_i32(gl::PACK_ALIGNMENT, pack_alignment);
+            if pack_buffer.is_some() {
+                gl.bind_buffer(gl::PIXEL_PACK_BUFFER, pack_buffer);
+            }
+            gl.bind_framebuffer(gl::READ_FRAMEBUFFER, old_read_framebuffer);
+
+            Ok(EGLSurfaceDataGuard {
+                phantom: PhantomData,
+                gl,
+                egl_display,
+                egl_context,
+                egl_surfaces,
+                texture_object: Some(texture_object),
+                framebuffer_object,
+                size: self.size,
+                upload_format,
+                data,
+                dirty: false,
+            })
+        }
+    }
+}
+
+impl EGLMultisampleObjects {
+    fn destroy(&mut self, gl: &Gl) {
+        unsafe {
+            if let Some(framebuffer) = self.resolve_framebuffer_object.take() {
+                gl_utils::destroy_framebuffer(gl, framebuffer);
+            }
+            if let Some(renderbuffer) = self.color_renderbuffer.take() {
+                gl.delete_renderbuffer(renderbuffer);
+            }
+        }
+    }
+}
+
+impl<'a> EGLSurfaceDataGuard<'a> {
+    #[inline]
+    pub(crate) fn stride(&self) -> usize {
+        self.size.width as usize * 4
+    }
+
+    #[inline]
+    pub(crate) fn data(&mut self) -> &mut [u8] {
+        // We can't tell whether the caller writes through this slice, so assume it does.
+        self.dirty = true;
+        &mut self.data
+    }
+}
+
+impl<'a> Drop for EGLSurfaceDataGuard<'a> {
+    fn drop(&mut self) {
+        // Switch back to the context that the surface was locked with, if necessary.
+        let _guard = CurrentContextGuard::new();
+        EGL_FUNCTIONS.with(|egl| unsafe {
+            if egl.GetCurrentContext() != self.egl_context {
+                egl.MakeCurrent(
+                    self.egl_display,
+                    self.egl_surfaces.draw,
+                    self.egl_surfaces.read,
+                    self.egl_context,
+                );
+            }
+        });
+
+        let gl = &self.gl;
+        unsafe {
+            if self.dirty {
+                let stride = self.stride();
+                gl_utils::flip_rows(&mut self.data, stride);
+
+                let old_texture_object = gl.get_parameter_texture(gl::TEXTURE_BINDING_2D);
+                let unpack_buffer = gl.get_parameter_buffer(gl::PIXEL_UNPACK_BUFFER_BINDING);
+                let unpack_alignment = gl.get_parameter_i32(gl::UNPACK_ALIGNMENT);
+                gl.bind_texture(gl::TEXTURE_2D, self.texture_object);
+                if unpack_buffer.is_some() {
+                    gl.bind_buffer(gl::PIXEL_UNPACK_BUFFER, None);
+                }
+                gl.pixel_store_i32(gl::UNPACK_ALIGNMENT, 1);
+                gl.tex_sub_image_2d(
+                    gl::TEXTURE_2D,
+                    0,
+                    0,
+                    0,
+                    self.size.width,
+                    self.size.height,
+                    self.upload_format,
+                    gl::UNSIGNED_BYTE,
+                    PixelUnpackData::Slice(Some(&self.data)),
+                );
+
+                // Restore the old bindings.
+                gl.pixel_store_i32(gl::UNPACK_ALIGNMENT, unpack_alignment);
+                if unpack_buffer.is_some() {
+                    gl.bind_buffer(gl::PIXEL_UNPACK_BUFFER, unpack_buffer);
+                }
+                gl.bind_texture(gl::TEXTURE_2D, old_texture_object);
+            }
+
+            if let Some(framebuffer_object) = self.framebuffer_object.take() {
+                gl_utils::destroy_framebuffer(gl, framebuffer_object);
+            }
+            if let Some(texture_object) = self.texture_object.take() {
+                gl.delete_texture(texture_object);
+            }
+
+            // Make sure other contexts see the new contents.
+            gl.flush();
+        }
+    }
 }
 
 impl EGLSurfaceTexture {
@@ -406,19 +1077,233 @@ pub(crate) unsafe fn create_pbuffer_surface(
 
 #[allow(dead_code)]
 pub(crate) unsafe fn bind_egl_image_to_gl_texture(gl: &Gl, egl_image: EGLImageKHR) -> Texture {
-    let texture = gl.create_texture().unwrap();
+    let texture = create_texture_from_egl_image(gl, egl_image, gl::TEXTURE_2D);
+    debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
+    texture
+}
+
+// Like `bind_egl_image_to_gl_texture()`, but binds to the given target, and returns `None` instead
+// of asserting if the driver can't bind the image to it. This happens with images from outside
+// OpenGL whose format can only be sampled as an external texture, or with external textures if
+// `GL_OES_EGL_image_external` isn't supported.
+//
+// Any errors already pending in the context are cleared first.
+#[allow(dead_code)]
+pub(crate) unsafe fn try_bind_egl_image_to_gl_texture(
+    gl: &Gl,
+    egl_image: EGLImageKHR,
+    target: u32,
+) -> Option<Texture> {
+    while gl.get_error() != gl::NO_ERROR {}
+    let texture = create_texture_from_egl_image(gl, egl_image, target);
+    if gl.get_error() != gl::NO_ERROR {
+        gl.delete_texture(texture);
+        return None;
+    }
+    Some(texture)
+}
 
-    let texture_binding = gl.get_parameter_texture(gl::TEXTURE_BINDING_2D);
+unsafe fn create_texture_from_egl_image(gl: &Gl, egl_image: EGLImageKHR, target: u32) -> Texture {
+    let texture = gl.create_texture().unwrap();
 
-    // FIXME(pcwalton): Should this be `GL_TEXTURE_EXTERNAL_OES`?
-    gl.bind_texture(gl::TEXTURE_2D, Some(texture));
-    (EGL_EXTENSION_FUNCTIONS.ImageTargetTexture2DOES)(gl::TEXTURE_2D, egl_image);
-    gl.tex_parameter_i32(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as _);
-    gl.tex_parameter_i32(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as _);
-    gl.tex_parameter_i32(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as _);
-    gl.tex_parameter_i32(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as _);
-    gl.bind_texture(gl::TEXTURE_2D, texture_binding);
+    let texture_binding = gl.get_parameter_texture(match target {
+        GL_TEXTURE_EXTERNAL_OES => GL_TEXTURE_BINDING_EXTERNAL_OES,
+        _ => gl::TEXTURE_BINDING_2D,
+    });
 
-    debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
+    gl.bind_texture(target, Some(texture));
+    (EGL_EXTENSION_FUNCTIONS.ImageTargetTexture2DOES)(target, egl_image);
+    gl.tex_parameter_i32(target, gl::TEXTURE_MAG_FILTER, gl::LINEAR as _);
+    gl.tex_parameter_i32(target, gl::TEXTURE_MIN_FILTER, gl::LINEAR as _);
+    gl.tex_parameter_i32(target, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as _);
+    gl.tex_parameter_i32(target, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as _);
+    gl.bind_texture(target, texture_binding);
     texture
 }
+
+// Makes a window surface current with the given context while `f` runs, since EGL only allows
+// some operations on the current draw surface. The previous context is restored afterward.
+pub(crate) unsafe fn with_window_surface_current<T>(
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    egl_context: EGLContext,
+    f: impl FnOnce(&Egl) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let _guard = CurrentContextGuard::new();
+    EGL_FUNCTIONS.with(|egl| {
+        let result = egl.MakeCurrent(egl_display, egl_surface, egl_surface, egl_context);
+        if result == egl::FALSE {
+            let err = egl.GetError().to_windowing_api_error();
+            return Err(Error::MakeCurrentFailed(err));
+        }
+        f(egl)
+    })
+}
+
+// Converts rectangles relative to the top left corner of a window surface into the flat list of
+// rectangles relative to the bottom left corner that EGL damage functions take.
+unsafe fn egl_damage_rects(
+    egl: &Egl,
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    damage: &[Rect<i32>],
+) -> Option<Vec<EGLint>> {
+    let mut height = 0;
+    if egl.QuerySurface(egl_display, egl_surface, egl::HEIGHT as EGLint, &mut height) == egl::FALSE
+    {
+        return None;
+    }
+    Some(
+        damage
+            .iter()
+            .flat_map(|rect| {
+                [
+                    rect.origin.x,
+                    height - rect.max_y(),
+                    rect.size.width,
+                    rect.size.height,
+                ]
+            })
+            .collect(),
+    )
+}
+
+// Swaps the buffers of a window surface, telling the compositor which rectangles changed if the
+// display supports `EGL_KHR_swap_buffers_with_damage` or `EGL_EXT_swap_buffers_with_damage`.
+//
+// The rectangles are relative to the top left corner of the surface, and are flipped here since
+// EGL expects them relative to the bottom left. An empty list damages the whole surface. Without
+// either extension, this falls back to a full `eglSwapBuffers()`.
+pub(crate) unsafe fn swap_buffers_with_damage(
+    egl: &Egl,
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    damage: &[Rect<i32>],
+) -> EGLBoolean {
+    let swap_buffers_with_damage = if damage.is_empty() {
+        None
+    } else if context::display_supports_extension(egl_display, "EGL_KHR_swap_buffers_with_damage") {
+        EGL_EXTENSION_FUNCTIONS.SwapBuffersWithDamageKHR
+    } else if context::display_supports_extension(egl_display, "EGL_EXT_swap_buffers_with_damage") {
+        EGL_EXTENSION_FUNCTIONS.SwapBuffersWithDamageEXT
+    } else {
+        None
+    };
+    let swap_buffers_with_damage = match swap_buffers_with_damage {
+        Some(swap_buffers_with_damage) => swap_buffers_with_damage,
+        None => return egl.SwapBuffers(egl_display, egl_surface),
+    };
+
+    match egl_damage_rects(egl, egl_display, egl_surface, damage) {
+        Some(rects) => swap_buffers_with_damage(
+            egl_display,
+            egl_surface,
+            rects.as_ptr(),
+            damage.len() as EGLint,
+        ),
+        None => egl.SwapBuffers(egl_display, egl_surface),
+    }
+}
+
+// Returns how many frames old the contents of the back buffer of the current window surface are,
+// using `EGL_EXT_buffer_age` or `EGL_KHR_partial_update`. An age of 0 means that the contents are
+// undefined.
+pub(crate) unsafe fn query_buffer_age(
+    egl: &Egl,
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+) -> Result<u32, Error> {
+    if !context::display_supports_extension(egl_display, "EGL_EXT_buffer_age")
+        && !context::display_supports_extension(egl_display, "EGL_KHR_partial_update")
+    {
+        return Err(Error::RequiredExtensionUnavailable);
+    }
+    let mut age = 0;
+    if egl.QuerySurface(
+        egl_display,
+        egl_surface,
+        EGL_BUFFER_AGE_EXT as EGLint,
+        &mut age,
+    ) == egl::FALSE
+    {
+        let err = egl.GetError().to_windowing_api_error();
+        return Err(Error::PresentFailed(err));
+    }
+    Ok(age as u32)
+}
+
+// Limits the pixels that will be updated in the back buffer of the current window surface this
+// frame, using `EGL_KHR_partial_update`. The rectangles are relative to the top left corner of
+// the surface, and an empty list damages the whole surface.
+pub(crate) unsafe fn set_damage_region(
+    egl: &Egl,
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    damage: &[Rect<i32>],
+) -> Result<(), Error> {
+    let set_damage_region = match EGL_EXTENSION_FUNCTIONS.SetDamageRegionKHR {
+        Some(set_damage_region)
+            if context::display_supports_extension(egl_display, "EGL_KHR_partial_update") =>
+        {
+            set_damage_region
+        }
+        _ => return Err(Error::RequiredExtensionUnavailable),
+    };
+
+    // The buffer age must be queried before the damage region is set in each frame.
+    query_buffer_age(egl, egl_display, egl_surface)?;
+    let mut rects = match egl_damage_rects(egl, egl_display, egl_surface, damage) {
+        Some(rects) => rects,
+        None => {
+            let err = egl.GetError().to_windowing_api_error();
+            return Err(Error::PresentFailed(err));
+        }
+    };
+    let ok = set_damage_region(
+        egl_display,
+        egl_surface,
+        rects.as_mut_ptr(),
+        damage.len() as EGLint,
+    );
+    if ok == egl::FALSE {
+        let err = egl.GetError().to_windowing_api_error();
+        return Err(Error::PresentFailed(err));
+    }
+    Ok(())
+}
+
+// Sets the minimum number of video frame periods per buffer swap of the current window surface.
+// `eglSwapInterval()` silently clamps the interval to the range supported by the surface's config,
+// so intervals outside that range are rejected with `UnsupportedSwapInterval` instead.
+pub(crate) unsafe fn set_swap_interval(
+    egl: &Egl,
+    egl_display: EGLDisplay,
+    egl_surface: EGLSurface,
+    interval: i32,
+) -> Result<(), Error> {
+    let mut egl_config_id = 0;
+    if egl.QuerySurface(
+        egl_display,
+        egl_surface,
+        egl::CONFIG_ID as EGLint,
+        &mut egl_config_id,
+    ) == egl::FALSE
+    {
+        let err = egl.GetError().to_windowing_api_error();
+        return Err(Error::PresentFailed(err));
+    }
+    let egl_config = context::egl_config_from_id(egl_display, egl_config_id);
+    let min_interval =
+        context::get_config_attr(egl_display, egl_config, egl::MIN_SWAP_INTERVAL as EGLint);
+    let max_interval =
+        context::get_config_attr(egl_display, egl_config, egl::MAX_SWAP_INTERVAL as EGLint);
+    if interval < min_interval || interval > max_interval {
+        return Err(Error::UnsupportedSwapInterval);
+    }
+
+    if egl.SwapInterval(egl_display, interval) == egl::FALSE {
+        let err = egl.GetError().to_windowing_api_error();
+        return Err(Error::PresentFailed(err));
+    }
+    Ok(())
+}
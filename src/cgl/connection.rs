@@ -7,8 +7,7 @@ use super::device::{Adapter, Device};
 use crate::base::io_surface::connection::Connection as SystemConnection;
 use crate::base::io_surface::device::NativeDevice;
 use crate::base::io_surface::surface::NativeWidget;
-use crate::Error;
-use crate::GLApi;
+use crate::{AdapterInfo, Error, GLApi};
 
 use euclid::default::Size2D;
 
@@ -73,6 +72,12 @@ impl Connection {
         self.0.create_software_adapter().map(Adapter)
     }
 
+    /// Returns an empty list, since this backend can't enumerate adapters.
+    #[inline]
+    pub fn enumerate_adapters(&self) -> Vec<AdapterInfo<Adapter>> {
+        vec![]
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
     /// Device handles are local to a single thread.
@@ -39,6 +39,11 @@ pub struct Context {
 /// Wraps a native CGL context object.
 pub struct NativeContext(pub CGLContextObj);
 
+/// Represents a GPU fence.
+///
+/// Fences aren't supported on this backend, so values of this type can't be created.
+pub enum Fence {}
+
 impl Drop for Context {
     #[inline]
     fn drop(&mut self) {
@@ -2,16 +2,18 @@
 
 use super::connection::Connection;
 use crate::base::io_surface::device::{Adapter as SystemAdapter, Device as SystemDevice};
-use crate::cgl::context::CurrentContextGuard;
+use crate::cgl::context::{CurrentContextGuard, Fence};
 use crate::cgl::error::ToWindowingApiError;
 use crate::cgl::ffi::{CGLReleaseContext, CGLRetainContext};
-use crate::cgl::surface::{surface_bind_to_gl_texture, NativeSurface};
+use crate::cgl::surface::{surface_bind_to_gl_texture, NativeSurface, SurfaceDataGuard};
 use crate::context::{ContextID, CREATE_CONTEXT_MUTEX};
+use crate::readback::ReadbackHandle;
 use crate::renderbuffers::Renderbuffers;
 use crate::surface::Framebuffer;
 use crate::{
-    gl, gl_utils, Context, GLVersion, NativeContext, NativeWidget, Surface, SurfaceAccess,
-    SurfaceInfo, SurfaceTexture, SurfaceType, WindowingApiError,
+    gl, gl_utils, Context, ContextResetStatus, DepthFormat, GLVersion, NativeContext, NativeWidget,
+    PixelFormat, Surface, SurfaceAccess, SurfaceFormat, SurfaceInfo, SurfaceTexture, SurfaceType,
+    WindowingApiError,
 };
 use crate::{ContextAttributeFlags, ContextAttributes, ContextDescriptor, Error, GLApi, Gl};
 use cgl::{
@@ -20,7 +22,7 @@ use cgl::{
     CGLDescribePixelFormat, CGLError, CGLGetPixelFormat, CGLPixelFormatAttribute,
     CGLRetainPixelFormat, CGLSetCurrentContext,
 };
-use euclid::default::Size2D;
+use euclid::default::{Rect, Size2D};
 use glow::HasContext;
 use glow::Texture;
 use objc2_core_foundation::{CFBundle, CFRetained, CFString};
@@ -29,6 +31,7 @@ use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::os::raw::c_void;
 use std::rc::Rc;
+use std::time::Duration;
 use std::{mem, ptr};
 
 pub use crate::base::io_surface::device::NativeDevice;
@@ -102,6 +105,23 @@ impl Device {
         &self,
         attributes: &ContextAttributes,
     ) -> Result<ContextDescriptor, Error> {
+        // Surfaces on this backend are always BGRA8 IOSurfaces, which also serve requests for
+        // RGBA8.
+        if !matches!(
+            attributes.surface_format,
+            SurfaceFormat::RGBA8 | SurfaceFormat::BGRA8
+        ) {
+            return Err(Error::UnsupportedSurfaceFormat);
+        }
+
+        // CGL has no way to request a robust or debug context.
+        if attributes
+            .flags
+            .intersects(ContextAttributeFlags::ROBUST_ACCESS | ContextAttributeFlags::DEBUG)
+        {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+
         if attributes
             .flags
             .contains(ContextAttributeFlags::COMPATIBILITY_PROFILE)
@@ -426,6 +446,9 @@ impl Device {
             return ContextAttributes {
                 flags: attribute_flags,
                 version,
+                samples: 0,
+                surface_format: SurfaceFormat::BGRA8,
+                depth_format: DepthFormat::Depth24,
             };
         }
 
@@ -445,6 +468,42 @@ impl Device {
         }
     }
 
+    /// Returns the largest number of samples per pixel that generic surfaces created with this
+    /// context can use.
+    ///
+    /// Multisampled generic surfaces aren't supported on this backend, so this always returns 0.
+    #[inline]
+    pub fn max_surface_samples(&self, _: &Context) -> Result<u32, Error> {
+        Ok(0)
+    }
+
+    /// Returns whether the context has been lost because of a GPU reset, and if so, whether this
+    /// context caused it.
+    pub fn context_reset_status(&self, context: &Context) -> Result<ContextResetStatus, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        gl_utils::context_reset_status(&context.gl, get_proc_address)
+    }
+
+    /// Returns `RequiredExtensionUnavailable`, since fences aren't supported on this backend.
+    pub fn create_fence(&self, _: &Context) -> Result<Fence, Error> {
+        Err(Error::RequiredExtensionUnavailable)
+    }
+
+    /// Fences can't be created on this backend, so this can't be called.
+    pub fn client_wait_fence(&self, fence: &Fence, _: Option<Duration>) -> Result<bool, Error> {
+        match *fence {}
+    }
+
+    /// Fences can't be created on this backend, so this can't be called.
+    pub fn server_wait_fence(&self, _: &Context, fence: &Fence) -> Result<(), Error> {
+        match *fence {}
+    }
+
+    /// Fences can't be created on this backend, so this can't be called.
+    pub fn destroy_fence(&self, fence: &mut Fence) -> Result<(), Error> {
+        match *fence {}
+    }
+
     /// Fetches the address of an OpenGL function associated with this context.
     ///
     /// OpenGL functions are local to a context. You should not use OpenGL functions on one context
@@ -691,6 +750,133 @@ impl Device {
         SURFACE_GL_TEXTURE_TARGET
     }
 
+    /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
+    ///
+    /// The surface must have been created with CPU access, or a `SurfaceDataInaccessible` error
+    /// is returned.
+    #[inline]
+    pub fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Surface,
+    ) -> Result<SurfaceDataGuard<'s>, Error> {
+        self.0.lock_surface_data(&mut surface.system_surface)
+    }
+
+    /// Reads back a rectangle of pixels from a generic surface.
+    ///
+    /// The rectangle is relative to the top left corner of the surface, and the returned rows are
+    /// ordered from top to bottom in the requested pixel format.
+    ///
+    /// The supplied context must be the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned.
+    pub fn read_surface_pixels(
+        &self,
+        context: &mut Context,
+        surface: &Surface,
+        rect: Rect<i32>,
+        format: PixelFormat,
+    ) -> Result<Vec<u8>, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        gl_utils::read_framebuffer_pixels(
+            &context.gl,
+            surface.framebuffer_object,
+            surface.system_surface.size,
+            rect,
+            format,
+        )
+    }
+
+    /// Starts copying the contents of a generic surface into CPU-visible memory.
+    ///
+    /// This does not wait for the GPU. Use `poll_surface_readback()` to check whether the copy
+    /// has finished and `map_surface_readback()` to retrieve the pixels.
+    ///
+    /// The supplied context must be the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned.
+    pub fn begin_surface_readback(
+        &self,
+        context: &mut Context,
+        surface: &Surface,
+    ) -> Result<ReadbackHandle, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        ReadbackHandle::new(
+            &context.gl,
+            context.id,
+            surface.framebuffer_object,
+            surface.system_surface.size,
+        )
+    }
+
+    /// Returns true if the GPU has finished a readback, so that `map_surface_readback()` will not
+    /// block.
+    ///
+    /// The supplied context must be the context the readback was started with, or an
+    /// `IncompatibleContext` error is returned.
+    pub fn poll_surface_readback(
+        &self,
+        context: &Context,
+        readback: &ReadbackHandle,
+    ) -> Result<bool, Error> {
+        if readback.context_id != context.id {
+            return Err(Error::IncompatibleContext);
+        }
+        let _guard = self.temporarily_make_context_current(context)?;
+        Ok(readback.poll(&context.gl))
+    }
+
+    /// Returns the pixels of a readback in RGBA format, with rows ordered from top to bottom, and
+    /// frees the readback.
+    ///
+    /// If the GPU has not yet finished the copy, this waits for it. The supplied context must be
+    /// the context the readback was started with, or an `IncompatibleContext` error is returned.
+    /// On failure, the readback is returned alongside the error, so that it can be mapped again
+    /// or destroyed.
+    pub fn map_surface_readback(
+        &self,
+        context: &mut Context,
+        readback: ReadbackHandle,
+    ) -> Result<Vec<u8>, (Error, ReadbackHandle)> {
+        if readback.context_id != context.id {
+            return Err((Error::IncompatibleContext, readback));
+        }
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, readback)),
+        };
+        readback.map(&context.gl)
+    }
+
+    /// Frees a readback without mapping it.
+    ///
+    /// The supplied context must be the context the readback was started with, or an
+    /// `IncompatibleContext` error is returned alongside the readback.
+    ///
+    /// You must call either this method or `map_surface_readback()` to dispose of a readback.
+    /// Otherwise, a panic occurs in the `drop` method.
+    pub fn destroy_surface_readback(
+        &self,
+        context: &mut Context,
+        mut readback: ReadbackHandle,
+    ) -> Result<(), (Error, ReadbackHandle)> {
+        if readback.context_id != context.id {
+            return Err((Error::IncompatibleContext, readback));
+        }
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, readback)),
+        };
+        readback.destroy(&context.gl);
+        Ok(())
+    }
+
     /// Displays the contents of a widget surface on screen.
     ///
     /// Widget surfaces are internally double-buffered, so changes to them don't show up in their
@@ -704,6 +890,41 @@ impl Device {
         Ok(())
     }
 
+    /// Displays the contents of a widget surface on screen.
+    ///
+    /// This backend can't present only part of a surface, so the damaged rectangles are ignored
+    /// and the whole surface is presented like `present_surface()`.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        _: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        self.present_surface(context, surface)
+    }
+
+    /// Returns `Unimplemented`, since this backend can't query the age of a surface's back
+    /// buffer.
+    pub fn surface_buffer_age(&self, _: &Context, _: &Surface) -> Result<u32, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Returns `Unimplemented`, since this backend can't limit rendering to part of a surface.
+    /// The whole surface can be rendered to as usual.
+    pub fn set_damage_region(
+        &self,
+        _: &Context,
+        _: &mut Surface,
+        _: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Returns `Unimplemented`, since this backend doesn't support changing the swap interval.
+    pub fn set_swap_interval(&self, _: &Context, _: &Surface, _: i32) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Resizes a widget surface.
     pub fn resize_surface(
         &self,
@@ -795,9 +1016,12 @@ impl Device {
         let system_surface_info = self.0.surface_info(&surface.system_surface);
         SurfaceInfo {
             size: system_surface_info.size,
+            valid_size: system_surface_info.size,
             id: system_surface_info.id,
             context_id: surface.context_id,
             framebuffer_object: surface.framebuffer_object,
+            format: Some(SurfaceFormat::BGRA8),
+            depth_format: surface.renderbuffers.depth_format(),
         }
     }
 
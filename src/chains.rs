@@ -23,18 +23,27 @@
 #![allow(missing_docs)]
 
 use crate::device::Device as DeviceAPI;
-use crate::{ContextID, Error, SurfaceAccess, SurfaceInfo, SurfaceType};
+use crate::{ContextID, DepthFormat, Error, SurfaceAccess, SurfaceFormat, SurfaceID};
+use crate::{SurfaceInfo, SurfaceType};
 use euclid::default::Size2D;
 use fnv::{FnvHashMap, FnvHashSet};
+use futures_core::Stream;
 use glow as gl;
 use glow::Context as Gl;
 use glow::HasContext;
 use log::debug;
+use std::any::Any;
 use std::collections::hash_map::Entry;
+use std::collections::VecDeque;
 use std::fmt::Debug;
+use std::future::Future;
 use std::hash::Hash;
 use std::mem;
-use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::task::{Context as TaskContext, Poll, Waker};
+use std::time::{Duration, Instant};
 
 // The data stored for each swap chain.
 struct SwapChainData<Device: DeviceAPI> {
@@ -46,10 +55,141 @@ struct SwapChainData<Device: DeviceAPI> {
     surface_access: SurfaceAccess,
     // The back buffer of the swap chain.
     back_buffer: BackBuffer<Device>,
-    // Some if the producing context has finished drawing a new front buffer, ready to be displayed.
-    pending_surface: Option<Device::Surface>,
-    // All of the surfaces that have already been displayed, ready to be recycled.
+    // The front buffers that the producing context has finished drawing, ready to be displayed,
+    // oldest first. There is at most one unless the present mode is FIFO.
+    pending_surfaces: VecDeque<PendingSurface<Device>>,
+    // Notified when a front buffer becomes pending, or the swap chain is destroyed.
+    pending_surface_condvar: Arc<Condvar>,
+    // Woken when a front buffer becomes pending, or the swap chain is destroyed.
+    pending_surface_wakers: Vec<Waker>,
+    // Notified when a front buffer becomes pending, if the swap chain belongs to a collection.
+    swap_chains_signal: Option<Arc<SwapChainsSignal>>,
+    // True once the swap chain has been destroyed.
+    destroyed: bool,
+    // All of the surfaces that have already been displayed, ready to be recycled,
+    // least recently recycled first.
     recycled_surfaces: Vec<Device::Surface>,
+    // The maximum estimated memory used by the recycled surfaces, in bytes, if any.
+    memory_budget: Option<usize>,
+    // The estimated memory used by the recycled surfaces.
+    // This is counted when the producer swaps buffers, and estimated in between
+    // using `front_buffer_memory` for surfaces that consumers recycle or take.
+    recycled_memory: usize,
+    // The estimated memory used by the most recent front buffer.
+    front_buffer_memory: usize,
+    // The memory used by all the swap chains, if the swap chain belongs to a collection.
+    swap_chains_memory: Option<Arc<SwapChainsMemory>>,
+    // What happens to front buffers that haven't been taken yet when the buffers are swapped.
+    present_mode: PresentMode,
+    // The maximum number of surfaces the swap chain may create, if any.
+    max_surfaces: Option<usize>,
+    // The number of surfaces created by the swap chain that haven't been destroyed yet,
+    // including any the consumers are holding on to.
+    surface_count: usize,
+    // The number of times the buffers have been swapped.
+    frame_count: u64,
+    // The frame whose contents each displayed surface holds, for computing buffer ages.
+    surface_frames: FnvHashMap<SurfaceID, u64>,
+    // The number of frames ago the contents of the back buffer were drawn, or 0 if unknown.
+    back_buffer_age: u32,
+    // Which surfaces may be reused for the current size.
+    resize_policy: ResizePolicy,
+    // The size of the valid contents of each surface that has been a front buffer.
+    surface_valid_sizes: FnvHashMap<SurfaceID, Size2D<i32>>,
+}
+
+// A front buffer waiting to be taken by a consumer.
+struct PendingSurface<Device: DeviceAPI> {
+    surface: Device::Surface,
+    // The frame the surface holds.
+    metadata: FrameMetadata,
+}
+
+/// Information about the frame held by a front buffer, attached when the buffers are swapped.
+#[derive(Debug)]
+pub struct FrameMetadata {
+    /// The number of times the swap chain's buffers had been swapped, including this time.
+    pub frame_number: u64,
+    /// When the buffers were swapped.
+    pub timestamp: Instant,
+    /// The size of the valid contents, in the bottom left corner of the surface.
+    /// This is smaller than the surface if the resize policy is tolerant,
+    /// and is the same as the `valid_size` reported by `SwapChain::surface_info()`.
+    pub valid_size: Size2D<i32>,
+    /// The data passed to `SwapChain::swap_buffers_with_user_data()`, if any.
+    pub user_data: Option<Box<dyn Any + Send>>,
+}
+
+impl FrameMetadata {
+    /// Get the user data, if there is some of type `T`.
+    pub fn user_data<T: Any>(&self) -> Option<&T> {
+        self.user_data.as_ref()?.downcast_ref()
+    }
+}
+
+/// How a swap chain hands front buffers to its consumers.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PresentMode {
+    /// Only the most recent front buffer is kept. Swapping recycles any front buffer that
+    /// the consumer hasn't taken yet, so frames may be dropped but the producer never waits
+    /// for the consumer. If the surface limit is reached and no recycled surface is free,
+    /// `swap_buffers` returns `WouldBlock`.
+    #[default]
+    Mailbox,
+    /// Front buffers are queued, and taken by the consumer in the order they were swapped,
+    /// so no frames are dropped. If `queue_depth` front buffers are already waiting,
+    /// or the surface limit is reached and no recycled surface is free,
+    /// `swap_buffers` returns `WouldBlock` and the producer should try again later.
+    Fifo { queue_depth: usize },
+    /// Like `Mailbox`, except that swapping never fails because of the surface limit.
+    /// The swap chain creates a new surface if none is free, and destroys surplus
+    /// surfaces once they are recycled.
+    Immediate,
+}
+
+/// Which surfaces a swap chain may use as back buffers when it is resized.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ResizePolicy {
+    /// Surfaces are always exactly the size of the swap chain. Resizing creates a new
+    /// back buffer, and destroys all the recycled surfaces.
+    #[default]
+    Exact,
+    /// New surfaces are created with their width and height rounded up to a multiple of
+    /// `bucket_size`, and a surface may be reused as long as it is at least the size of
+    /// the swap chain, and at most `max_slack` pixels larger than the rounded size in each
+    /// dimension. The producer draws into the bottom left corner of a larger surface, and
+    /// consumers find the size of the valid contents with `SwapChain::surface_info()`.
+    Tolerant { bucket_size: i32, max_slack: i32 },
+}
+
+impl ResizePolicy {
+    // The size of the surfaces to create for a swap chain of the given size.
+    fn allocation_size(&self, size: Size2D<i32>) -> Size2D<i32> {
+        match *self {
+            ResizePolicy::Exact => size,
+            ResizePolicy::Tolerant { bucket_size, .. } => {
+                let round_up = |length: i32| match length % bucket_size {
+                    0 => length,
+                    remainder => length.saturating_add(bucket_size - remainder),
+                };
+                Size2D::new(round_up(size.width), round_up(size.height))
+            }
+        }
+    }
+
+    // Returns `true` if a surface of size `surface_size` can be used by a swap chain of size `size`.
+    fn fits(&self, surface_size: Size2D<i32>, size: Size2D<i32>) -> bool {
+        match *self {
+            ResizePolicy::Exact => surface_size == size,
+            ResizePolicy::Tolerant { max_slack, .. } => {
+                let max_size = self.allocation_size(size);
+                surface_size.width >= size.width
+                    && surface_size.height >= size.height
+                    && surface_size.width <= max_size.width.saturating_add(max_slack)
+                    && surface_size.height <= max_size.height.saturating_add(max_slack)
+            }
+        }
+    }
 }
 
 pub enum PreserveBuffer<'a> {
@@ -127,6 +267,13 @@ impl<Device: DeviceAPI> BackBuffer<Device> {
             .map_err(|(err, _)| err)?;
         self.replace_surface(device, context, surface)
     }
+    fn size(&self, device: &Device, context: &Device::Context) -> Result<Size2D<i32>, Error> {
+        match self {
+            BackBuffer::Attached => Ok(device.context_surface_info(context)?.unwrap().size),
+            BackBuffer::Detached(surface) => Ok(device.surface_info(surface).size),
+            _ => Err(Error::Failed),
+        }
+    }
 }
 
 impl<Device: DeviceAPI> SwapChainData<Device> {
@@ -147,37 +294,73 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
         device: &Device,
         context: &mut Device::Context,
         preserve_buffer: PreserveBuffer<'_>,
+        user_data: Option<Box<dyn Any + Send>>,
     ) -> Result<(), Error> {
         debug!("Swap buffers on context {:?}", self.context_id);
         self.validate_context(device, context)?;
 
-        // Recycle the old front buffer
-        if let Some(old_front_buffer) = self.pending_surface.take() {
-            let SurfaceInfo { id, size, .. } = device.surface_info(&old_front_buffer);
-            debug!(
-                "Recycling surface {:?} ({:?}) for context {:?}",
-                id, size, self.context_id
-            );
-            self.recycle_surface(old_front_buffer);
+        match self.present_mode {
+            PresentMode::Fifo { queue_depth } => {
+                if self.pending_surfaces.len() >= queue_depth {
+                    debug!(
+                        "Pending surfaces for context {:?} are full",
+                        self.context_id
+                    );
+                    return Err(Error::WouldBlock);
+                }
+            }
+            PresentMode::Mailbox | PresentMode::Immediate => {
+                // Recycle the old front buffer
+                while let Some(PendingSurface {
+                    surface: old_front_buffer,
+                    ..
+                }) = self.pending_surfaces.pop_front()
+                {
+                    let SurfaceInfo { id, size, .. } = device.surface_info(&old_front_buffer);
+                    debug!(
+                        "Recycling surface {:?} ({:?}) for context {:?}",
+                        id, size, self.context_id
+                    );
+                    self.recycle_surface(old_front_buffer);
+                }
+            }
         }
 
         // Fetch a new back buffer, recycling presented buffers if possible.
-        let new_back_buffer = self
-            .recycled_surfaces
-            .iter()
-            .position(|surface| device.surface_info(surface).size == self.size)
-            .map(|index| {
+        let recycled_index = self.recycled_surfaces.iter().rposition(|surface| {
+            let surface_size = device.surface_info(surface).size;
+            self.resize_policy.fits(surface_size, self.size)
+        });
+        let mut new_back_buffer_frame = None;
+        let new_back_buffer = match recycled_index {
+            Some(index) => {
                 debug!("Recycling surface for context {:?}", self.context_id);
-                Ok(self.recycled_surfaces.swap_remove(index))
-            })
-            .unwrap_or_else(|| {
+                let surface = self.recycled_surfaces.remove(index);
+                new_back_buffer_frame = self
+                    .surface_frames
+                    .remove(&device.surface_info(&surface).id);
+                surface
+            }
+            None => {
+                if !self.can_create_surface() {
+                    debug!(
+                        "Out of surfaces ({:?}) for context {:?}",
+                        self.surface_count, self.context_id
+                    );
+                    return Err(Error::WouldBlock);
+                }
                 debug!(
                     "Creating a new surface ({:?}) for context {:?}",
                     self.size, self.context_id
                 );
-                let surface_type = SurfaceType::Generic { size: self.size };
-                device.create_surface(context, self.surface_access, surface_type)
-            })?;
+                let size = self.resize_policy.allocation_size(self.size);
+                let surface_type = SurfaceType::Generic { size };
+                let surface = device.create_surface(context, self.surface_access, surface_type)?;
+                self.surface_count += 1;
+                surface
+            }
+        };
+        self.frame_count += 1;
 
         let back_info = device.surface_info(&new_back_buffer);
 
@@ -201,12 +384,12 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
                 gl.blit_framebuffer(
                     0,
                     0,
-                    front_info.size.width,
-                    front_info.size.height,
+                    self.size.width,
+                    self.size.height,
                     0,
                     0,
-                    back_info.size.width,
-                    back_info.size.height,
+                    self.size.width,
+                    self.size.height,
                     gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT,
                     gl::NEAREST,
                 );
@@ -215,12 +398,171 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
         }
 
         // Update the state
+        let front_info = device.surface_info(&new_front_buffer);
+        let front_id = front_info.id;
+        self.front_buffer_memory = surface_memory(&front_info);
         debug!(
             "Surface {:?} is the new front buffer for context {:?}",
-            device.surface_info(&new_front_buffer).id,
-            self.context_id
+            front_id, self.context_id
+        );
+        self.surface_frames.insert(front_id, self.frame_count);
+        self.surface_valid_sizes.insert(front_id, self.size);
+        self.back_buffer_age = match (preserve_buffer, new_back_buffer_frame) {
+            (PreserveBuffer::Yes(_), _) => 1,
+            (PreserveBuffer::No, Some(frame)) => {
+                u32::try_from(self.frame_count + 1 - frame).unwrap_or(0)
+            }
+            (PreserveBuffer::No, None) => 0,
+        };
+        self.pending_surfaces.push_back(PendingSurface {
+            surface: new_front_buffer,
+            metadata: FrameMetadata {
+                frame_number: self.frame_count,
+                timestamp: Instant::now(),
+                valid_size: self.size,
+                user_data,
+            },
+        });
+        self.pending_surface_condvar.notify_all();
+
+        self.evict_recycled_surfaces(device, context)
+    }
+
+    // Destroy the recycled surfaces that can't be reused because they are the wrong size,
+    // then the least recently recycled surfaces while over the surface limit or a memory budget.
+    // Called by the producer.
+    fn evict_recycled_surfaces(
+        &mut self,
+        device: &Device,
+        context: &mut Device::Context,
+    ) -> Result<(), Error> {
+        let mut index = 0;
+        while index < self.recycled_surfaces.len() {
+            let surface_size = device.surface_info(&self.recycled_surfaces[index]).size;
+            if self.resize_policy.fits(surface_size, self.size) {
+                index += 1;
+            } else {
+                self.destroy_recycled_surface(device, context, index)?;
+            }
+        }
+
+        let mut recycled_memory = self
+            .recycled_surfaces
+            .iter()
+            .map(|surface| surface_memory(&device.surface_info(surface)))
+            .sum();
+        self.set_recycled_memory(recycled_memory);
+        while !self.recycled_surfaces.is_empty()
+            && (self
+                .max_surfaces
+                .is_some_and(|max| self.surface_count > max)
+                || self
+                    .memory_budget
+                    .is_some_and(|budget| recycled_memory > budget)
+                || self
+                    .swap_chains_memory
+                    .as_ref()
+                    .is_some_and(|memory| memory.over_budget()))
+        {
+            recycled_memory -= surface_memory(&device.surface_info(&self.recycled_surfaces[0]));
+            self.destroy_recycled_surface(device, context, 0)?;
+            self.set_recycled_memory(recycled_memory);
+        }
+        Ok(())
+    }
+
+    // Destroy all the recycled surfaces.
+    // Called by the producer.
+    // Returns an error if `context` is not the producer context for this swap chain.
+    fn trim(&mut self, device: &Device, context: &mut Device::Context) -> Result<(), Error> {
+        self.validate_context(device, context)?;
+        while !self.recycled_surfaces.is_empty() {
+            self.destroy_recycled_surface(device, context, 0)?;
+        }
+        self.set_recycled_memory(0);
+        Ok(())
+    }
+
+    // Destroy one of the recycled surfaces.
+    // Called by the producer.
+    fn destroy_recycled_surface(
+        &mut self,
+        device: &Device,
+        context: &mut Device::Context,
+        index: usize,
+    ) -> Result<(), Error> {
+        let mut surface = self.recycled_surfaces.remove(index);
+        let SurfaceInfo { id, size, .. } = device.surface_info(&surface);
+        debug!(
+            "Destroying recycled surface {:?} ({:?}) for context {:?}",
+            id, size, self.context_id
         );
-        self.pending_surface = Some(new_front_buffer);
+        self.surface_frames.remove(&id);
+        self.surface_valid_sizes.remove(&id);
+        self.surface_count = self.surface_count.saturating_sub(1);
+        device.destroy_surface(context, &mut surface)
+    }
+
+    // Record the memory used by the recycled surfaces,
+    // including in the total for the collection.
+    fn set_recycled_memory(&mut self, recycled_memory: usize) {
+        if let Some(ref swap_chains_memory) = self.swap_chains_memory {
+            swap_chains_memory
+                .recycled
+                .fetch_add(recycled_memory, Ordering::Relaxed);
+            swap_chains_memory
+                .recycled
+                .fetch_sub(self.recycled_memory, Ordering::Relaxed);
+        }
+        self.recycled_memory = recycled_memory;
+    }
+
+    // Set the memory budget for the recycled surfaces.
+    fn set_memory_budget(&mut self, memory_budget: Option<usize>) {
+        self.memory_budget = memory_budget;
+    }
+
+    // Returns `true` if a new surface can be created without going over the surface limit.
+    fn can_create_surface(&self) -> bool {
+        match (self.present_mode, self.max_surfaces) {
+            (PresentMode::Immediate, _) | (_, None) => true,
+            (_, Some(max_surfaces)) => self.surface_count < max_surfaces,
+        }
+    }
+
+    // Set the present mode.
+    // Returns an error if the mode is FIFO with a queue depth of 0.
+    fn set_present_mode(&mut self, present_mode: PresentMode) -> Result<(), Error> {
+        if present_mode == (PresentMode::Fifo { queue_depth: 0 }) {
+            return Err(Error::Failed);
+        }
+        self.present_mode = present_mode;
+        Ok(())
+    }
+
+    // Set the resize policy.
+    // Returns an error if the bucket size is smaller than 1, or the slack is negative.
+    fn set_resize_policy(&mut self, resize_policy: ResizePolicy) -> Result<(), Error> {
+        if let ResizePolicy::Tolerant {
+            bucket_size,
+            max_slack,
+        } = resize_policy
+        {
+            if bucket_size < 1 || max_slack < 0 {
+                return Err(Error::Failed);
+            }
+        }
+        self.resize_policy = resize_policy;
+        Ok(())
+    }
+
+    // Set the maximum number of surfaces.
+    // Returns an error if the limit is smaller than 2, which is the back buffer and one front buffer.
+    fn set_max_surfaces(&mut self, max_surfaces: Option<usize>) -> Result<(), Error> {
+        if max_surfaces.is_some_and(|max_surfaces| max_surfaces < 2) {
+            return Err(Error::Failed);
+        }
+        self.max_surfaces = max_surfaces;
         Ok(())
     }
 
@@ -250,6 +592,8 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
     // Resize the swap chain.
     // This creates a new back buffer of the appropriate size,
     // and destroys the old one.
+    // If the resize policy is tolerant, the back buffer is kept or replaced
+    // by a recycled surface instead, if one fits.
     // Called by the producer.
     // Returns an error if `context` is not the producer context for this swap chain.
     // Returns an error if `size` is smaller than (1, 1).
@@ -268,6 +612,9 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
         if (size.width < 1) || (size.height < 1) {
             return Err(Error::Failed);
         }
+        if let ResizePolicy::Tolerant { .. } = self.resize_policy {
+            return self.resize_tolerant(device, context, size);
+        }
         let surface_type = SurfaceType::Generic { size };
         let new_back_buffer = device.create_surface(context, self.surface_access, surface_type)?;
         let mut old_back_buffer = self.back_buffer.take_surface(device, context)?;
@@ -275,18 +622,66 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
             .replace_surface(device, context, new_back_buffer)?;
         device.destroy_surface(context, &mut old_back_buffer)?;
         for mut surface in self.recycled_surfaces.drain(..) {
+            self.surface_count = self.surface_count.saturating_sub(1);
             device.destroy_surface(context, &mut surface)?;
         }
+        self.set_recycled_memory(0);
+        self.surface_frames.clear();
+        self.surface_valid_sizes.clear();
+        self.back_buffer_age = 0;
         self.size = size;
         Ok(())
     }
 
+    // Resize the swap chain, reusing surfaces that fit the new size.
+    // Called by the producer.
+    fn resize_tolerant(
+        &mut self,
+        device: &Device,
+        context: &mut Device::Context,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        let back_buffer_size = self.back_buffer.size(device, context)?;
+        if !self.resize_policy.fits(back_buffer_size, size) {
+            let recycled_index = self.recycled_surfaces.iter().rposition(|surface| {
+                let surface_size = device.surface_info(surface).size;
+                self.resize_policy.fits(surface_size, size)
+            });
+            let new_back_buffer = match recycled_index {
+                Some(index) => self.recycled_surfaces.remove(index),
+                None => {
+                    let size = self.resize_policy.allocation_size(size);
+                    let surface_type = SurfaceType::Generic { size };
+                    let surface =
+                        device.create_surface(context, self.surface_access, surface_type)?;
+                    self.surface_count += 1;
+                    surface
+                }
+            };
+            let mut old_back_buffer = self.back_buffer.take_surface(device, context)?;
+            self.back_buffer
+                .replace_surface(device, context, new_back_buffer)?;
+            self.surface_count = self.surface_count.saturating_sub(1);
+            device.destroy_surface(context, &mut old_back_buffer)?;
+        }
+        self.surface_frames.clear();
+        self.back_buffer_age = 0;
+        self.size = size;
+        self.evict_recycled_surfaces(device, context)
+    }
+
     // Get the current size.
     // Called by a consumer.
     fn size(&self) -> Size2D<i32> {
         self.size
     }
 
+    // Get the age of the current back buffer.
+    // Called by the producer.
+    fn back_buffer_age(&self) -> u32 {
+        self.back_buffer_age
+    }
+
     // Take the current back buffer.
     // Called by a producer.
     fn take_surface_texture(
@@ -315,22 +710,59 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
     // Returns the most recent recycled surface if there is no current front buffer.
     // Called by a consumer.
     fn take_surface(&mut self) -> Option<Device::Surface> {
-        self.pending_surface
-            .take()
-            .or_else(|| self.recycled_surfaces.pop())
+        self.take_surface_with_metadata()
+            .map(|(surface, _)| surface)
+    }
+
+    // Take the current front buffer and its metadata.
+    // Returns the most recent recycled surface, without metadata,
+    // if there is no current front buffer.
+    // Called by a consumer.
+    fn take_surface_with_metadata(&mut self) -> Option<(Device::Surface, Option<FrameMetadata>)> {
+        if let Some(PendingSurface { surface, metadata }) = self.pending_surfaces.pop_front() {
+            return Some((surface, Some(metadata)));
+        }
+        let surface = self.recycled_surfaces.pop()?;
+        let recycled_memory = self
+            .recycled_memory
+            .saturating_sub(self.front_buffer_memory);
+        self.set_recycled_memory(recycled_memory);
+        Some((surface, None))
     }
 
     // Take the current front buffer.
     // Returns `None` if there is no current front buffer.
     // Called by a consumer.
     fn take_pending_surface(&mut self) -> Option<Device::Surface> {
-        self.pending_surface.take()
+        self.take_pending_surface_with_metadata()
+            .map(|(surface, _)| surface)
+    }
+
+    // Take the current front buffer and its metadata.
+    // Returns `None` if there is no current front buffer.
+    // Called by a consumer.
+    fn take_pending_surface_with_metadata(&mut self) -> Option<(Device::Surface, FrameMetadata)> {
+        self.pending_surfaces
+            .pop_front()
+            .map(|PendingSurface { surface, metadata }| (surface, metadata))
+    }
+
+    // When the oldest front buffer was swapped, if there is one.
+    // Called by a consumer.
+    fn oldest_pending_swap(&self) -> Option<Instant> {
+        self.pending_surfaces
+            .front()
+            .map(|pending_surface| pending_surface.metadata.timestamp)
     }
 
     // Recycle the current front buffer.
+    // Its memory is estimated to be that of the most recent front buffer,
+    // until the producer next counts it.
     // Called by a consumer.
     fn recycle_surface(&mut self, surface: Device::Surface) {
-        self.recycled_surfaces.push(surface)
+        self.recycled_surfaces.push(surface);
+        let recycled_memory = self.recycled_memory + self.front_buffer_memory;
+        self.set_recycled_memory(recycled_memory);
     }
 
     // Clear the current back buffer.
@@ -415,6 +847,8 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
                 .replace_surface(device, context, old_surface)?;
         }
 
+        self.back_buffer_age = 0;
+
         // Restore the GL state
         unsafe {
             gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, draw_fbo);
@@ -455,18 +889,102 @@ impl<Device: DeviceAPI> SwapChainData<Device> {
     fn destroy(&mut self, device: &Device, context: &mut Device::Context) -> Result<(), Error> {
         self.validate_context(device, context)?;
         let surfaces = self
-            .pending_surface
-            .take()
-            .into_iter()
+            .pending_surfaces
+            .drain(..)
+            .map(|pending_surface| pending_surface.surface)
             .chain(self.back_buffer.take_surface(device, context).into_iter())
             .chain(self.recycled_surfaces.drain(..));
         for mut surface in surfaces {
             device.destroy_surface(context, &mut surface)?;
         }
+        self.set_recycled_memory(0);
+        self.surface_valid_sizes.clear();
+        self.surface_count = 0;
+        self.destroyed = true;
+        self.pending_surface_condvar.notify_all();
         Ok(())
     }
 }
 
+// Estimate the memory used by a surface, in bytes.
+fn surface_memory(info: &SurfaceInfo) -> usize {
+    let color_bytes = match info.format {
+        Some(SurfaceFormat::RGBA16F) => 8,
+        Some(SurfaceFormat::RGBA8)
+        | Some(SurfaceFormat::BGRA8)
+        | Some(SurfaceFormat::SRGBA8)
+        | Some(SurfaceFormat::RGB10A2)
+        | None => 4,
+    };
+    let depth_bytes = match info.depth_format {
+        None => 0,
+        Some(DepthFormat::Depth16) => 2,
+        Some(DepthFormat::Depth24) | Some(DepthFormat::Depth32F) => 4,
+    };
+    let pixels = info.size.width.max(0) as usize * info.size.height.max(0) as usize;
+    pixels * (color_bytes + depth_bytes)
+}
+
+// The memory used by the recycled surfaces of all the swap chains in a collection.
+struct SwapChainsMemory {
+    // The estimated memory used by the recycled surfaces, in bytes.
+    recycled: AtomicUsize,
+    // The maximum estimated memory used by the recycled surfaces, or `usize::MAX` if there is none.
+    budget: AtomicUsize,
+}
+
+impl Default for SwapChainsMemory {
+    fn default() -> SwapChainsMemory {
+        SwapChainsMemory {
+            recycled: AtomicUsize::new(0),
+            budget: AtomicUsize::new(usize::MAX),
+        }
+    }
+}
+
+impl SwapChainsMemory {
+    fn over_budget(&self) -> bool {
+        self.recycled.load(Ordering::Relaxed) > self.budget.load(Ordering::Relaxed)
+    }
+}
+
+// Wakes up consumers waiting for any swap chain in a collection to have a front buffer.
+#[derive(Default)]
+struct SwapChainsSignal {
+    mutex: Mutex<()>,
+    condvar: Condvar,
+}
+
+impl SwapChainsSignal {
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        self.mutex.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn notify(&self) {
+        let _guard = self.lock();
+        self.condvar.notify_all();
+    }
+}
+
+// Wait on a condition variable until it is notified, or until the deadline has passed.
+// Returns `None` if the deadline has passed.
+fn wait_until<'a, T>(
+    condvar: &Condvar,
+    guard: MutexGuard<'a, T>,
+    deadline: Option<Instant>,
+) -> Option<MutexGuard<'a, T>> {
+    match deadline {
+        None => Some(condvar.wait(guard).unwrap_or_else(|err| err.into_inner())),
+        Some(deadline) => {
+            let timeout = deadline.checked_duration_since(Instant::now())?;
+            let (guard, _) = condvar
+                .wait_timeout(guard, timeout)
+                .unwrap_or_else(|err| err.into_inner());
+            Some(guard)
+        }
+    }
+}
+
 /// A thread-safe swap chain.
 pub struct SwapChain<Device: DeviceAPI>(Arc<Mutex<SwapChainData<Device>>>);
 
@@ -492,7 +1010,43 @@ impl<Device: DeviceAPI> SwapChain<Device> {
         context: &mut Device::Context,
         preserve_buffer: PreserveBuffer<'_>,
     ) -> Result<(), Error> {
-        self.lock().swap_buffers(device, context, preserve_buffer)
+        self.swap_buffers_impl(device, context, preserve_buffer, None)
+    }
+
+    /// Swap the back and front buffers, attaching some data to the new front buffer,
+    /// which consumers get back from `take_surface_with_metadata()`.
+    /// Called by the producer.
+    /// Returns an error if `context` is not the producer context for this swap chain.
+    pub fn swap_buffers_with_user_data<T: Any + Send>(
+        &self,
+        device: &Device,
+        context: &mut Device::Context,
+        preserve_buffer: PreserveBuffer<'_>,
+        user_data: T,
+    ) -> Result<(), Error> {
+        self.swap_buffers_impl(device, context, preserve_buffer, Some(Box::new(user_data)))
+    }
+
+    fn swap_buffers_impl(
+        &self,
+        device: &Device,
+        context: &mut Device::Context,
+        preserve_buffer: PreserveBuffer<'_>,
+        user_data: Option<Box<dyn Any + Send>>,
+    ) -> Result<(), Error> {
+        // Signal the collection and wake any futures after unlocking the swap chain,
+        // since waiting on the collection and polling futures lock the swap chains.
+        let (swap_chains_signal, wakers) = {
+            let mut data = self.lock();
+            data.swap_buffers(device, context, preserve_buffer, user_data)?;
+            let wakers = mem::take(&mut data.pending_surface_wakers);
+            (data.swap_chains_signal.clone(), wakers)
+        };
+        if let Some(swap_chains_signal) = swap_chains_signal {
+            swap_chains_signal.notify();
+        }
+        wakers.into_iter().for_each(Waker::wake);
+        Ok(())
     }
 
     /// Swap the attached swap chain.
@@ -512,6 +1066,8 @@ impl<Device: DeviceAPI> SwapChain<Device> {
     /// Resize the swap chain.
     /// This creates a new back buffer of the appropriate size,
     /// and destroys the old one.
+    /// If the resize policy is tolerant, the back buffer is kept, or replaced by a recycled
+    /// surface, if it fits the new size.
     /// Called by the producer.
     /// Returns an error if `context` is not the producer context for this swap chain.
     pub fn resize(
@@ -529,6 +1085,82 @@ impl<Device: DeviceAPI> SwapChain<Device> {
         self.lock().size()
     }
 
+    /// Set how front buffers are handed to consumers.
+    /// Returns an error if the mode is FIFO with a queue depth of 0.
+    pub fn set_present_mode(&self, present_mode: PresentMode) -> Result<(), Error> {
+        self.lock().set_present_mode(present_mode)
+    }
+
+    /// Get how front buffers are handed to consumers.
+    pub fn present_mode(&self) -> PresentMode {
+        self.lock().present_mode
+    }
+
+    /// Set which surfaces may be used as back buffers when the swap chain is resized.
+    /// Returns an error if the policy is tolerant, with a bucket size smaller than 1,
+    /// or a negative slack.
+    pub fn set_resize_policy(&self, resize_policy: ResizePolicy) -> Result<(), Error> {
+        self.lock().set_resize_policy(resize_policy)
+    }
+
+    /// Get which surfaces may be used as back buffers when the swap chain is resized.
+    pub fn resize_policy(&self) -> ResizePolicy {
+        self.lock().resize_policy
+    }
+
+    /// Get information about a surface taken from this swap chain,
+    /// including the size of its valid contents, which may be smaller than the surface
+    /// if the resize policy is tolerant.
+    /// Called by a consumer.
+    pub fn surface_info(&self, device: &Device, surface: &Device::Surface) -> SurfaceInfo {
+        let mut surface_info = device.surface_info(surface);
+        if let Some(valid_size) = self.lock().surface_valid_sizes.get(&surface_info.id) {
+            surface_info.valid_size = *valid_size;
+        }
+        surface_info
+    }
+
+    /// Set the maximum number of surfaces the swap chain may create,
+    /// including the back buffer, and any surfaces taken by consumers.
+    /// `None` means there is no limit, which is the default.
+    /// Surplus surfaces are destroyed as they are recycled.
+    /// Returns an error if the limit is smaller than 2.
+    pub fn set_max_surfaces(&self, max_surfaces: Option<usize>) -> Result<(), Error> {
+        self.lock().set_max_surfaces(max_surfaces)
+    }
+
+    /// Set the maximum estimated memory, in bytes, used by the surfaces waiting to be recycled.
+    /// `None` means there is no limit, which is the default.
+    /// When the producer swaps buffers, it destroys the least recently recycled surfaces
+    /// until they fit in the budget.
+    pub fn set_memory_budget(&self, memory_budget: Option<usize>) {
+        self.lock().set_memory_budget(memory_budget)
+    }
+
+    /// Get the estimated memory, in bytes, used by the surfaces waiting to be recycled.
+    pub fn recycled_memory(&self, device: &Device) -> usize {
+        self.lock()
+            .recycled_surfaces
+            .iter()
+            .map(|surface| surface_memory(&device.surface_info(surface)))
+            .sum()
+    }
+
+    /// Destroy all the surfaces waiting to be recycled, for example when memory is low.
+    /// Called by the producer.
+    /// Returns an error if `context` is not the producer context for this swap chain.
+    pub fn trim(&self, device: &Device, context: &mut Device::Context) -> Result<(), Error> {
+        self.lock().trim(device, context)
+    }
+
+    /// Get the number of frames ago that the contents of the current back buffer were drawn,
+    /// like `Device::surface_buffer_age()` does for widget surfaces.
+    /// Returns 0 if the contents are undefined, for example after a resize or a clear.
+    /// Called by the producer.
+    pub fn back_buffer_age(&self) -> u32 {
+        self.lock().back_buffer_age()
+    }
+
     /// Take the current back buffer.
     /// Called by a producer.
     pub fn take_surface_texture(
@@ -558,6 +1190,72 @@ impl<Device: DeviceAPI> SwapChain<Device> {
         self.lock().take_pending_surface()
     }
 
+    /// Take the current front buffer and its metadata.
+    /// Returns `None` if there is no current front buffer.
+    /// Called by a consumer.
+    pub fn take_pending_surface_with_metadata(&self) -> Option<(Device::Surface, FrameMetadata)> {
+        self.lock().take_pending_surface_with_metadata()
+    }
+
+    /// Take the current front buffer and its metadata, waiting for the producer to swap buffers if there isn't one.
+    /// `None` waits forever.
+    /// Returns `None` if the timeout expires first, or the swap chain is destroyed.
+    /// Called by a consumer.
+    pub fn wait_for_surface(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Option<(Device::Surface, FrameMetadata)> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut data = self.lock();
+        loop {
+            if let Some(result) = data.take_pending_surface_with_metadata() {
+                return Some(result);
+            }
+            if data.destroyed {
+                return None;
+            }
+            let condvar = data.pending_surface_condvar.clone();
+            data = wait_until(&condvar, data, deadline)?;
+        }
+    }
+
+    /// Get a future that resolves to the next front buffer and its metadata.
+    /// It resolves to `None` if the swap chain is destroyed first.
+    /// Called by a consumer.
+    pub fn next_surface(&self) -> NextSurface<Device> {
+        NextSurface(self.clone())
+    }
+
+    /// Get a stream of front buffers and their metadata, which ends when the swap chain is destroyed.
+    /// Called by a consumer.
+    pub fn surfaces(&self) -> SurfaceStream<Device> {
+        SurfaceStream(self.clone())
+    }
+
+    // Take the current front buffer and its metadata,
+    // or arrange for the task to be woken when there is one.
+    fn poll_pending_surface(
+        &self,
+        task_context: &TaskContext,
+    ) -> Poll<Option<(Device::Surface, FrameMetadata)>> {
+        let mut data = self.lock();
+        if let Some(result) = data.take_pending_surface_with_metadata() {
+            return Poll::Ready(Some(result));
+        }
+        if data.destroyed {
+            return Poll::Ready(None);
+        }
+        let waker = task_context.waker();
+        if !data
+            .pending_surface_wakers
+            .iter()
+            .any(|pending_surface_waker| pending_surface_waker.will_wake(waker))
+        {
+            data.pending_surface_wakers.push(waker.clone());
+        }
+        Poll::Pending
+    }
+
     /// Clear the current back buffer.
     /// Called by the producer.
     /// Returns an error if `context` is not the producer context for this swap chain.
@@ -580,7 +1278,13 @@ impl<Device: DeviceAPI> SwapChain<Device> {
     /// Called by the producer.
     /// Returns an error if `context` is not the producer context for this swap chain.
     pub fn destroy(&self, device: &Device, context: &mut Device::Context) -> Result<(), Error> {
-        self.lock().destroy(device, context)
+        let wakers = {
+            let mut data = self.lock();
+            data.destroy(device, context)?;
+            mem::take(&mut data.pending_surface_wakers)
+        };
+        wakers.into_iter().for_each(Waker::wake);
+        Ok(())
     }
 
     /// Create a new attached swap chain
@@ -595,8 +1299,24 @@ impl<Device: DeviceAPI> SwapChain<Device> {
             context_id: device.context_id(context),
             surface_access,
             back_buffer: BackBuffer::Attached,
-            pending_surface: None,
+            pending_surfaces: VecDeque::new(),
+            pending_surface_condvar: Arc::new(Condvar::new()),
+            pending_surface_wakers: Vec::new(),
+            swap_chains_signal: None,
+            destroyed: false,
             recycled_surfaces: Vec::new(),
+            memory_budget: None,
+            recycled_memory: 0,
+            front_buffer_memory: 0,
+            swap_chains_memory: None,
+            present_mode: PresentMode::default(),
+            max_surfaces: None,
+            surface_count: 1,
+            frame_count: 0,
+            surface_frames: FnvHashMap::default(),
+            back_buffer_age: 0,
+            resize_policy: ResizePolicy::default(),
+            surface_valid_sizes: FnvHashMap::default(),
         }))))
     }
 
@@ -614,8 +1334,24 @@ impl<Device: DeviceAPI> SwapChain<Device> {
             context_id: device.context_id(context),
             surface_access,
             back_buffer: BackBuffer::Detached(surface),
-            pending_surface: None,
+            pending_surfaces: VecDeque::new(),
+            pending_surface_condvar: Arc::new(Condvar::new()),
+            pending_surface_wakers: Vec::new(),
+            swap_chains_signal: None,
+            destroyed: false,
             recycled_surfaces: Vec::new(),
+            memory_budget: None,
+            recycled_memory: 0,
+            front_buffer_memory: 0,
+            swap_chains_memory: None,
+            present_mode: PresentMode::default(),
+            max_surfaces: None,
+            surface_count: 1,
+            frame_count: 0,
+            surface_frames: FnvHashMap::default(),
+            back_buffer_age: 0,
+            resize_policy: ResizePolicy::default(),
+            surface_valid_sizes: FnvHashMap::default(),
         }))))
     }
 }
@@ -634,6 +1370,14 @@ where
         self.lock().take_surface()
     }
 
+    /// Take the current front buffer, and the metadata attached when it was swapped.
+    /// Returns the most recent recycled surface, without metadata,
+    /// if there is no current front buffer.
+    /// Called by a consumer.
+    fn take_surface_with_metadata(&self) -> Option<(Device::Surface, Option<FrameMetadata>)> {
+        self.lock().take_surface_with_metadata()
+    }
+
     /// Recycle the current front buffer.
     /// Called by a consumer.
     fn recycle_surface(&self, surface: Device::Surface) {
@@ -641,6 +1385,28 @@ where
     }
 }
 
+/// A future that resolves to the next front buffer of a swap chain and its metadata.
+pub struct NextSurface<Device: DeviceAPI>(SwapChain<Device>);
+
+impl<Device: DeviceAPI> Future for NextSurface<Device> {
+    type Output = Option<(Device::Surface, FrameMetadata)>;
+
+    fn poll(self: Pin<&mut Self>, task_context: &mut TaskContext) -> Poll<Self::Output> {
+        self.0.poll_pending_surface(task_context)
+    }
+}
+
+/// A stream of the front buffers of a swap chain and their metadata.
+pub struct SurfaceStream<Device: DeviceAPI>(SwapChain<Device>);
+
+impl<Device: DeviceAPI> Stream for SurfaceStream<Device> {
+    type Item = (Device::Surface, FrameMetadata);
+
+    fn poll_next(self: Pin<&mut Self>, task_context: &mut TaskContext) -> Poll<Option<Self::Item>> {
+        self.0.poll_pending_surface(task_context)
+    }
+}
+
 /// A thread-safe collection of swap chains.
 #[derive(Default)]
 pub struct SwapChains<SwapChainID: Eq + Hash, Device: DeviceAPI> {
@@ -648,6 +1414,10 @@ pub struct SwapChains<SwapChainID: Eq + Hash, Device: DeviceAPI> {
     ids: Arc<Mutex<FnvHashMap<ContextID, FnvHashSet<SwapChainID>>>>,
     // The swap chains, indexed by swap chain id
     table: Arc<RwLock<FnvHashMap<SwapChainID, SwapChain<Device>>>>,
+    // Notified when any of the swap chains has a new front buffer
+    signal: Arc<SwapChainsSignal>,
+    // The memory used by the swap chains
+    memory: Arc<SwapChainsMemory>,
 }
 
 // We can't derive Clone unfortunately
@@ -656,6 +1426,8 @@ impl<SwapChainID: Eq + Hash, Device: DeviceAPI> Clone for SwapChains<SwapChainID
         SwapChains {
             ids: self.ids.clone(),
             table: self.table.clone(),
+            signal: self.signal.clone(),
+            memory: self.memory.clone(),
         }
     }
 }
@@ -670,6 +1442,8 @@ where
         SwapChains {
             ids: Arc::new(Mutex::new(FnvHashMap::default())),
             table: Arc::new(RwLock::new(FnvHashMap::default())),
+            signal: Arc::new(SwapChainsSignal::default()),
+            memory: Arc::new(SwapChainsMemory::default()),
         }
     }
 
@@ -700,7 +1474,9 @@ where
         match self.table_mut().entry(id.clone()) {
             Entry::Occupied(_) => Err(Error::Failed)?,
             Entry::Vacant(entry) => {
-                entry.insert(SwapChain::create_attached(device, context, surface_access)?)
+                let swap_chain = SwapChain::create_attached(device, context, surface_access)?;
+                self.join(&swap_chain);
+                entry.insert(swap_chain)
             }
         };
         self.ids()
@@ -722,12 +1498,11 @@ where
     ) -> Result<(), Error> {
         match self.table_mut().entry(id.clone()) {
             Entry::Occupied(_) => Err(Error::Failed)?,
-            Entry::Vacant(entry) => entry.insert(SwapChain::create_detached(
-                device,
-                context,
-                surface_access,
-                size,
-            )?),
+            Entry::Vacant(entry) => {
+                let swap_chain = SwapChain::create_detached(device, context, surface_access, size)?;
+                self.join(&swap_chain);
+                entry.insert(swap_chain)
+            }
         };
         self.ids()
             .entry(device.context_id(context))
@@ -754,6 +1529,86 @@ where
         Ok(())
     }
 
+    // Make a new swap chain part of the collection.
+    fn join(&self, swap_chain: &SwapChain<Device>) {
+        let mut data = swap_chain.lock();
+        data.swap_chains_signal = Some(self.signal.clone());
+        data.swap_chains_memory = Some(self.memory.clone());
+    }
+
+    /// Set the maximum estimated memory, in bytes, used by the surfaces waiting to be recycled
+    /// by all the swap chains.
+    /// `None` means there is no limit, which is the default.
+    /// While over budget, swap chains destroy their least recently recycled surfaces
+    /// when their producer swaps buffers.
+    /// Each swap chain only destroys its own surfaces, so surfaces waiting to be recycled
+    /// by swap chains whose producers aren't swapping buffers are kept until they do,
+    /// or until `trim()` is called.
+    pub fn set_memory_budget(&self, memory_budget: Option<usize>) {
+        self.memory
+            .budget
+            .store(memory_budget.unwrap_or(usize::MAX), Ordering::Relaxed);
+    }
+
+    /// Get the estimated memory, in bytes, used by the surfaces waiting to be recycled
+    /// by all the swap chains.
+    /// This is updated when consumers recycle surfaces, assuming that they are the same size
+    /// as the most recent front buffer, and recounted when producers swap buffers.
+    pub fn recycled_memory(&self) -> usize {
+        self.memory.recycled.load(Ordering::Relaxed)
+    }
+
+    /// Destroy all the surfaces waiting to be recycled by the swap chains for a particular
+    /// producer context, for example when memory is low.
+    /// Called by the producer.
+    pub fn trim(&self, device: &Device, context: &mut Device::Context) -> Result<(), Error> {
+        for (_, swap_chain) in self.iter(device, context) {
+            swap_chain.trim(device, context)?;
+        }
+        Ok(())
+    }
+
+    /// Take the front buffer and its metadata of whichever swap chain swapped buffers first,
+    /// waiting for one of them to swap buffers if none has a front buffer.
+    /// `None` waits forever.
+    /// Returns `None` if the timeout expires first.
+    /// Called by a consumer.
+    pub fn wait_for_surface(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Option<(SwapChainID, Device::Surface, FrameMetadata)> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        // Holding the signal lock while looking for a front buffer means that a swap
+        // can't happen between looking and waiting without waking us up.
+        let mut guard = self.signal.lock();
+        loop {
+            if let Some(result) = self.take_oldest_pending_surface() {
+                return Some(result);
+            }
+            guard = wait_until(&self.signal.condvar, guard, deadline)?;
+        }
+    }
+
+    // Take the front buffer that was swapped first, and its metadata, across all the swap chains.
+    fn take_oldest_pending_surface(&self) -> Option<(SwapChainID, Device::Surface, FrameMetadata)> {
+        let table = self.table();
+        loop {
+            let (id, swap_chain) = table
+                .iter()
+                .filter_map(|(id, swap_chain)| {
+                    let swapped_at = swap_chain.lock().oldest_pending_swap()?;
+                    Some((swapped_at, id, swap_chain))
+                })
+                .min_by_key(|(swapped_at, _, _)| *swapped_at)
+                .map(|(_, id, swap_chain)| (id, swap_chain))?;
+            // Another consumer may have emptied the swap chain since we looked at it, in which
+            // case the other swap chains may still have front buffers.
+            if let Some((surface, metadata)) = swap_chain.take_pending_surface_with_metadata() {
+                return Some((id.clone(), surface, metadata));
+            }
+        }
+    }
+
     /// Iterate over all the swap chains for a particular producer context.
     /// Called by the producer.
     pub fn iter(
@@ -794,6 +1649,11 @@ pub trait SwapChainAPI: 'static + Clone + Send {
     /// Take the current front buffer.
     fn take_surface(&self) -> Option<Self::Surface>;
 
+    /// Take the current front buffer, and the metadata attached when it was swapped, if any.
+    fn take_surface_with_metadata(&self) -> Option<(Self::Surface, Option<FrameMetadata>)> {
+        self.take_surface().map(|surface| (surface, None))
+    }
+
     /// Recycle the current front buffer.
     fn recycle_surface(&self, surface: Self::Surface);
 }
@@ -1,7 +1,6 @@
 //! The abstract interface that all connections conform to.
 
-use crate::Error;
-use crate::GLApi;
+use crate::{AdapterInfo, Error, GLApi};
 
 use euclid::default::Size2D;
 
@@ -43,6 +42,13 @@ pub trait Connection: Sized {
     /// Returns the "best" adapter on this system, preferring software adapters.
     fn create_software_adapter(&self) -> Result<Self::Adapter, Error>;
 
+    /// Returns every adapter on this system that can be chosen individually, along with what is
+    /// known about each one.
+    ///
+    /// Any of the returned adapters can be passed to `create_device()`. Backends that can't
+    /// enumerate adapters return an empty list.
+    fn enumerate_adapters(&self) -> Vec<AdapterInfo<Self::Adapter>>;
+
     /// Opens a device.
     fn create_device(&self, adapter: &Self::Adapter) -> Result<Self::Device, Error>;
 
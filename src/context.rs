@@ -4,6 +4,7 @@
 
 use crate::gl;
 use crate::info::GLVersion;
+use crate::surface::{DepthFormat, SurfaceFormat};
 use crate::Gl;
 
 use std::ffi::CStr;
@@ -41,6 +42,18 @@ bitflags! {
         /// The OpenGL compatibility profile will be used. If this is not present, the core profile
         /// is used.
         const COMPATIBILITY_PROFILE = 0x08;
+        /// The context will be created with robust buffer access and will be lost if the GPU is
+        /// reset, so that resets can be detected with `Device::context_reset_status()`.
+        ///
+        /// This requires `EGL_EXT_create_context_robustness` for OpenGL ES or
+        /// `EGL_KHR_create_context` for OpenGL, and is only available on EGL-based backends.
+        const ROBUST_ACCESS         = 0x10;
+        /// The context will be created as a debug context, and messages that the driver reports
+        /// through `KHR_debug` will be forwarded to the `log` crate, tagged with the context's ID.
+        ///
+        /// This requires `EGL_KHR_create_context` on EGL-based backends and isn't supported with
+        /// CGL.
+        const DEBUG                 = 0x20;
     }
 }
 
@@ -56,6 +69,35 @@ pub struct ContextAttributes {
     pub version: GLVersion,
     /// Various flags.
     pub flags: ContextAttributeFlags,
+    /// The number of samples per pixel to use for generic surfaces created from this context.
+    ///
+    /// Values of 0 and 1 disable multisampling. Larger values are clamped to the value returned by
+    /// `Device::max_surface_samples()`; backends that can't multisample generic surfaces report a
+    /// maximum of 0. Multisampled surfaces are rendered into an offscreen multisampled buffer
+    /// that is resolved into the surface's texture when the surface is unbound from its context.
+    pub samples: u32,
+    /// The format that generic surfaces created from this context store their pixels in.
+    ///
+    /// `SurfaceFormat::RGBA8` is supported everywhere.
+    pub surface_format: SurfaceFormat,
+    /// The format of the depth buffer of generic surfaces created from this context, if the
+    /// `DEPTH` flag is present.
+    pub depth_format: DepthFormat,
+}
+
+/// Whether a context has been lost because of a GPU reset, and if so, who caused it.
+///
+/// See `Device::context_reset_status()`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ContextResetStatus {
+    /// No reset has happened since the context was created.
+    NoReset,
+    /// A reset was caused by this context.
+    Guilty,
+    /// A reset was caused by some other context.
+    Innocent,
+    /// A reset happened, but its cause couldn't be determined.
+    Unknown,
 }
 
 impl ContextAttributes {
@@ -64,10 +106,38 @@ impl ContextAttributes {
         ContextAttributes {
             version: GLVersion::new(0, 0),
             flags: ContextAttributeFlags::empty(),
+            samples: 0,
+            surface_format: SurfaceFormat::RGBA8,
+            depth_format: DepthFormat::Depth24,
         }
     }
 }
 
+#[allow(dead_code)]
+pub(crate) fn current_context_uses_robust_access(gl: &Gl) -> bool {
+    use glow::HasContext;
+
+    unsafe {
+        // `GL_RESET_NOTIFICATION_STRATEGY` is only defined if some form of robustness is supported,
+        // so ignore any error from querying it.
+        let reset_notification_strategy = gl.get_parameter_i32(gl::RESET_NOTIFICATION_STRATEGY);
+        gl.get_error() == gl::NO_ERROR
+            && reset_notification_strategy == gl::LOSE_CONTEXT_ON_RESET as i32
+    }
+}
+
+#[allow(dead_code)]
+pub(crate) fn current_context_is_debug(gl: &Gl) -> bool {
+    use glow::HasContext;
+
+    unsafe {
+        // OpenGL ES contexts before 3.2 don't have `GL_CONTEXT_FLAGS`, so ignore any error from
+        // querying it.
+        let context_flags = gl.get_parameter_i32(gl::CONTEXT_FLAGS);
+        gl.get_error() == gl::NO_ERROR && (context_flags & gl::CONTEXT_FLAG_DEBUG_BIT as i32) != 0
+    }
+}
+
 #[cfg(any(target_os = "android", target_env = "ohos"))]
 pub(crate) fn current_context_uses_compatibility_profile(_gl: &Gl) -> bool {
     false
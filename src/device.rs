@@ -1,11 +1,19 @@
 //! The abstract interface that all devices conform to.
 
 use super::connection::Connection as ConnectionInterface;
-use crate::{ContextAttributes, ContextID, Error, GLApi, SurfaceAccess, SurfaceInfo, SurfaceType};
-use euclid::default::Size2D;
+use crate::{
+    ContextAttributes, ContextID, ContextResetStatus, Error, GLApi, PixelFormat, ReadbackHandle,
+    SurfaceAccess, SurfaceInfo, SurfaceType,
+};
+#[cfg(free_unix)]
+use crate::{DmaBufDescriptor, DmaBufFormat};
+use euclid::default::{Rect, Size2D};
 use glow::Texture;
 
 use std::os::raw::c_void;
+#[cfg(free_unix)]
+use std::os::unix::io::OwnedFd;
+use std::time::Duration;
 
 /// A thread-local handle to a device.
 ///
@@ -26,6 +34,10 @@ where
     type Surface;
     /// The surface texture type associated with this device.
     type SurfaceTexture;
+    /// The type of the CPU view of a surface's pixel data associated with this device.
+    type SurfaceDataGuard<'a>: SurfaceDataGuard;
+    /// The GPU fence type associated with this device.
+    type Fence;
 
     // device.rs
 
@@ -94,6 +106,22 @@ where
         context_descriptor: &Self::ContextDescriptor,
     ) -> ContextAttributes;
 
+    /// Returns the largest number of samples per pixel that generic surfaces created with this
+    /// context can use.
+    ///
+    /// A result of 0 means that generic surfaces can't be multisampled on this context, in which
+    /// case the `samples` field of the context attributes is ignored.
+    fn max_surface_samples(&self, context: &Self::Context) -> Result<u32, Error>;
+
+    /// Returns whether the context has been lost because of a GPU reset, and if so, whether this
+    /// context caused it.
+    ///
+    /// Resets are only reliably reported for contexts created with the `ROBUST_ACCESS` flag. A
+    /// context that reports a reset must be destroyed and recreated before it can be used again;
+    /// other contexts that report `NoReset` are unaffected. Returns `RequiredExtensionUnavailable`
+    /// if the context can't query its reset status.
+    fn context_reset_status(&self, context: &Self::Context) -> Result<ContextResetStatus, Error>;
+
     /// Fetches the address of an OpenGL function associated with this context.
     ///
     /// OpenGL functions are local to a context. You should not use OpenGL functions on one context
@@ -142,6 +170,54 @@ where
     /// Returns the native context associated with the given context.
     fn native_context(&self, context: &Self::Context) -> Self::NativeContext;
 
+    /// Inserts a fence into the command stream of the given context and flushes it.
+    ///
+    /// The fence is signaled once the GPU finishes all commands issued before it. Fences can be
+    /// waited on from any context on a device that shares the same connection, on any thread.
+    /// Returns `RequiredExtensionUnavailable` if fences aren't supported.
+    ///
+    /// You must explicitly call `destroy_fence` to dispose of a fence. Otherwise, a panic occurs
+    /// in the `drop` method.
+    fn create_fence(&self, context: &Self::Context) -> Result<Self::Fence, Error>;
+
+    /// Blocks the calling thread until the fence is signaled, or until the timeout expires if
+    /// one is given.
+    ///
+    /// Returns true if the fence was signaled and false if the timeout expired first.
+    fn client_wait_fence(
+        &self,
+        fence: &Self::Fence,
+        timeout: Option<Duration>,
+    ) -> Result<bool, Error>;
+
+    /// Makes the GPU wait until the fence is signaled before executing any further commands
+    /// issued by the given context.
+    ///
+    /// Unlike `client_wait_fence`, this returns immediately without blocking the CPU.
+    fn server_wait_fence(&self, context: &Self::Context, fence: &Self::Fence) -> Result<(), Error>;
+
+    /// Destroys a fence.
+    ///
+    /// Pending waits on the fence are unaffected.
+    fn destroy_fence(&self, fence: &mut Self::Fence) -> Result<(), Error>;
+
+    /// Returns a new file descriptor for a native sync file that is signaled along with the
+    /// fence, for handing to other processes or APIs such as Vulkan or KMS.
+    ///
+    /// Returns `RequiredExtensionUnavailable` if the fence isn't backed by a native sync file.
+    #[cfg(free_unix)]
+    fn export_native_fence_fd(&self, fence: &Self::Fence) -> Result<OwnedFd, Error>;
+
+    /// Wraps a native sync file in a fence that can be waited on in the given context.
+    ///
+    /// This takes ownership of the file descriptor.
+    #[cfg(free_unix)]
+    fn import_native_fence_fd(
+        &self,
+        context: &Self::Context,
+        fd: OwnedFd,
+    ) -> Result<Self::Fence, Error>;
+
     // surface.rs
 
     /// Creates either a generic or a widget surface, depending on the supplied surface type.
@@ -199,7 +275,9 @@ where
 
     /// Returns the OpenGL texture target needed to read from this surface texture.
     ///
-    /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform.
+    /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform. Surface
+    /// textures imported from dma-bufs may need `GL_TEXTURE_EXTERNAL_OES` instead; use
+    /// `surface_texture_gl_texture_target()` for those.
     fn surface_gl_texture_target(&self) -> u32;
 
     /// Displays the contents of the currently bound surface to the screen, if
@@ -222,6 +300,62 @@ where
         surface: &mut Self::Surface,
     ) -> Result<(), Error>;
 
+    /// Displays the contents of a widget surface on screen, telling the compositor that only the
+    /// given rectangles have changed since the surface was last presented.
+    ///
+    /// The rectangles are relative to the top left corner of the surface. An empty list damages
+    /// the whole surface. Backends and displays that can't present part of a surface present the
+    /// whole surface like `present_surface()`, so the surface must always hold complete contents.
+    fn present_surface_with_damage(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error>;
+
+    /// Returns how many frames old the contents of the back buffer of a widget surface are.
+    ///
+    /// An age of 1 means that the back buffer holds the contents that were presented last, 2
+    /// means the contents of the frame before that, and so on. An age of 0 means that the
+    /// contents are undefined, and the whole surface must be redrawn. Together with
+    /// `present_surface_with_damage()`, this allows repainting only the parts of a surface that
+    /// changed.
+    ///
+    /// Generic surfaces return `NoWidgetAttached`. Swap chains report the age of their back
+    /// buffers with `SwapChain::back_buffer_age()` instead.
+    fn surface_buffer_age(
+        &self,
+        context: &Self::Context,
+        surface: &Self::Surface,
+    ) -> Result<u32, Error>;
+
+    /// Tells the driver that only the given rectangles of a widget surface will be rendered to
+    /// before it's next presented, so that it can avoid loading the rest of the back buffer.
+    ///
+    /// This must be called at most once per frame, before rendering to the surface. The
+    /// rectangles are relative to the top left corner of the surface, and an empty list damages
+    /// the whole surface. If this returns an error, the whole surface can be rendered to as usual.
+    fn set_damage_region(
+        &self,
+        context: &Self::Context,
+        surface: &mut Self::Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error>;
+
+    /// Sets the minimum number of display refreshes between presentations of a widget surface.
+    ///
+    /// An interval of 0 presents immediately without waiting for vertical sync, and 1 waits for
+    /// one refresh. The interval applies to later calls to `present_surface()` and
+    /// `present_bound_surface()`, and is kept while the surface is bound to and unbound from
+    /// contexts. Returns `UnsupportedSwapInterval` if the platform can't honor the interval, or
+    /// `NoWidgetAttached` for generic surfaces.
+    fn set_swap_interval(
+        &self,
+        context: &Self::Context,
+        surface: &Self::Surface,
+        interval: i32,
+    ) -> Result<(), Error>;
+
     /// If the currently bound surface is a widget surface, resize it,
     fn resize_bound_surface(
         &self,
@@ -249,4 +383,153 @@ where
     ///
     /// It is only legal to read from, not write to, this texture object.
     fn surface_texture_object(&self, surface_texture: &Self::SurfaceTexture) -> Option<Texture>;
+
+    /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
+    ///
+    /// The surface must have been created with `SurfaceAccess::GPUCPU` or
+    /// `SurfaceAccess::GPUCPUWriteCombined`, or a `SurfaceDataInaccessible` error is returned.
+    /// Widget surfaces cannot be locked.
+    ///
+    /// The data uses 4 bytes per pixel, in the channel order of the surface's format. Surfaces
+    /// whose format doesn't have 8 bits per channel can't be locked, and return an
+    /// `UnsupportedSurfaceFormat` error. Multisampled surfaces can't be locked either, since writes
+    /// to them would be overwritten when they are next resolved, and return a
+    /// `MultisampledSurface` error.
+    fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Self::Surface,
+    ) -> Result<Self::SurfaceDataGuard<'s>, Error>;
+
+    /// Reads back a rectangle of pixels from a generic surface.
+    ///
+    /// The rectangle is relative to the top left corner of the surface, and the returned rows are
+    /// ordered from top to bottom in the requested pixel format, regardless of the origin
+    /// convention of the underlying platform.
+    ///
+    /// The supplied context must be the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned. Rectangles that extend outside the surface return
+    /// an `InvalidRect` error.
+    ///
+    /// Surfaces with more than 8 bits per channel are converted to the requested pixel format.
+    /// Half-float surfaces can't be read back on OpenGL ES, and return an
+    /// `UnsupportedSurfaceFormat` error.
+    fn read_surface_pixels(
+        &self,
+        context: &mut Self::Context,
+        surface: &Self::Surface,
+        rect: Rect<i32>,
+        format: PixelFormat,
+    ) -> Result<Vec<u8>, Error>;
+
+    /// Starts copying the contents of a generic surface into CPU-visible memory.
+    ///
+    /// The copy is performed into a pixel pack buffer, reading from the same framebuffer object
+    /// that `surface_info()` returns, and is followed by a fence. This method does not wait for
+    /// the GPU, so it does not stall the producer.
+    ///
+    /// The supplied context must be the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned. If the context does not support pixel pack
+    /// buffers and fence sync objects, a `RequiredExtensionUnavailable` error is returned.
+    fn begin_surface_readback(
+        &self,
+        context: &mut Self::Context,
+        surface: &Self::Surface,
+    ) -> Result<ReadbackHandle, Error>;
+
+    /// Returns true if the GPU has finished a readback, so that `map_surface_readback()` will not
+    /// block.
+    ///
+    /// The supplied context must be the context the readback was started with, or an
+    /// `IncompatibleContext` error is returned.
+    fn poll_surface_readback(
+        &self,
+        context: &Self::Context,
+        readback: &ReadbackHandle,
+    ) -> Result<bool, Error>;
+
+    /// Returns the pixels of a readback in RGBA format, with rows ordered from top to bottom, and
+    /// frees the readback.
+    ///
+    /// If the GPU has not yet finished the copy, this waits for it. The supplied context must be
+    /// the context the readback was started with, or an `IncompatibleContext` error is returned.
+    /// On failure, the readback is returned alongside the error, so that it can be mapped again
+    /// or destroyed.
+    fn map_surface_readback(
+        &self,
+        context: &mut Self::Context,
+        readback: ReadbackHandle,
+    ) -> Result<Vec<u8>, (Error, ReadbackHandle)>;
+
+    /// Frees a readback without mapping it.
+    ///
+    /// The supplied context must be the context the readback was started with, or an
+    /// `IncompatibleContext` error is returned alongside the readback.
+    ///
+    /// You must call either this method or `map_surface_readback()` to dispose of a readback.
+    /// Otherwise, a panic occurs in the `drop` method.
+    fn destroy_surface_readback(
+        &self,
+        context: &mut Self::Context,
+        readback: ReadbackHandle,
+    ) -> Result<(), (Error, ReadbackHandle)>;
+
+    /// Exports the contents of a generic surface as a Linux dma-buf, so that other processes or
+    /// APIs can use them without copying.
+    ///
+    /// The surface's contents are only guaranteed to be visible through the dma-buf once the
+    /// surface has been unbound from its context. Widget surfaces return a `WidgetAttached`
+    /// error.
+    #[cfg(free_unix)]
+    fn export_surface_dmabuf(&self, surface: &Self::Surface) -> Result<DmaBufDescriptor, Error>;
+
+    /// Creates a generic surface that renders into an existing Linux dma-buf.
+    ///
+    /// The buffer must be in a format that a `SurfaceFormat` corresponds to, or an
+    /// `UnsupportedSurfaceFormat` error is returned. The caller keeps ownership of the
+    /// descriptor's file descriptors.
+    #[cfg(free_unix)]
+    fn create_surface_from_dmabuf(
+        &self,
+        context: &Self::Context,
+        surface_access: SurfaceAccess,
+        descriptor: &DmaBufDescriptor,
+    ) -> Result<Self::Surface, Error>;
+
+    /// Wraps an existing Linux dma-buf, which may have several planes, in a surface texture for
+    /// use with the given context.
+    ///
+    /// Destroying the surface texture returns a surface that can't be rendered to, and which only
+    /// needs to be destroyed. The caller keeps ownership of the descriptor's file descriptors.
+    #[cfg(free_unix)]
+    fn create_surface_texture_from_dmabuf(
+        &self,
+        context: &mut Self::Context,
+        descriptor: &DmaBufDescriptor,
+    ) -> Result<Self::SurfaceTexture, Error>;
+
+    /// Returns the dma-buf formats, and the modifiers for each format, that this device can
+    /// import.
+    #[cfg(free_unix)]
+    fn dmabuf_formats(&self) -> Result<Vec<DmaBufFormat>, Error>;
+
+    /// Returns the OpenGL texture target needed to read from the given surface texture.
+    ///
+    /// This is `GL_TEXTURE_EXTERNAL_OES` for surface textures imported from dma-bufs that the
+    /// driver can only sample as external textures, such as many YUV buffers, and the same as
+    /// `surface_gl_texture_target()` otherwise.
+    #[cfg(free_unix)]
+    fn surface_texture_gl_texture_target(&self, surface_texture: &Self::SurfaceTexture) -> u32;
+}
+
+/// The CPU view of the pixel data of a surface, returned by `Device::lock_surface_data()`.
+///
+/// The surface stays locked until this guard is dropped.
+pub trait SurfaceDataGuard {
+    /// Returns the number of bytes per row of the surface.
+    fn stride(&self) -> usize;
+
+    /// Returns a mutable slice of the pixel data in this surface.
+    ///
+    /// The pixel format is backend-specific.
+    fn data(&mut self) -> &mut [u8];
 }
@@ -0,0 +1,52 @@
+//! Descriptions of Linux dma-bufs that surfaces are shared through.
+
+use euclid::default::Size2D;
+use std::os::unix::io::OwnedFd;
+
+/// Describes a Linux dma-buf that holds the contents of a surface.
+///
+/// A descriptor owns its file descriptors, which keep the underlying memory alive even after the
+/// surface it was exported from is destroyed. The file descriptors can be sent to other processes
+/// (for example, over a Unix domain socket) so that they can use the pixels without copying them.
+///
+/// There is one entry in `fds`, `strides`, and `offsets` for each plane of the buffer.
+#[derive(Debug)]
+pub struct DmaBufDescriptor {
+    /// The size of the buffer, in pixels.
+    pub size: Size2D<i32>,
+    /// The DRM fourcc code that describes the pixel format, as defined in `drm_fourcc.h`.
+    pub fourcc: u32,
+    /// The DRM format modifier that describes the memory layout, such as tiling or compression.
+    pub modifier: u64,
+    /// The file descriptors of each plane.
+    pub fds: Vec<OwnedFd>,
+    /// The number of bytes per row of each plane.
+    pub strides: Vec<u32>,
+    /// The offset of each plane within its file, in bytes.
+    pub offsets: Vec<u32>,
+}
+
+/// A dma-buf pixel format that the display can import, as returned by
+/// `Device::dmabuf_formats()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DmaBufFormat {
+    /// The DRM fourcc code of the format.
+    pub fourcc: u32,
+    /// The memory layouts that buffers in this format can use.
+    ///
+    /// This may be empty if the driver only accepts buffers whose layout is implied by the
+    /// driver, in which case `DmaBufDescriptor::modifier` should be `DRM_FORMAT_MOD_INVALID`
+    /// (`0x00ff_ffff_ffff_ffff`).
+    pub modifiers: Vec<DmaBufModifier>,
+}
+
+/// A memory layout that the display can import buffers of a given format with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DmaBufModifier {
+    /// The DRM format modifier.
+    pub modifier: u64,
+    /// True if buffers with this layout can only be sampled as `GL_TEXTURE_EXTERNAL_OES`
+    /// textures. Such buffers can only be imported with
+    /// `Device::create_surface_texture_from_dmabuf()`.
+    pub external_only: bool,
+}
@@ -0,0 +1,183 @@
+//! A connection to the EGL device platform.
+//!
+//! There is no display server on this backend. Each device opens its own `EGLDisplay` on the
+//! `EGLDeviceEXT` that its adapter refers to.
+
+use super::device::{Adapter, Device, NativeDevice};
+use super::surface::NativeWidget;
+use crate::base::egl::device;
+use crate::{AdapterInfo, Error, GLApi};
+
+use euclid::default::Size2D;
+
+use std::os::raw::c_void;
+
+/// A connection to the EGL device platform.
+///
+/// This is a placeholder, since there is no display server to connect to.
+#[derive(Clone)]
+pub struct Connection;
+
+/// An empty placeholder for native connections.
+#[derive(Clone)]
+pub struct NativeConnection;
+
+impl Connection {
+    /// Checks that the EGL implementation supports the device platform and has at least one
+    /// device, and returns a connection.
+    ///
+    /// Returns `RequiredExtensionUnavailable` if `EGL_EXT_platform_device` isn't supported, or
+    /// `NoAdapterFound` if no EGL devices could be enumerated.
+    pub fn new() -> Result<Connection, Error> {
+        if !device::supports_device_displays() {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+        if device::enumerate_devices().is_empty() {
+            return Err(Error::NoAdapterFound);
+        }
+        Ok(Connection)
+    }
+
+    /// An alias for `Connection::new()`, present for consistency with other backends.
+    ///
+    /// # Safety
+    ///
+    /// This function is always safe to call, since `NativeConnection` carries no state. It is
+    /// only marked `unsafe` because it is on other backends.
+    #[inline]
+    pub unsafe fn from_native_connection(_: NativeConnection) -> Result<Connection, Error> {
+        Connection::new()
+    }
+
+    /// Returns the underlying native connection.
+    #[inline]
+    pub fn native_connection(&self) -> NativeConnection {
+        NativeConnection
+    }
+
+    /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
+    #[inline]
+    pub fn gl_api(&self) -> GLApi {
+        GLApi::GL
+    }
+
+    /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
+    ///
+    /// This is an alias for `Connection::create_hardware_adapter()`.
+    #[inline]
+    pub fn create_adapter(&self) -> Result<Adapter, Error> {
+        self.create_hardware_adapter()
+    }
+
+    /// Returns the first hardware EGL device on this system, or the first EGL device of any kind
+    /// if there are no hardware devices.
+    #[inline]
+    pub fn create_hardware_adapter(&self) -> Result<Adapter, Error> {
+        choose_adapter(false)
+    }
+
+    /// Returns the first hardware EGL device on this system, or the first EGL device of any kind
+    /// if there are no hardware devices.
+    ///
+    /// EGL doesn't say which devices are low-power, so this is the same as
+    /// `create_hardware_adapter()`.
+    #[inline]
+    pub fn create_low_power_adapter(&self) -> Result<Adapter, Error> {
+        choose_adapter(false)
+    }
+
+    /// Returns the first software EGL device on this system, or the first EGL device of any kind
+    /// if there are no software devices.
+    #[inline]
+    pub fn create_software_adapter(&self) -> Result<Adapter, Error> {
+        choose_adapter(true)
+    }
+
+    /// Returns an adapter for each EGL device on this system, along with what is known about it.
+    pub fn enumerate_adapters(&self) -> Vec<AdapterInfo<Adapter>> {
+        device::enumerate_devices()
+            .into_iter()
+            .map(|info| info.map_adapter(Adapter))
+            .collect()
+    }
+
+    /// Opens the hardware device corresponding to the given adapter.
+    ///
+    /// Device handles are local to a single thread.
+    #[inline]
+    pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
+        Device::new(adapter)
+    }
+
+    /// Opens a device on the given `EGLDeviceEXT`.
+    ///
+    /// # Safety
+    ///
+    /// The `EGLDeviceEXT` must be valid, such as one returned by `eglQueryDevicesEXT()`.
+    #[inline]
+    pub unsafe fn create_device_from_native_device(
+        &self,
+        native_device: NativeDevice,
+    ) -> Result<Device, Error> {
+        Device::new(&Adapter(native_device.0))
+    }
+
+    /// Opens the display connection corresponding to the given `RawDisplayHandle`.
+    #[cfg(feature = "sm-raw-window-handle-05")]
+    pub fn from_raw_display_handle(_: rwh_05::RawDisplayHandle) -> Result<Connection, Error> {
+        Err(Error::IncompatibleNativeWidget)
+    }
+
+    /// Opens the display connection corresponding to the given `DisplayHandle`.
+    #[cfg(feature = "sm-raw-window-handle-06")]
+    pub fn from_display_handle(_: rwh_06::DisplayHandle) -> Result<Connection, Error> {
+        Err(Error::IncompatibleNativeWidget)
+    }
+
+    /// Create a native widget from a raw pointer
+    ///
+    /// # Safety
+    ///
+    /// This function is always safe to call, since this backend has no native widgets and
+    /// ignores the pointer.
+    pub unsafe fn create_native_widget_from_ptr(
+        &self,
+        _raw: *mut c_void,
+        _size: Size2D<i32>,
+    ) -> NativeWidget {
+        NativeWidget
+    }
+
+    /// Create a native widget type from the given `RawWindowHandle`.
+    #[cfg(feature = "sm-raw-window-handle-05")]
+    #[inline]
+    pub fn create_native_widget_from_raw_window_handle(
+        &self,
+        _: rwh_05::RawWindowHandle,
+        _size: Size2D<i32>,
+    ) -> Result<NativeWidget, Error> {
+        Err(Error::IncompatibleNativeWidget)
+    }
+
+    /// Create a native widget type from the given `WindowHandle`.
+    #[cfg(feature = "sm-raw-window-handle-06")]
+    #[inline]
+    pub fn create_native_widget_from_window_handle(
+        &self,
+        _: rwh_06::WindowHandle,
+        _size: Size2D<i32>,
+    ) -> Result<NativeWidget, Error> {
+        Err(Error::IncompatibleNativeWidget)
+    }
+}
+
+// Returns the first EGL device whose software flag matches, falling back to the first device.
+fn choose_adapter(software: bool) -> Result<Adapter, Error> {
+    let devices = device::enumerate_devices();
+    devices
+        .iter()
+        .find(|info| info.software == software)
+        .or(devices.first())
+        .map(|info| Adapter(info.adapter))
+        .ok_or(Error::NoAdapterFound)
+}
@@ -0,0 +1,13 @@
+//! The EGL device platform backend, which renders off-screen on a chosen GPU (or software
+//! device) without a display server, using `EGL_EXT_platform_device`.
+
+pub mod connection;
+pub mod context;
+pub mod device;
+pub mod surface;
+
+crate::implement_interfaces!();
+
+#[cfg(test)]
+#[path = "../tests.rs"]
+mod tests;
@@ -0,0 +1,62 @@
+//! Wrapper for EGL surfaces on EGL devices.
+
+use crate::base::egl::surface::{EGLBackedSurface, EGLSurfaceDataGuard, EGLSurfaceTexture};
+
+/// Represents a hardware buffer of pixels that can be rendered to via the CPU or GPU and either
+/// displayed in a native widget or bound to a texture for reading.
+///
+/// Surfaces come in two varieties: generic and widget surfaces. Generic surfaces can be bound to a
+/// texture but cannot be displayed in a widget (without using other APIs such as Core Animation,
+/// DirectComposition, or XPRESENT). Widget surfaces are the opposite: they can be displayed in a
+/// widget but not bound to a texture.
+///
+/// Surfaces are specific to a given context and cannot be rendered to from any context other than
+/// the one they were created with. However, they can be *read* from any context on any thread (as
+/// long as that context shares the same adapter and connection), by wrapping them in a
+/// `SurfaceTexture`.
+///
+/// Depending on the platform, each surface may be internally double-buffered.
+///
+/// Surfaces must be destroyed with the `destroy_surface()` method, or a panic will occur.
+#[derive(Debug)]
+pub struct Surface(pub(crate) EGLBackedSurface);
+
+/// Represents an OpenGL texture that wraps a surface.
+///
+/// Reading from the associated OpenGL texture reads from the surface. It is undefined behavior to
+/// write to such a texture (e.g. by binding it to a framebuffer and rendering to that
+/// framebuffer).
+///
+/// Surface textures are local to a context, but that context does not have to be the same context
+/// as that associated with the underlying surface. The texture must be destroyed with the
+/// `destroy_surface_texture()` method, or a panic will occur.
+#[derive(Debug)]
+pub struct SurfaceTexture(pub(crate) EGLSurfaceTexture);
+
+/// A placeholder wrapper for a native widget.
+#[derive(Clone)]
+pub struct NativeWidget;
+
+unsafe impl Send for Surface {}
+
+/// Represents the CPU view of the pixel data of this surface.
+///
+/// The pixel data is a copy of the surface contents. Any changes made to it are written back to
+/// the surface when this guard is dropped.
+pub struct SurfaceDataGuard<'a>(pub(crate) EGLSurfaceDataGuard<'a>);
+
+impl<'a> SurfaceDataGuard<'a> {
+    /// Returns the number of bytes per row of the surface.
+    #[inline]
+    pub fn stride(&self) -> usize {
+        self.0.stride()
+    }
+
+    /// Returns a mutable slice of the pixel data in this surface, in RGBA format.
+    ///
+    /// Rows are stored top to bottom.
+    #[inline]
+    pub fn data(&mut self) -> &mut [u8] {
+        self.0.data()
+    }
+}
@@ -47,10 +47,18 @@ pub enum Error {
     SurfaceCreationFailed(WindowingApiError),
     /// The system couldn't import a surface from another thread.
     SurfaceImportFailed(WindowingApiError),
+    /// The system couldn't export a surface for use by another process.
+    SurfaceExportFailed(WindowingApiError),
     /// The system couldn't create a surface texture from a surface.
     SurfaceTextureCreationFailed(WindowingApiError),
     /// The system couldn't present a widget surface.
     PresentFailed(WindowingApiError),
+    /// The system couldn't create a fence.
+    FenceCreationFailed(WindowingApiError),
+    /// The system couldn't wait on a fence.
+    FenceWaitFailed(WindowingApiError),
+    /// The system couldn't export a fence as a native file descriptor.
+    FenceExportFailed(WindowingApiError),
     /// A context couldn't be created because there is no current context.
     NoCurrentContext,
     /// The current connection couldn't be fetched because there is no current connection.
@@ -78,6 +86,15 @@ pub enum Error {
     SurfaceDataInaccessible,
     /// The surface could not be locked for CPU reading due to an OS error.
     SurfaceLockFailed,
+    /// The requested rectangle does not lie within the surface.
+    InvalidRect,
+    /// The device can't render to the requested surface format, or can't share surfaces in that
+    /// format through the platform's texture sharing mechanism.
+    UnsupportedSurfaceFormat,
+    /// The surface is multisampled, so it has no single-sample image that the CPU can access.
+    MultisampledSurface,
+    /// The widget surface's pixel format doesn't support the requested swap interval.
+    UnsupportedSwapInterval,
     /// A connection to the display server could not be opened.
     ConnectionFailed,
     /// A connection to the window server is required to open a hardware device.
@@ -92,6 +109,11 @@ pub enum Error {
     IncompatibleNativeContext,
     /// The native device does not match the supplied connection.
     IncompatibleNativeDevice,
+    /// A surface couldn't be sent to or received from another process.
+    IpcFailed(std::io::ErrorKind),
+    /// The operation can't complete without waiting, for example because a swap chain's queue of
+    /// front buffers is full. It can be retried later.
+    WouldBlock,
 }
 
 /// Abstraction of the errors that EGL, CGL, GLX, CGL, etc. return.
@@ -0,0 +1,375 @@
+//! A connection to a DRM render node through `libgbm`.
+
+use super::device::{Adapter, Device, NativeDevice};
+use super::ffi::{gbm_device, GBMFunctions, GBM_BO_USE_RENDERING, GBM_FUNCTIONS};
+use super::surface::NativeWidget;
+use crate::base::egl::context;
+use crate::base::egl::device::{self, EGL_FUNCTIONS};
+use crate::base::egl::dmabuf::{self, DmaBufPlane};
+use crate::base::egl::ffi::{EGLImageKHR, EGL_PLATFORM_GBM_KHR};
+use crate::egl;
+use crate::egl::types::{EGLAttrib, EGLDisplay};
+use crate::{AdapterInfo, Error, GLApi, WindowingApiError};
+
+use euclid::default::Size2D;
+
+use std::fs::{self, File, OpenOptions};
+use std::os::raw::c_void;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+static DRI_DIRECTORY: &str = "/dev/dri";
+static RENDER_NODE_PREFIX: &str = "renderD";
+
+/// A connection to a DRM render node.
+#[derive(Clone)]
+pub struct Connection {
+    pub(crate) native_connection: Arc<NativeConnectionWrapper>,
+}
+
+/// Native connections.
+#[derive(Clone)]
+pub struct NativeConnection(Arc<NativeConnectionWrapper>);
+
+/// Native connections.
+pub struct NativeConnectionWrapper {
+    pub(crate) render_node_path: PathBuf,
+    pub(crate) gbm_device: *mut gbm_device,
+    pub(crate) egl_display: EGLDisplay,
+    // `libgbm` devices may not be used from several threads at once.
+    gbm_lock: Mutex<()>,
+    // Keeps the render node open for as long as the GBM device uses it.
+    #[allow(dead_code)]
+    render_node: File,
+}
+
+unsafe impl Send for NativeConnectionWrapper {}
+unsafe impl Sync for NativeConnectionWrapper {}
+
+impl Drop for NativeConnectionWrapper {
+    fn drop(&mut self) {
+        unsafe {
+            EGL_FUNCTIONS.with(|egl| {
+                egl.Terminate(self.egl_display);
+            });
+            if let Some(gbm) = GBM_FUNCTIONS.as_ref() {
+                (gbm.DeviceDestroy)(self.gbm_device);
+            }
+        }
+    }
+}
+
+impl NativeConnectionWrapper {
+    // Allocates a buffer object that can be rendered to, and wraps it in an EGL image through its
+    // dma-buf file descriptors.
+    //
+    // The image keeps the underlying memory alive, so the buffer object is destroyed right away.
+    pub(crate) fn create_egl_image(
+        &self,
+        size: &Size2D<i32>,
+        drm_format: u32,
+    ) -> Result<EGLImageKHR, Error> {
+        let gbm = gbm_functions()?;
+        let _lock = self.gbm_lock.lock().unwrap();
+        unsafe {
+            if (gbm.DeviceIsFormatSupported)(self.gbm_device, drm_format, GBM_BO_USE_RENDERING) == 0
+            {
+                return Err(Error::UnsupportedSurfaceFormat);
+            }
+            let bo = (gbm.BoCreate)(
+                self.gbm_device,
+                size.width as u32,
+                size.height as u32,
+                drm_format,
+                GBM_BO_USE_RENDERING,
+            );
+            if bo.is_null() {
+                return Err(Error::SurfaceCreationFailed(WindowingApiError::BadAlloc));
+            }
+
+            let plane_count = (gbm.BoGetPlaneCount)(bo);
+            let mut fds = Vec::with_capacity(plane_count as usize);
+            let mut planes = Vec::with_capacity(plane_count as usize);
+            for plane in 0..plane_count {
+                let fd = (gbm.BoGetFdForPlane)(bo, plane);
+                if fd < 0 {
+                    break;
+                }
+                // EGL duplicates the file descriptors, so ours can be closed afterward.
+                fds.push(OwnedFd::from_raw_fd(fd));
+                planes.push(DmaBufPlane {
+                    fd,
+                    offset: (gbm.BoGetOffset)(bo, plane),
+                    stride: (gbm.BoGetStrideForPlane)(bo, plane),
+                });
+            }
+            let modifier = (gbm.BoGetModifier)(bo);
+            (gbm.BoDestroy)(bo);
+
+            if planes.len() != plane_count as usize {
+                return Err(Error::SurfaceCreationFailed(WindowingApiError::BadAccess));
+            }
+            dmabuf::create_egl_image(self.egl_display, size, drm_format, modifier, &planes)
+        }
+    }
+}
+
+impl Connection {
+    /// Opens the first DRM render node under `/dev/dri` that `libgbm` and EGL can use.
+    pub fn new() -> Result<Connection, Error> {
+        let mut render_nodes: Vec<PathBuf> = match fs::read_dir(DRI_DIRECTORY) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| {
+                    entry
+                        .file_name()
+                        .to_string_lossy()
+                        .starts_with(RENDER_NODE_PREFIX)
+                })
+                .map(|entry| entry.path())
+                .collect(),
+            Err(_) => return Err(Error::ConnectionFailed),
+        };
+        render_nodes.sort();
+
+        render_nodes
+            .iter()
+            .find_map(|render_node| Connection::from_render_node(render_node).ok())
+            .ok_or(Error::ConnectionFailed)
+    }
+
+    /// Opens the given DRM render node, such as `/dev/dri/renderD128`.
+    ///
+    /// Returns `RequiredExtensionUnavailable` if EGL doesn't support the GBM platform, or
+    /// `ConnectionFailed` if the node can't be opened, `libgbm` can't be loaded, or EGL can't
+    /// create a display for it.
+    pub fn from_render_node(path: &Path) -> Result<Connection, Error> {
+        let gbm = gbm_functions().map_err(|_| Error::ConnectionFailed)?;
+        unsafe {
+            if !context::display_supports_extension(egl::NO_DISPLAY, "EGL_KHR_platform_gbm")
+                && !context::display_supports_extension(egl::NO_DISPLAY, "EGL_MESA_platform_gbm")
+            {
+                return Err(Error::RequiredExtensionUnavailable);
+            }
+        }
+
+        let render_node = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|_| Error::ConnectionFailed)?;
+
+        unsafe {
+            let gbm_device = (gbm.CreateDevice)(render_node.as_raw_fd());
+            if gbm_device.is_null() {
+                return Err(Error::ConnectionFailed);
+            }
+
+            let egl_display = EGL_FUNCTIONS.with(|egl| {
+                let egl_display_attributes = [egl::NONE as EGLAttrib];
+                let egl_display = egl.GetPlatformDisplay(
+                    EGL_PLATFORM_GBM_KHR,
+                    gbm_device as *mut c_void,
+                    egl_display_attributes.as_ptr(),
+                );
+                if egl_display == egl::NO_DISPLAY {
+                    return None;
+                }
+
+                let (mut egl_major_version, mut egl_minor_version) = (0, 0);
+                let ok =
+                    egl.Initialize(egl_display, &mut egl_major_version, &mut egl_minor_version);
+                if ok == egl::FALSE {
+                    return None;
+                }
+
+                Some(egl_display)
+            });
+            let egl_display = match egl_display {
+                Some(egl_display) => egl_display,
+                None => {
+                    (gbm.DeviceDestroy)(gbm_device);
+                    return Err(Error::ConnectionFailed);
+                }
+            };
+
+            Ok(Connection {
+                native_connection: Arc::new(NativeConnectionWrapper {
+                    render_node_path: path.to_owned(),
+                    gbm_device,
+                    egl_display,
+                    gbm_lock: Mutex::new(()),
+                    render_node,
+                }),
+            })
+        }
+    }
+
+    /// Wraps an existing native connection.
+    ///
+    /// # Safety
+    ///
+    /// The native connection must have been returned by `Connection::native_connection()`, so
+    /// that its GBM device and EGL display are valid.
+    #[inline]
+    pub unsafe fn from_native_connection(
+        native_connection: NativeConnection,
+    ) -> Result<Connection, Error> {
+        Ok(Connection {
+            native_connection: native_connection.0,
+        })
+    }
+
+    /// Returns the underlying native connection.
+    #[inline]
+    pub fn native_connection(&self) -> NativeConnection {
+        NativeConnection(self.native_connection.clone())
+    }
+
+    /// Returns the path of the DRM render node that this connection opened.
+    #[inline]
+    pub fn render_node(&self) -> &Path {
+        &self.native_connection.render_node_path
+    }
+
+    /// Returns the OpenGL API flavor that this connection supports (OpenGL or OpenGL ES).
+    #[inline]
+    pub fn gl_api(&self) -> GLApi {
+        GLApi::GL
+    }
+
+    /// Returns the "best" adapter on this system, preferring high-performance hardware adapters.
+    ///
+    /// This is an alias for `Connection::create_hardware_adapter()`.
+    #[inline]
+    pub fn create_adapter(&self) -> Result<Adapter, Error> {
+        self.create_hardware_adapter()
+    }
+
+    /// Returns the adapter for the render node that this connection opened.
+    ///
+    /// On this backend, the GPU is chosen when the connection is opened, so all adapters are the
+    /// same.
+    #[inline]
+    pub fn create_hardware_adapter(&self) -> Result<Adapter, Error> {
+        Ok(Adapter)
+    }
+
+    /// Returns the adapter for the render node that this connection opened.
+    ///
+    /// On this backend, the GPU is chosen when the connection is opened, so all adapters are the
+    /// same.
+    #[inline]
+    pub fn create_low_power_adapter(&self) -> Result<Adapter, Error> {
+        Ok(Adapter)
+    }
+
+    /// Returns the adapter for the render node that this connection opened.
+    ///
+    /// On this backend, the GPU is chosen when the connection is opened, so all adapters are the
+    /// same.
+    #[inline]
+    pub fn create_software_adapter(&self) -> Result<Adapter, Error> {
+        Ok(Adapter)
+    }
+
+    /// Returns the adapter for the render node this connection was opened on, along with what is
+    /// known about it.
+    ///
+    /// The render node is chosen when the connection is opened, so this list always has exactly
+    /// one entry. Use `Connection::from_render_node()` to pick another one.
+    pub fn enumerate_adapters(&self) -> Vec<AdapterInfo<Adapter>> {
+        let native_connection = &self.native_connection;
+        let info = match unsafe { device::display_device(native_connection.egl_display) } {
+            Some(info) => info.map_adapter(|_| Adapter),
+            None => AdapterInfo {
+                adapter: Adapter,
+                drm_render_node: None,
+                vendor: None,
+                renderer: None,
+                software: false,
+            },
+        };
+        vec![AdapterInfo {
+            drm_render_node: Some(native_connection.render_node_path.clone()),
+            ..info
+        }]
+    }
+
+    /// Opens the hardware device corresponding to the given adapter.
+    ///
+    /// Device handles are local to a single thread.
+    #[inline]
+    pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
+        Device::new(self, adapter)
+    }
+
+    /// An alias for `connection.create_device()` with the default adapter.
+    ///
+    /// # Safety
+    ///
+    /// This function is always safe to call, since the native device is ignored. It is only
+    /// marked `unsafe` because it is on other backends.
+    #[inline]
+    pub unsafe fn create_device_from_native_device(
+        &self,
+        _: NativeDevice,
+    ) -> Result<Device, Error> {
+        Device::new(self, &self.create_adapter()?)
+    }
+
+    /// Opens the display connection corresponding to the given `RawDisplayHandle`.
+    #[cfg(feature = "sm-raw-window-handle-05")]
+    pub fn from_raw_display_handle(_: rwh_05::RawDisplayHandle) -> Result<Connection, Error> {
+        Err(Error::IncompatibleNativeWidget)
+    }
+
+    /// Opens the display connection corresponding to the given `DisplayHandle`.
+    #[cfg(feature = "sm-raw-window-handle-06")]
+    pub fn from_display_handle(_: rwh_06::DisplayHandle) -> Result<Connection, Error> {
+        Err(Error::IncompatibleNativeWidget)
+    }
+
+    /// Create a native widget from a raw pointer
+    ///
+    /// # Safety
+    ///
+    /// This function is always safe to call, since this backend has no native widgets and
+    /// ignores the pointer.
+    pub unsafe fn create_native_widget_from_ptr(
+        &self,
+        _raw: *mut c_void,
+        _size: Size2D<i32>,
+    ) -> NativeWidget {
+        NativeWidget
+    }
+
+    /// Create a native widget type from the given `RawWindowHandle`.
+    #[cfg(feature = "sm-raw-window-handle-05")]
+    #[inline]
+    pub fn create_native_widget_from_raw_window_handle(
+        &self,
+        _: rwh_05::RawWindowHandle,
+        _size: Size2D<i32>,
+    ) -> Result<NativeWidget, Error> {
+        Err(Error::IncompatibleNativeWidget)
+    }
+
+    /// Create a native widget type from the given `WindowHandle`.
+    #[cfg(feature = "sm-raw-window-handle-06")]
+    #[inline]
+    pub fn create_native_widget_from_window_handle(
+        &self,
+        _: rwh_06::WindowHandle,
+        _size: Size2D<i32>,
+    ) -> Result<NativeWidget, Error> {
+        Err(Error::IncompatibleNativeWidget)
+    }
+}
+
+pub(crate) fn gbm_functions() -> Result<&'static GBMFunctions, Error> {
+    GBM_FUNCTIONS
+        .as_ref()
+        .ok_or(Error::RequiredExtensionUnavailable)
+}
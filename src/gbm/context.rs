@@ -0,0 +1,32 @@
+//! OpenGL rendering contexts on GBM devices.
+
+use crate::base::egl::context::EGLBackedContext;
+pub use crate::base::egl::context::{ContextDescriptor, NativeContext};
+use crate::base::egl::fence::EGLFence;
+use crate::Gl;
+
+/// Represents an OpenGL rendering context.
+///
+/// A context allows you to issue rendering commands to a surface. When initially created, a
+/// context has no attached surface, so rendering commands will fail or be ignored. Typically, you
+/// attach a surface to the context before rendering.
+///
+/// Contexts take ownership of the surfaces attached to them. In order to mutate a surface in any
+/// way other than rendering to it (e.g. presenting it to a window, which causes a buffer swap), it
+/// must first be detached from its context. Each surface is associated with a single context upon
+/// creation and may not be rendered to from any other context. However, you can wrap a surface in
+/// a surface texture, which allows the surface to be read from another context.
+///
+/// OpenGL objects may not be shared across contexts directly, but surface textures effectively
+/// allow for sharing of texture data. Contexts are local to a single thread and device.
+///
+/// A context must be explicitly destroyed with `destroy_context()`, or a panic will occur.
+pub struct Context(pub(crate) EGLBackedContext, pub(crate) Gl);
+
+/// Represents a GPU fence, which is signaled once the GPU finishes all commands issued before it.
+///
+/// Fences can be waited on from any context on a device that shares the same connection, on any
+/// thread.
+///
+/// A fence must be explicitly destroyed with `destroy_fence()`, or a panic will occur.
+pub struct Fence(pub(crate) EGLFence);
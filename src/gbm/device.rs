@@ -0,0 +1,824 @@
+//! A thread-local handle to the device behind a GBM connection.
+
+use super::connection::{Connection, NativeConnectionWrapper};
+use super::surface::SurfaceDataGuard;
+use crate::base::egl::context::{self, CurrentContextGuard, EGLBackedContext};
+use crate::base::egl::dmabuf;
+use crate::base::egl::fence::EGLFence;
+use crate::base::egl::surface::EGLBackedSurface;
+use crate::context::ContextID;
+use crate::egl;
+use crate::egl::types::EGLint;
+use crate::gbm::context::{Context, ContextDescriptor, Fence, NativeContext};
+use crate::gbm::surface::{NativeWidget, Surface, SurfaceTexture};
+use crate::gl;
+use crate::gl_utils;
+use crate::readback::ReadbackHandle;
+use crate::{ContextAttributes, ContextResetStatus, Gl, SurfaceInfo};
+use crate::{DmaBufDescriptor, DmaBufFormat};
+use crate::{Error, GLApi, PixelFormat, SurfaceAccess, SurfaceType};
+use euclid::default::{Rect, Size2D};
+use glow::Texture;
+use std::os::raw::c_void;
+use std::os::unix::io::OwnedFd;
+use std::sync::Arc;
+use std::time::Duration;
+
+const SURFACE_GL_TEXTURE_TARGET: u32 = gl::TEXTURE_2D;
+
+/// Represents a hardware display adapter that can be used for rendering (including the CPU).
+///
+/// On this backend, the render node is chosen when the connection is opened, so there is only one
+/// adapter per connection. Adapters can be sent between threads. To render with an adapter, open a
+/// thread-local `Device`.
+#[derive(Clone, Debug)]
+pub struct Adapter;
+
+/// A thread-local handle to a device.
+///
+/// Devices contain most of the relevant surface management methods.
+pub struct Device {
+    pub(crate) native_connection: Arc<NativeConnectionWrapper>,
+}
+
+/// Wraps an adapter.
+///
+/// On GBM, devices and adapters are essentially identical types.
+#[derive(Clone)]
+pub struct NativeDevice {
+    /// The hardware adapter corresponding to this device.
+    pub adapter: Adapter,
+}
+
+impl Device {
+    #[inline]
+    pub(crate) fn new(connection: &Connection, _: &Adapter) -> Result<Device, Error> {
+        Ok(Device {
+            native_connection: connection.native_connection.clone(),
+        })
+    }
+
+    /// Returns the native device corresponding to this device.
+    ///
+    /// This method is essentially an alias for the `adapter()` method on GBM, since there is no
+    /// explicit concept of a device on this backend.
+    #[inline]
+    pub fn native_device(&self) -> NativeDevice {
+        NativeDevice {
+            adapter: self.adapter(),
+        }
+    }
+
+    /// Returns the display server connection that this device was created with.
+    #[inline]
+    pub fn connection(&self) -> Connection {
+        Connection {
+            native_connection: self.native_connection.clone(),
+        }
+    }
+
+    /// Returns the adapter that this device was created with.
+    #[inline]
+    pub fn adapter(&self) -> Adapter {
+        Adapter
+    }
+
+    /// Returns the OpenGL API flavor that this device supports (OpenGL or OpenGL ES).
+    #[inline]
+    pub fn gl_api(&self) -> GLApi {
+        GLApi::GL
+    }
+
+    /// Creates a context descriptor with the given attributes.
+    ///
+    /// Context descriptors are local to this device.
+    #[inline]
+    pub fn create_context_descriptor(
+        &self,
+        attributes: &ContextAttributes,
+    ) -> Result<ContextDescriptor, Error> {
+        unsafe {
+            ContextDescriptor::new(
+                self.native_connection.egl_display,
+                self.gl_api(),
+                attributes,
+                &[
+                    egl::RENDERABLE_TYPE as EGLint,
+                    egl::OPENGL_BIT as EGLint,
+                    egl::COLOR_BUFFER_TYPE as EGLint,
+                    egl::RGB_BUFFER as EGLint,
+                ],
+            )
+        }
+    }
+
+    /// Creates a new OpenGL context.
+    ///
+    /// The context initially has no surface attached. Until a surface is bound to it, rendering
+    /// commands will fail or have no effect.
+    #[inline]
+    pub fn create_context(
+        &self,
+        descriptor: &ContextDescriptor,
+        share_with: Option<&Context>,
+    ) -> Result<Context, Error> {
+        unsafe {
+            let context = EGLBackedContext::new(
+                self.native_connection.egl_display,
+                descriptor,
+                share_with.map(|ctx| &ctx.0),
+                self.gl_api(),
+            )?;
+            context.make_current(self.native_connection.egl_display)?;
+            let mut gl = Gl::from_loader_function(context::get_proc_address);
+            if descriptor.debug {
+                gl_utils::install_debug_message_callback(&mut gl, context.id);
+            }
+            Ok(Context(context, gl))
+        }
+    }
+
+    /// Wraps an `EGLContext` in a native context and returns it. The context must be current.
+    ///
+    /// The context is not retained, as there is no way to do this in the EGL API. Therefore,
+    /// it is the caller's responsibility to ensure that the returned `Context` object remains
+    /// alive as long as the `EGLContext` is.
+    ///
+    /// # Safety
+    ///
+    /// The `EGLContext` must be valid and current, and must outlive the returned `Context`.
+    #[inline]
+    pub unsafe fn create_context_from_native_context(
+        &self,
+        native_context: NativeContext,
+    ) -> Result<Context, Error> {
+        Ok(Context(
+            EGLBackedContext::from_native_context(native_context),
+            Gl::from_loader_function(context::get_proc_address),
+        ))
+    }
+
+    /// Destroys a context.
+    ///
+    /// The context must have been created on this device.
+    pub fn destroy_context(&self, context: &mut Context) -> Result<(), Error> {
+        if let Ok(Some(mut surface)) = self.unbind_surface_from_context(context) {
+            self.destroy_surface(context, &mut surface)?;
+        }
+
+        unsafe {
+            context.0.destroy(self.native_connection.egl_display);
+            Ok(())
+        }
+    }
+
+    /// Given a context, returns its underlying EGL context and attached surfaces.
+    #[inline]
+    pub fn native_context(&self, context: &Context) -> NativeContext {
+        context.0.native_context()
+    }
+
+    /// Returns the descriptor that this context was created with.
+    #[inline]
+    pub fn context_descriptor(&self, context: &Context) -> ContextDescriptor {
+        unsafe {
+            ContextDescriptor::from_egl_context(
+                &context.1,
+                self.native_connection.egl_display,
+                context.0.egl_context,
+                context.0.surface_attributes,
+            )
+        }
+    }
+
+    /// Makes the context the current OpenGL context for this thread.
+    ///
+    /// After calling this function, it is valid to use OpenGL rendering commands.
+    #[inline]
+    pub fn make_context_current(&self, context: &Context) -> Result<(), Error> {
+        unsafe { context.0.make_current(self.native_connection.egl_display) }
+    }
+
+    /// Removes the current OpenGL context from this thread.
+    ///
+    /// After calling this function, OpenGL rendering commands will fail until a new context is
+    /// made current.
+    #[inline]
+    pub fn make_no_context_current(&self) -> Result<(), Error> {
+        unsafe { context::make_no_context_current(self.native_connection.egl_display) }
+    }
+
+    #[inline]
+    pub(crate) fn temporarily_make_context_current(
+        &self,
+        context: &Context,
+    ) -> Result<CurrentContextGuard, Error> {
+        let guard = CurrentContextGuard::new();
+        self.make_context_current(context)?;
+        Ok(guard)
+    }
+
+    /// Returns the attributes that the context descriptor was created with.
+    #[inline]
+    pub fn context_descriptor_attributes(
+        &self,
+        context_descriptor: &ContextDescriptor,
+    ) -> ContextAttributes {
+        unsafe { context_descriptor.attributes(self.native_connection.egl_display) }
+    }
+
+    /// Returns the largest number of samples per pixel that generic surfaces created with this
+    /// context can use.
+    pub fn max_surface_samples(&self, context: &Context) -> Result<u32, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        Ok(gl_utils::max_samples(&context.1))
+    }
+
+    /// Returns whether the context has been lost because of a GPU reset, and if so, whether this
+    /// context caused it.
+    pub fn context_reset_status(&self, context: &Context) -> Result<ContextResetStatus, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        gl_utils::context_reset_status(&context.1, context::get_proc_address)
+    }
+
+    /// Inserts a fence into the command stream of the given context and flushes it.
+    ///
+    /// The fence is signaled once the GPU finishes all commands issued before it. Returns
+    /// `RequiredExtensionUnavailable` if the display doesn't support `EGL_KHR_fence_sync`.
+    pub fn create_fence(&self, context: &Context) -> Result<Fence, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        unsafe { EGLFence::new(&context.1, self.native_connection.egl_display).map(Fence) }
+    }
+
+    /// Blocks the calling thread until the fence is signaled, or until the timeout expires if
+    /// one is given.
+    ///
+    /// Returns true if the fence was signaled and false if the timeout expired first.
+    pub fn client_wait_fence(
+        &self,
+        fence: &Fence,
+        timeout: Option<Duration>,
+    ) -> Result<bool, Error> {
+        unsafe {
+            fence
+                .0
+                .client_wait(self.native_connection.egl_display, timeout)
+        }
+    }
+
+    /// Makes the GPU wait until the fence is signaled before executing any further commands
+    /// issued by the given context.
+    ///
+    /// Returns `RequiredExtensionUnavailable` if the display doesn't support `EGL_KHR_wait_sync`.
+    pub fn server_wait_fence(&self, context: &Context, fence: &Fence) -> Result<(), Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        unsafe { fence.0.server_wait(self.native_connection.egl_display) }
+    }
+
+    /// Destroys a fence.
+    pub fn destroy_fence(&self, fence: &mut Fence) -> Result<(), Error> {
+        unsafe {
+            fence.0.destroy(self.native_connection.egl_display);
+        }
+        Ok(())
+    }
+
+    /// Returns a new file descriptor for the native sync file behind the fence.
+    ///
+    /// Returns `RequiredExtensionUnavailable` unless the display supports
+    /// `EGL_ANDROID_native_fence_sync`.
+    pub fn export_native_fence_fd(&self, fence: &Fence) -> Result<OwnedFd, Error> {
+        unsafe { fence.0.export_native_fd(self.native_connection.egl_display) }
+    }
+
+    /// Wraps a native sync file in a fence that can be waited on in the given context.
+    ///
+    /// This takes ownership of the file descriptor.
+    pub fn import_native_fence_fd(&self, context: &Context, fd: OwnedFd) -> Result<Fence, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        unsafe { EGLFence::from_native_fd(self.native_connection.egl_display, fd).map(Fence) }
+    }
+
+    /// Fetches the address of an OpenGL function associated with this context.
+    ///
+    /// OpenGL functions are local to a context. You should not use OpenGL functions on one context
+    /// with any other context.
+    ///
+    /// This method is typically used with a function like `gl::load_with()` from the `gl` crate to
+    /// load OpenGL function pointers.
+    #[inline]
+    pub fn get_proc_address(&self, _: &Context, symbol_name: &str) -> *const c_void {
+        context::get_proc_address(symbol_name)
+    }
+
+    /// Attaches a surface to a context for rendering.
+    ///
+    /// This function takes ownership of the surface. The surface must have been created with this
+    /// context, or an `IncompatibleSurface` error is returned.
+    ///
+    /// If this function is called with a surface already bound, a `SurfaceAlreadyBound` error is
+    /// returned. To avoid this error, first unbind the existing surface with
+    /// `unbind_surface_from_context`.
+    ///
+    /// If an error is returned, the surface is returned alongside it.
+    #[inline]
+    pub fn bind_surface_to_context(
+        &self,
+        context: &mut Context,
+        surface: Surface,
+    ) -> Result<(), (Error, Surface)> {
+        unsafe {
+            context
+                .0
+                .bind_surface(self.native_connection.egl_display, surface.0)
+                .map_err(|(err, surface)| (err, Surface(surface)))
+        }
+    }
+
+    /// Removes and returns any attached surface from this context.
+    ///
+    /// Any pending OpenGL commands targeting this surface will be automatically flushed, so the
+    /// surface is safe to read from immediately when this function returns.
+    pub fn unbind_surface_from_context(
+        &self,
+        context: &mut Context,
+    ) -> Result<Option<Surface>, Error> {
+        unsafe {
+            context
+                .0
+                .unbind_surface(&context.1, self.native_connection.egl_display)
+                .map(|maybe_surface| maybe_surface.map(Surface))
+        }
+    }
+
+    /// Displays the contents of the currently bound surface to the screen, if
+    /// it is a widget surface.
+    ///
+    /// Widget surfaces are internally double-buffered, so changes to them don't
+    /// show up in their associated widgets until this method is called.
+    pub fn present_bound_surface(&self, context: &mut Context) -> Result<(), Error> {
+        context
+            .0
+            .present_bound_surface(self.native_connection.egl_display)
+    }
+
+    /// Resizes a widget surface.
+    pub fn resize_bound_surface(
+        &self,
+        context: &mut Context,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        context.0.resize_bound_surface(size)
+    }
+
+    /// Returns a unique ID representing a context.
+    ///
+    /// This ID is unique to all currently-allocated contexts. If you destroy a context and create
+    /// a new one, the new context might have the same ID as the destroyed one.
+    #[inline]
+    pub fn context_id(&self, context: &Context) -> ContextID {
+        context.0.id
+    }
+
+    /// Returns various information about the surface attached to a context.
+    ///
+    /// This includes, most notably, the OpenGL framebuffer object needed to render to the surface.
+    #[inline]
+    pub fn context_surface_info(&self, context: &Context) -> Result<Option<SurfaceInfo>, Error> {
+        context.0.surface_info()
+    }
+
+    /// Creates either a generic or a widget surface, depending on the supplied surface type.
+    ///
+    /// Only the given context may ever render to the surface, but generic surfaces can be wrapped
+    /// up in a `SurfaceTexture` for reading by other contexts.
+    pub fn create_surface(
+        &self,
+        context: &Context,
+        surface_access: SurfaceAccess,
+        surface_type: SurfaceType<NativeWidget>,
+    ) -> Result<Surface, Error> {
+        match surface_type {
+            SurfaceType::Generic { size } => {
+                self.create_generic_surface(context, surface_access, &size)
+            }
+            SurfaceType::Widget { .. } => Err(Error::UnsupportedOnThisPlatform),
+        }
+    }
+
+    fn create_generic_surface(
+        &self,
+        context: &Context,
+        surface_access: SurfaceAccess,
+        size: &Size2D<i32>,
+    ) -> Result<Surface, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+
+        let drm_format = match dmabuf::drm_format(context_attributes.surface_format) {
+            Some(drm_format) => drm_format,
+            None => return Err(Error::UnsupportedSurfaceFormat),
+        };
+        let egl_image = self.native_connection.create_egl_image(size, drm_format)?;
+
+        EGLBackedSurface::new_generic_from_egl_image(
+            &context.1,
+            self.native_connection.egl_display,
+            egl_image,
+            context.0.id,
+            &context_attributes,
+            surface_access,
+            size,
+        )
+        .map(Surface)
+    }
+
+    /// Creates a surface texture from an existing generic surface for use with the given context.
+    ///
+    /// The surface texture is local to the supplied context and takes ownership of the surface.
+    /// Destroying the surface texture allows you to retrieve the surface again.
+    ///
+    /// *The supplied context does not have to be the same context that the surface is associated
+    /// with.* This allows you to render to a surface in one context and sample from that surface
+    /// in another context.
+    ///
+    /// Calling this method on a widget surface returns a `WidgetAttached` error.
+    pub fn create_surface_texture(
+        &self,
+        context: &mut Context,
+        surface: Surface,
+    ) -> Result<SurfaceTexture, (Error, Surface)> {
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, surface)),
+        };
+
+        match surface.0.to_surface_texture(&context.1) {
+            Ok(surface_texture) => Ok(SurfaceTexture(surface_texture)),
+            Err((err, surface)) => Err((err, Surface(surface))),
+        }
+    }
+
+    /// Destroys a surface.
+    ///
+    /// The supplied context must be the context the surface is associated with, or this returns
+    /// an `IncompatibleSurface` error.
+    ///
+    /// You must explicitly call this method to dispose of a surface. Otherwise, a panic occurs in
+    /// the `drop` method.
+    pub fn destroy_surface(
+        &self,
+        context: &mut Context,
+        surface: &mut Surface,
+    ) -> Result<(), Error> {
+        let egl_display = self.native_connection.egl_display;
+        let window = surface.0.destroy(&context.1, egl_display, context.0.id)?;
+        debug_assert!(window.is_none());
+        Ok(())
+    }
+
+    /// Destroys a surface texture and returns the underlying surface.
+    ///
+    /// The supplied context must be the same context the surface texture was created with, or an
+    /// `IncompatibleSurfaceTexture` error is returned.
+    ///
+    /// All surface textures must be explicitly destroyed with this function, or a panic will
+    /// occur.
+    pub fn destroy_surface_texture(
+        &self,
+        context: &mut Context,
+        surface_texture: SurfaceTexture,
+    ) -> Result<Surface, (Error, SurfaceTexture)> {
+        match self.temporarily_make_context_current(context) {
+            Ok(_guard) => Ok(Surface(surface_texture.0.destroy(&context.1))),
+            Err(err) => Err((err, surface_texture)),
+        }
+    }
+
+    /// Displays the contents of a widget surface on screen.
+    ///
+    /// Widget surfaces are internally double-buffered, so changes to them don't show up in their
+    /// associated widgets until this method is called.
+    ///
+    /// The supplied context must match the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned.
+    pub fn present_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
+        surface.0.present(
+            self.native_connection.egl_display,
+            context.0.egl_context,
+            &[],
+        )
+    }
+
+    /// Displays the contents of a widget surface on screen, telling the compositor that only the
+    /// given rectangles have changed since the surface was last presented.
+    ///
+    /// The rectangles are relative to the top left corner of the surface. An empty list damages
+    /// the whole surface. If the display supports neither `EGL_KHR_swap_buffers_with_damage` nor
+    /// `EGL_EXT_swap_buffers_with_damage`, this presents the whole surface like
+    /// `present_surface()`.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        surface.0.present(
+            self.native_connection.egl_display,
+            context.0.egl_context,
+            damage,
+        )
+    }
+
+    /// Returns how many frames old the contents of the back buffer of a widget surface are.
+    ///
+    /// An age of 1 means that the back buffer holds the contents that were presented last, 2
+    /// means the contents of the frame before that, and so on. An age of 0 means that the
+    /// contents are undefined, and the whole surface must be redrawn. Returns
+    /// `RequiredExtensionUnavailable` if the display supports neither `EGL_EXT_buffer_age` nor
+    /// `EGL_KHR_partial_update`, or `NoWidgetAttached` for generic surfaces.
+    pub fn surface_buffer_age(&self, context: &Context, surface: &Surface) -> Result<u32, Error> {
+        surface
+            .0
+            .buffer_age(self.native_connection.egl_display, context.0.egl_context)
+    }
+
+    /// Tells the driver that only the given rectangles of a widget surface will be rendered to
+    /// before it's next presented, so that it can avoid loading the rest of the back buffer.
+    ///
+    /// This must be called at most once per frame, before rendering to the surface. The
+    /// rectangles are relative to the top left corner of the surface, and an empty list damages
+    /// the whole surface. Returns `RequiredExtensionUnavailable` if the display doesn't support
+    /// `EGL_KHR_partial_update`, in which case the whole surface can be rendered to as usual.
+    pub fn set_damage_region(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        surface.0.set_damage_region(
+            self.native_connection.egl_display,
+            context.0.egl_context,
+            damage,
+        )
+    }
+
+    /// Sets the minimum number of display refreshes between presentations of a widget surface.
+    ///
+    /// An interval of 0 presents immediately without waiting for vertical sync, and 1 waits for
+    /// one refresh. The interval is kept while the surface is bound to and unbound from contexts.
+    /// Returns `UnsupportedSwapInterval` if the surface's EGL config doesn't support the interval,
+    /// or `NoWidgetAttached` for generic surfaces.
+    pub fn set_swap_interval(
+        &self,
+        context: &Context,
+        surface: &Surface,
+        interval: i32,
+    ) -> Result<(), Error> {
+        surface.0.set_swap_interval(
+            self.native_connection.egl_display,
+            context.0.egl_context,
+            interval,
+        )
+    }
+
+    /// Resizes a widget surface.
+    pub fn resize_surface(
+        &self,
+        _context: &Context,
+        surface: &mut Surface,
+        size: Size2D<i32>,
+    ) -> Result<(), Error> {
+        surface.0.size = size;
+        Ok(())
+    }
+
+    /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
+    ///
+    /// A context created on this device must be current. The surface contents are read back with
+    /// that context, and any changes are written back with it when the guard is dropped.
+    ///
+    /// Returns `SurfaceDataInaccessible` if the surface was created with `SurfaceAccess::GPUOnly`,
+    /// or `NoCurrentContext` if no context on this device is current.
+    #[inline]
+    pub fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Surface,
+    ) -> Result<SurfaceDataGuard<'s>, Error> {
+        surface
+            .0
+            .lock_data(self.native_connection.egl_display)
+            .map(SurfaceDataGuard)
+    }
+
+    /// Reads back a rectangle of pixels from a generic surface.
+    ///
+    /// The rectangle is relative to the top left corner of the surface, and the returned rows are
+    /// ordered from top to bottom in the requested pixel format.
+    ///
+    /// The supplied context must be the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned. Widget surfaces return a `WidgetAttached` error.
+    pub fn read_surface_pixels(
+        &self,
+        context: &mut Context,
+        surface: &Surface,
+        rect: Rect<i32>,
+        format: PixelFormat,
+    ) -> Result<Vec<u8>, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        surface
+            .0
+            .read_pixels(&context.1, context.0.id, rect, format)
+    }
+
+    /// Starts copying the contents of a generic surface into CPU-visible memory.
+    ///
+    /// This does not wait for the GPU. Use `poll_surface_readback()` to check whether the copy
+    /// has finished and `map_surface_readback()` to retrieve the pixels.
+    ///
+    /// The supplied context must be the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned. Widget surfaces return a `WidgetAttached` error.
+    pub fn begin_surface_readback(
+        &self,
+        context: &mut Context,
+        surface: &Surface,
+    ) -> Result<ReadbackHandle, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        surface.0.begin_readback(&context.1, context.0.id)
+    }
+
+    /// Returns true if the GPU has finished a readback, so that `map_surface_readback()` will not
+    /// block.
+    ///
+    /// The supplied context must be the context the readback was started with, or an
+    /// `IncompatibleContext` error is returned.
+    pub fn poll_surface_readback(
+        &self,
+        context: &Context,
+        readback: &ReadbackHandle,
+    ) -> Result<bool, Error> {
+        if readback.context_id != context.0.id {
+            return Err(Error::IncompatibleContext);
+        }
+        let _guard = self.temporarily_make_context_current(context)?;
+        Ok(readback.poll(&context.1))
+    }
+
+    /// Returns the pixels of a readback in RGBA format, with rows ordered from top to bottom, and
+    /// frees the readback.
+    ///
+    /// If the GPU has not yet finished the copy, this waits for it. The supplied context must be
+    /// the context the readback was started with, or an `IncompatibleContext` error is returned.
+    /// On failure, the readback is returned alongside the error, so that it can be mapped again
+    /// or destroyed.
+    pub fn map_surface_readback(
+        &self,
+        context: &mut Context,
+        readback: ReadbackHandle,
+    ) -> Result<Vec<u8>, (Error, ReadbackHandle)> {
+        if readback.context_id != context.0.id {
+            return Err((Error::IncompatibleContext, readback));
+        }
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, readback)),
+        };
+        readback.map(&context.1)
+    }
+
+    /// Frees a readback without mapping it.
+    ///
+    /// The supplied context must be the context the readback was started with, or an
+    /// `IncompatibleContext` error is returned alongside the readback.
+    ///
+    /// You must call either this method or `map_surface_readback()` to dispose of a readback.
+    /// Otherwise, a panic occurs in the `drop` method.
+    pub fn destroy_surface_readback(
+        &self,
+        context: &mut Context,
+        mut readback: ReadbackHandle,
+    ) -> Result<(), (Error, ReadbackHandle)> {
+        if readback.context_id != context.0.id {
+            return Err((Error::IncompatibleContext, readback));
+        }
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, readback)),
+        };
+        readback.destroy(&context.1);
+        Ok(())
+    }
+
+    /// Returns the OpenGL texture target needed to read from this surface texture.
+    ///
+    /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform.
+    #[inline]
+    pub fn surface_gl_texture_target(&self) -> u32 {
+        SURFACE_GL_TEXTURE_TARGET
+    }
+
+    /// Returns various information about the surface, including the framebuffer object needed to
+    /// render to this surface.
+    ///
+    /// Before rendering to a surface attached to a context, you must call `glBindFramebuffer()`
+    /// on the framebuffer object returned by this function. This framebuffer object may or not be
+    /// 0, the default framebuffer, depending on platform.
+    pub fn surface_info(&self, surface: &Surface) -> SurfaceInfo {
+        surface.0.info()
+    }
+
+    /// Returns the OpenGL texture object containing the contents of this surface.
+    ///
+    /// It is only legal to read from, not write to, this texture object.
+    #[inline]
+    pub fn surface_texture_object(&self, surface_texture: &SurfaceTexture) -> Option<Texture> {
+        surface_texture.0.texture_object
+    }
+
+    /// Exports the contents of a generic surface as a Linux dma-buf, so that other processes or
+    /// APIs can use them without copying.
+    ///
+    /// The surface's contents are only guaranteed to be visible through the dma-buf once the
+    /// surface has been unbound from its context, which resolves multisampled surfaces and flushes
+    /// rendering.
+    ///
+    /// Widget surfaces return a `WidgetAttached` error. If the EGL implementation doesn't support
+    /// `EGL_MESA_image_dma_buf_export`, this returns `RequiredExtensionUnavailable`.
+    pub fn export_surface_dmabuf(&self, surface: &Surface) -> Result<DmaBufDescriptor, Error> {
+        surface.0.export_dmabuf(self.native_connection.egl_display)
+    }
+
+    /// Creates a generic surface that renders into an existing Linux dma-buf, such as one exported
+    /// by another process.
+    ///
+    /// The buffer must be in a format that a `SurfaceFormat` corresponds to, or an
+    /// `UnsupportedSurfaceFormat` error is returned. The surface uses the depth, stencil, and
+    /// multisampling attributes of the supplied context. The descriptor's file descriptors are
+    /// duplicated, so the caller keeps ownership of them.
+    pub fn create_surface_from_dmabuf(
+        &self,
+        context: &Context,
+        surface_access: SurfaceAccess,
+        descriptor: &DmaBufDescriptor,
+    ) -> Result<Surface, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+
+        EGLBackedSurface::new_generic_from_dmabuf(
+            &context.1,
+            self.native_connection.egl_display,
+            context.0.id,
+            &context_attributes,
+            surface_access,
+            descriptor,
+        )
+        .map(Surface)
+    }
+
+    /// Wraps an existing Linux dma-buf, such as a frame from a video decoder, in a surface texture
+    /// for use with the given context.
+    ///
+    /// Buffers with several planes, including YUV buffers, are supported as long as the driver can
+    /// sample them; otherwise an `UnsupportedSurfaceFormat` error is returned. Buffers that can
+    /// only be sampled as external textures are bound to `GL_TEXTURE_EXTERNAL_OES`, as reported by
+    /// `surface_texture_gl_texture_target()`. Use `dmabuf_formats()` to find out which formats the
+    /// driver accepts.
+    ///
+    /// Destroying the surface texture returns a surface that can't be rendered to, and which only
+    /// needs to be destroyed. The descriptor's file descriptors are duplicated, so the caller keeps
+    /// ownership of them.
+    pub fn create_surface_texture_from_dmabuf(
+        &self,
+        context: &mut Context,
+        descriptor: &DmaBufDescriptor,
+    ) -> Result<SurfaceTexture, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        EGLBackedSurface::new_texture_from_dmabuf(
+            &context.1,
+            self.native_connection.egl_display,
+            context.0.id,
+            descriptor,
+        )
+        .map(SurfaceTexture)
+    }
+
+    /// Returns the dma-buf formats, and the modifiers for each format, that this device can
+    /// import.
+    ///
+    /// If the EGL implementation doesn't support `EGL_EXT_image_dma_buf_import_modifiers`, this
+    /// returns `RequiredExtensionUnavailable`.
+    pub fn dmabuf_formats(&self) -> Result<Vec<DmaBufFormat>, Error> {
+        unsafe { dmabuf::query_formats(self.native_connection.egl_display) }
+    }
+
+    /// Returns the OpenGL texture target needed to read from the given surface texture.
+    ///
+    /// This is `GL_TEXTURE_EXTERNAL_OES` for surface textures imported from dma-bufs that the
+    /// driver can only sample as external textures, and `GL_TEXTURE_2D` otherwise.
+    #[inline]
+    pub fn surface_texture_gl_texture_target(&self, surface_texture: &SurfaceTexture) -> u32 {
+        surface_texture.0.texture_target
+    }
+}
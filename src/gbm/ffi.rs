@@ -0,0 +1,75 @@
+//! Bindings to `libgbm`, which is loaded at runtime.
+
+#![allow(non_camel_case_types)]
+
+use libc::{dlopen, dlsym, RTLD_LAZY};
+use std::ffi::CStr;
+use std::mem;
+use std::os::raw::{c_int, c_void};
+use std::sync::LazyLock;
+
+pub(crate) const GBM_BO_USE_RENDERING: u32 = 1 << 2;
+
+#[repr(C)]
+pub struct gbm_device {
+    opaque: i32,
+}
+
+#[repr(C)]
+pub struct gbm_bo {
+    opaque: i32,
+}
+
+static GBM_POTENTIAL_SO_NAMES: [&CStr; 2] = [c"libgbm.so.1", c"libgbm.so"];
+
+#[allow(non_snake_case)]
+pub(crate) struct GBMFunctions {
+    pub(crate) CreateDevice: unsafe extern "C" fn(fd: c_int) -> *mut gbm_device,
+    pub(crate) DeviceDestroy: unsafe extern "C" fn(gbm: *mut gbm_device),
+    pub(crate) DeviceIsFormatSupported:
+        unsafe extern "C" fn(gbm: *mut gbm_device, format: u32, usage: u32) -> c_int,
+    pub(crate) BoCreate: unsafe extern "C" fn(
+        gbm: *mut gbm_device,
+        width: u32,
+        height: u32,
+        format: u32,
+        flags: u32,
+    ) -> *mut gbm_bo,
+    pub(crate) BoDestroy: unsafe extern "C" fn(bo: *mut gbm_bo),
+    pub(crate) BoGetPlaneCount: unsafe extern "C" fn(bo: *mut gbm_bo) -> c_int,
+    pub(crate) BoGetFdForPlane: unsafe extern "C" fn(bo: *mut gbm_bo, plane: c_int) -> c_int,
+    pub(crate) BoGetStrideForPlane: unsafe extern "C" fn(bo: *mut gbm_bo, plane: c_int) -> u32,
+    pub(crate) BoGetOffset: unsafe extern "C" fn(bo: *mut gbm_bo, plane: c_int) -> u32,
+    pub(crate) BoGetModifier: unsafe extern "C" fn(bo: *mut gbm_bo) -> u64,
+}
+
+// `None` if `libgbm` couldn't be loaded, or if it's too old to have all the functions we need.
+pub(crate) static GBM_FUNCTIONS: LazyLock<Option<GBMFunctions>> = LazyLock::new(|| unsafe {
+    let handle = GBM_POTENTIAL_SO_NAMES
+        .iter()
+        .map(|soname| dlopen(soname.as_ptr(), RTLD_LAZY))
+        .find(|handle| !handle.is_null())?;
+
+    Some(GBMFunctions {
+        CreateDevice: load(handle, c"gbm_create_device")?,
+        DeviceDestroy: load(handle, c"gbm_device_destroy")?,
+        DeviceIsFormatSupported: load(handle, c"gbm_device_is_format_supported")?,
+        BoCreate: load(handle, c"gbm_bo_create")?,
+        BoDestroy: load(handle, c"gbm_bo_destroy")?,
+        BoGetPlaneCount: load(handle, c"gbm_bo_get_plane_count")?,
+        BoGetFdForPlane: load(handle, c"gbm_bo_get_fd_for_plane")?,
+        BoGetStrideForPlane: load(handle, c"gbm_bo_get_stride_for_plane")?,
+        BoGetOffset: load(handle, c"gbm_bo_get_offset")?,
+        BoGetModifier: load(handle, c"gbm_bo_get_modifier")?,
+    })
+});
+
+// Looks up a function in `libgbm`.
+unsafe fn load<T>(handle: *mut c_void, name: &CStr) -> Option<T> {
+    let symbol = dlsym(handle, name.as_ptr());
+    if symbol.is_null() {
+        None
+    } else {
+        Some(mem::transmute_copy(&symbol))
+    }
+}
@@ -0,0 +1,16 @@
+//! The GBM backend, which renders off-screen into buffer objects allocated on a DRM render node,
+//! without a display server.
+//!
+//! This backend is enabled with the `sm-gbm` feature.
+
+pub mod connection;
+pub mod context;
+pub mod device;
+mod ffi;
+pub mod surface;
+
+crate::implement_interfaces!();
+
+#[cfg(test)]
+#[path = "../tests.rs"]
+mod tests;
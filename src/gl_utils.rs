@@ -1,9 +1,15 @@
 //! Various OpenGL utilities used by the different backends.
 
-use glow::{HasContext, NativeFramebuffer};
+use euclid::default::{Rect, Size2D};
+use glow::{HasContext, NativeFramebuffer, PixelPackData};
 
 use crate::gl;
 use crate::Gl;
+use crate::{ContextID, ContextResetStatus, Error, PixelFormat, SurfaceFormat};
+use log::{log, warn, Level};
+
+use std::mem;
+use std::os::raw::c_void;
 
 #[allow(dead_code)]
 pub(crate) fn create_and_bind_framebuffer(
@@ -46,3 +52,316 @@ pub(crate) fn destroy_framebuffer(gl: &Gl, framebuffer_object: NativeFramebuffer
         gl.delete_framebuffer(framebuffer_object);
     }
 }
+
+// The OpenGL formats used to allocate storage for a surface format.
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub(crate) struct SurfaceGLFormats {
+    // The internal format of the surface's texture.
+    pub(crate) internal_format: u32,
+    // The format and type of pixel data uploaded to the texture.
+    pub(crate) format: u32,
+    pub(crate) data_type: u32,
+    // The internal format of a multisampled color renderbuffer that can be resolved into the
+    // texture, if there is one.
+    pub(crate) renderbuffer_format: Option<u32>,
+}
+
+// Returns the OpenGL formats for a surface format, or `None` if the current context can't render
+// to that format.
+#[allow(dead_code)]
+pub(crate) fn surface_gl_formats(
+    gl: &Gl,
+    surface_format: SurfaceFormat,
+) -> Option<SurfaceGLFormats> {
+    let version = gl.version();
+    let (is_gles, is_gl3) = (version.is_embedded, version.major >= 3);
+    let has_extension = |name: &str| gl.supported_extensions().contains(name);
+
+    let (internal_format, format, data_type, renderbuffer_format) = match surface_format {
+        // Use an unsized internal format so that this works on OpenGL ES 2.0.
+        SurfaceFormat::RGBA8 => (gl::RGBA, gl::RGBA, gl::UNSIGNED_BYTE, Some(gl::RGBA8)),
+        SurfaceFormat::BGRA8 if is_gles => {
+            if !has_extension("GL_EXT_texture_format_BGRA8888") {
+                return None;
+            }
+            // There's no multisampled renderbuffer format that matches `GL_BGRA_EXT` textures.
+            (gl::BGRA, gl::BGRA, gl::UNSIGNED_BYTE, None)
+        }
+        SurfaceFormat::BGRA8 => (gl::RGBA8, gl::BGRA, gl::UNSIGNED_BYTE, Some(gl::RGBA8)),
+        SurfaceFormat::SRGBA8 if is_gles && !is_gl3 => return None,
+        SurfaceFormat::SRGBA8 => (
+            gl::SRGB8_ALPHA8,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            Some(gl::SRGB8_ALPHA8),
+        ),
+        SurfaceFormat::RGB10A2 if is_gles && !is_gl3 => return None,
+        SurfaceFormat::RGB10A2 => (
+            gl::RGB10_A2,
+            gl::RGBA,
+            gl::UNSIGNED_INT_2_10_10_10_REV,
+            Some(gl::RGB10_A2),
+        ),
+        SurfaceFormat::RGBA16F => {
+            let renderable = if is_gles {
+                is_gl3
+                    && (has_extension("GL_EXT_color_buffer_half_float")
+                        || has_extension("GL_EXT_color_buffer_float"))
+            } else {
+                is_gl3 || has_extension("GL_ARB_texture_float")
+            };
+            if !renderable {
+                return None;
+            }
+            (gl::RGBA16F, gl::RGBA, gl::HALF_FLOAT, Some(gl::RGBA16F))
+        }
+    };
+
+    Some(SurfaceGLFormats {
+        internal_format,
+        format,
+        data_type,
+        renderbuffer_format,
+    })
+}
+
+// Returns the maximum number of samples that the current context supports for multisampled
+// renderbuffers, or 0 if it can't create them or resolve them with `glBlitFramebuffer()`.
+#[allow(dead_code)]
+pub(crate) fn max_samples(gl: &Gl) -> u32 {
+    let version = gl.version();
+    let supported = if version.is_embedded {
+        version.major >= 3
+    } else {
+        version.major >= 3
+            || gl
+                .supported_extensions()
+                .contains("GL_ARB_framebuffer_object")
+    };
+    if !supported {
+        return 0;
+    }
+    unsafe { gl.get_parameter_i32(gl::MAX_SAMPLES).max(0) as u32 }
+}
+
+// Returns the reset status of the current context.
+//
+// `glow` doesn't expose `glGetGraphicsResetStatus()`, so it's looked up with the backend's
+// `get_proc_address()` under whichever name the context's version or extensions provide.
+pub(crate) fn context_reset_status<F>(
+    gl: &Gl,
+    mut get_proc_address: F,
+) -> Result<ContextResetStatus, Error>
+where
+    F: FnMut(&str) -> *const c_void,
+{
+    let version = gl.version();
+    let extensions = gl.supported_extensions();
+    let symbol_name = if version.is_embedded {
+        if (version.major, version.minor) >= (3, 2) {
+            "glGetGraphicsResetStatus"
+        } else if extensions.contains("GL_KHR_robustness") {
+            "glGetGraphicsResetStatusKHR"
+        } else if extensions.contains("GL_EXT_robustness") {
+            "glGetGraphicsResetStatusEXT"
+        } else {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+    } else if (version.major, version.minor) >= (4, 5) || extensions.contains("GL_KHR_robustness") {
+        "glGetGraphicsResetStatus"
+    } else if extensions.contains("GL_ARB_robustness") {
+        "glGetGraphicsResetStatusARB"
+    } else {
+        return Err(Error::RequiredExtensionUnavailable);
+    };
+
+    let get_graphics_reset_status = get_proc_address(symbol_name);
+    if get_graphics_reset_status.is_null() {
+        return Err(Error::RequiredExtensionUnavailable);
+    }
+
+    let status = unsafe {
+        let get_graphics_reset_status: extern "system" fn() -> u32 =
+            mem::transmute(get_graphics_reset_status);
+        get_graphics_reset_status()
+    };
+    Ok(match status {
+        gl::NO_ERROR => ContextResetStatus::NoReset,
+        gl::GUILTY_CONTEXT_RESET => ContextResetStatus::Guilty,
+        gl::INNOCENT_CONTEXT_RESET => ContextResetStatus::Innocent,
+        _ => ContextResetStatus::Unknown,
+    })
+}
+
+// Forwards the messages that the current context reports through `KHR_debug` to the `log` crate,
+// tagged with the context's ID.
+#[allow(dead_code)]
+pub(crate) fn install_debug_message_callback(gl: &mut Gl, context_id: ContextID) {
+    let version = gl.version();
+    let supported = if version.is_embedded {
+        (version.major, version.minor) >= (3, 2)
+    } else {
+        (version.major, version.minor) >= (4, 3)
+    } || gl.supported_extensions().contains("GL_KHR_debug");
+    if !supported {
+        warn!("Debug output isn't supported on context {:?}", context_id);
+        return;
+    }
+
+    unsafe {
+        gl.enable(gl::DEBUG_OUTPUT);
+        gl.debug_message_callback(move |source, message_type, id, severity, message| {
+            let level = match severity {
+                gl::DEBUG_SEVERITY_HIGH => Level::Error,
+                gl::DEBUG_SEVERITY_MEDIUM => Level::Warn,
+                gl::DEBUG_SEVERITY_LOW => Level::Info,
+                _ => Level::Debug,
+            };
+            log!(
+                level,
+                "{:?}: {} (source {:#x}, type {:#x}, id {})",
+                context_id,
+                message,
+                source,
+                message_type,
+                id
+            );
+        });
+    }
+}
+
+// Copies the color buffer of a multisampled framebuffer into a single-sampled one of the same
+// size, leaving the framebuffer bindings and scissor test as they were.
+#[allow(dead_code)]
+pub(crate) fn resolve_framebuffer(
+    gl: &Gl,
+    multisampled_framebuffer_object: NativeFramebuffer,
+    resolved_framebuffer_object: NativeFramebuffer,
+    size: Size2D<i32>,
+) {
+    unsafe {
+        let old_read_framebuffer = gl.get_parameter_framebuffer(gl::READ_FRAMEBUFFER_BINDING);
+        let old_draw_framebuffer = gl.get_parameter_framebuffer(gl::DRAW_FRAMEBUFFER_BINDING);
+        let scissor_test_enabled = gl.is_enabled(gl::SCISSOR_TEST);
+
+        gl.bind_framebuffer(gl::READ_FRAMEBUFFER, Some(multisampled_framebuffer_object));
+        gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, Some(resolved_framebuffer_object));
+        if scissor_test_enabled {
+            gl.disable(gl::SCISSOR_TEST);
+        }
+        gl.blit_framebuffer(
+            0,
+            0,
+            size.width,
+            size.height,
+            0,
+            0,
+            size.width,
+            size.height,
+            gl::COLOR_BUFFER_BIT,
+            gl::NEAREST,
+        );
+
+        if scissor_test_enabled {
+            gl.enable(gl::SCISSOR_TEST);
+        }
+        gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, old_draw_framebuffer);
+        gl.bind_framebuffer(gl::READ_FRAMEBUFFER, old_read_framebuffer);
+    }
+}
+
+// Reads back a region of a framebuffer, returning rows from top to bottom in the given format.
+//
+// `rect` is relative to the top left corner of the framebuffer. The framebuffer object must
+// belong to the current context; the read framebuffer and pixel pack state are restored afterward.
+#[allow(dead_code)]
+pub(crate) fn read_framebuffer_pixels(
+    gl: &Gl,
+    framebuffer_object: Option<NativeFramebuffer>,
+    framebuffer_size: Size2D<i32>,
+    rect: Rect<i32>,
+    format: PixelFormat,
+) -> Result<Vec<u8>, Error> {
+    let (max_x, max_y) = match (
+        rect.origin.x.checked_add(rect.size.width),
+        rect.origin.y.checked_add(rect.size.height),
+    ) {
+        (Some(max_x), Some(max_y)) => (max_x, max_y),
+        _ => return Err(Error::InvalidRect),
+    };
+    if rect.min_x() < 0
+        || rect.min_y() < 0
+        || max_x > framebuffer_size.width
+        || max_y > framebuffer_size.height
+    {
+        return Err(Error::InvalidRect);
+    }
+    if rect.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let stride = rect.size.width as usize * 4;
+    let mut data = vec![0; stride * rect.size.height as usize];
+    unsafe {
+        let old_read_framebuffer = gl.get_parameter_framebuffer(gl::READ_FRAMEBUFFER_BINDING);
+        let pack_buffer = gl.get_parameter_buffer(gl::PIXEL_PACK_BUFFER_BINDING);
+        let pack_alignment = gl.get_parameter_i32(gl::PACK_ALIGNMENT);
+        gl.bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffer_object);
+        if pack_buffer.is_some() {
+            gl.bind_buffer(gl::PIXEL_PACK_BUFFER, None);
+        }
+        gl.pixel_store_i32(gl::PACK_ALIGNMENT, 1);
+
+        // OpenGL's origin is the bottom left corner.
+        gl.read_pixels(
+            rect.origin.x,
+            framebuffer_size.height - max_y,
+            rect.size.width,
+            rect.size.height,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            PixelPackData::Slice(Some(&mut data)),
+        );
+
+        gl.pixel_store_i32(gl::PACK_ALIGNMENT, pack_alignment);
+        if pack_buffer.is_some() {
+            gl.bind_buffer(gl::PIXEL_PACK_BUFFER, pack_buffer);
+        }
+        gl.bind_framebuffer(gl::READ_FRAMEBUFFER, old_read_framebuffer);
+    }
+
+    flip_rows(&mut data, stride);
+    Ok(convert_rgba_pixels(data, format))
+}
+
+// Converts between OpenGL's bottom-up row order and the top-down order that CPU code expects.
+#[allow(dead_code)]
+pub(crate) fn flip_rows(data: &mut [u8], stride: usize) {
+    let row_count = data.len() / stride;
+    for row in 0..(row_count / 2) {
+        let (top, bottom) = data.split_at_mut((row_count - row - 1) * stride);
+        top[(row * stride)..((row + 1) * stride)].swap_with_slice(&mut bottom[..stride]);
+    }
+}
+
+// Converts tightly-packed RGBA pixels to BGRA, or vice versa.
+pub(crate) fn swap_red_and_blue(data: &mut [u8]) {
+    for pixel in data.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+}
+
+fn convert_rgba_pixels(mut data: Vec<u8>, format: PixelFormat) -> Vec<u8> {
+    match format {
+        PixelFormat::RGBA8 => data,
+        PixelFormat::BGRA8 => {
+            swap_red_and_blue(&mut data);
+            data
+        }
+        PixelFormat::RGB8 => data
+            .chunks_exact(4)
+            .flat_map(|pixel| pixel[..3].iter().copied())
+            .collect(),
+    }
+}
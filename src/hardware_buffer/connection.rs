@@ -4,8 +4,7 @@
 
 use super::device::{Adapter, Device, NativeDevice};
 use super::surface::NativeWidget;
-use crate::Error;
-use crate::GLApi;
+use crate::{AdapterInfo, Error, GLApi};
 
 #[cfg(android_platform)]
 use super::android_ffi::ANativeWindow;
@@ -75,6 +74,12 @@ impl Connection {
         Ok(Adapter)
     }
 
+    /// Returns an empty list, since this backend has only one adapter.
+    #[inline]
+    pub fn enumerate_adapters(&self) -> Vec<AdapterInfo<Adapter>> {
+        vec![]
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
     /// Device handles are local to a single thread.
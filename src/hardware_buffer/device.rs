@@ -1,20 +1,23 @@
 //! A thread-local handle to the device.
 
 use super::connection::Connection;
-use crate::base::egl::context::{self, CurrentContextGuard};
+use super::context::Fence;
+use crate::base::egl::context::{self, CurrentContextGuard, GenericSurfaceAttributes};
 use crate::base::egl::device::EGL_FUNCTIONS;
 use crate::base::egl::error::ToWindowingApiError;
+use crate::base::egl::fence::EGLFence;
 use crate::base::egl::surface::ExternalEGLSurfaces;
 use crate::context::{ContextID, CREATE_CONTEXT_MUTEX};
 use crate::egl::types::{EGLConfig, EGLDisplay, EGLint};
 use crate::hardware_buffer::surface::SurfaceObjects;
 use crate::surface::Framebuffer;
-use crate::{egl, ContextDescriptor, NativeContext, Surface};
-use crate::{Context, ContextAttributes, Error, GLApi, Gl, SurfaceInfo};
+use crate::{egl, gl_utils, ContextDescriptor, NativeContext, Surface, SurfaceFormat, SurfaceInfo};
+use crate::{Context, ContextAttributes, ContextResetStatus, Error, GLApi, Gl};
 use euclid::default::Size2D;
 use glow::HasContext;
 use std::mem;
 use std::os::raw::c_void;
+use std::time::Duration;
 
 /// Represents a hardware display adapter that can be used for rendering (including the CPU).
 ///
@@ -109,9 +112,15 @@ impl Device {
         &self,
         attributes: &ContextAttributes,
     ) -> Result<ContextDescriptor, Error> {
+        // Surfaces on this backend are always RGBA8.
+        if attributes.surface_format != SurfaceFormat::RGBA8 {
+            return Err(Error::UnsupportedSurfaceFormat);
+        }
+
         unsafe {
             ContextDescriptor::new(
                 self.egl_display,
+                self.gl_api(),
                 attributes,
                 &[
                     egl::COLOR_BUFFER_TYPE as EGLint,
@@ -159,6 +168,11 @@ impl Device {
                 Ok(())
             })?;
 
+            let mut gl = Gl::from_loader_function(context::get_proc_address);
+            if descriptor.debug {
+                gl_utils::install_debug_message_callback(&mut gl, *next_context_id);
+            }
+
             // Wrap up the EGL context.
             let context = Context {
                 egl_context,
@@ -166,7 +180,7 @@ impl Device {
                 pbuffer,
                 framebuffer: Framebuffer::None,
                 context_is_owned: true,
-                gl: Gl::from_loader_function(context::get_proc_address),
+                gl,
             };
             next_context_id.0 += 1;
             Ok(context)
@@ -247,7 +261,13 @@ impl Device {
     /// Returns the descriptor that this context was created with.
     pub fn context_descriptor(&self, context: &Context) -> ContextDescriptor {
         unsafe {
-            ContextDescriptor::from_egl_context(&context.gl, self.egl_display, context.egl_context)
+            ContextDescriptor::from_egl_context(
+                &context.gl,
+                self.egl_display,
+                context.egl_context,
+                // Surfaces on this backend are always single-sampled RGBA8.
+                GenericSurfaceAttributes::default(),
+            )
         }
     }
 
@@ -356,7 +376,7 @@ impl Device {
     /// show up in their associated widgets until this method is called.
     pub fn present_bound_surface(&self, context: &mut Context) -> Result<(), Error> {
         match &context.framebuffer {
-            Framebuffer::Surface(surface) => self.present_surface_inner(context, surface),
+            Framebuffer::Surface(surface) => self.present_surface_inner(context, surface, &[]),
             _ => Ok(()),
         }
     }
@@ -381,6 +401,60 @@ impl Device {
         unsafe { context_descriptor.attributes(self.egl_display) }
     }
 
+    /// Returns the largest number of samples per pixel that generic surfaces created with this
+    /// context can use.
+    ///
+    /// Multisampled generic surfaces aren't supported on this backend, so this always returns 0.
+    #[inline]
+    pub fn max_surface_samples(&self, _: &Context) -> Result<u32, Error> {
+        Ok(0)
+    }
+
+    /// Returns whether the context has been lost because of a GPU reset, and if so, whether this
+    /// context caused it.
+    pub fn context_reset_status(&self, context: &Context) -> Result<ContextResetStatus, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        gl_utils::context_reset_status(&context.gl, context::get_proc_address)
+    }
+
+    /// Inserts a fence into the command stream of the given context and flushes it.
+    ///
+    /// The fence is signaled once the GPU finishes all commands issued before it. Returns
+    /// `RequiredExtensionUnavailable` if the display doesn't support `EGL_KHR_fence_sync`.
+    pub fn create_fence(&self, context: &Context) -> Result<Fence, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        unsafe { EGLFence::new(&context.gl, self.egl_display).map(Fence) }
+    }
+
+    /// Blocks the calling thread until the fence is signaled, or until the timeout expires if
+    /// one is given.
+    ///
+    /// Returns true if the fence was signaled and false if the timeout expired first.
+    pub fn client_wait_fence(
+        &self,
+        fence: &Fence,
+        timeout: Option<Duration>,
+    ) -> Result<bool, Error> {
+        unsafe { fence.0.client_wait(self.egl_display, timeout) }
+    }
+
+    /// Makes the GPU wait until the fence is signaled before executing any further commands
+    /// issued by the given context.
+    ///
+    /// Returns `RequiredExtensionUnavailable` if the display doesn't support `EGL_KHR_wait_sync`.
+    pub fn server_wait_fence(&self, context: &Context, fence: &Fence) -> Result<(), Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        unsafe { fence.0.server_wait(self.egl_display) }
+    }
+
+    /// Destroys a fence.
+    pub fn destroy_fence(&self, fence: &mut Fence) -> Result<(), Error> {
+        unsafe {
+            fence.0.destroy(self.egl_display);
+        }
+        Ok(())
+    }
+
     /// Fetches the address of an OpenGL function associated with this context.
     ///
     /// OpenGL functions are local to a context. You should not use OpenGL functions on one context
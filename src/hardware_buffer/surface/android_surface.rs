@@ -25,7 +25,9 @@ use crate::egl::types::{EGLSurface, EGLint};
 use crate::gl;
 use crate::gl_utils;
 use crate::renderbuffers::Renderbuffers;
-use crate::{Error, SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceType, WindowingApiError};
+use crate::{
+    Error, SurfaceAccess, SurfaceFormat, SurfaceID, SurfaceInfo, SurfaceType, WindowingApiError,
+};
 
 use euclid::default::Size2D;
 use glow::{HasContext, Texture};
@@ -356,6 +358,7 @@ impl Device {
     pub fn surface_info(&self, surface: &Surface) -> SurfaceInfo {
         SurfaceInfo {
             size: surface.size,
+            valid_size: surface.size,
             id: surface.id(),
             context_id: surface.context_id,
             framebuffer_object: match surface.objects {
@@ -364,6 +367,13 @@ impl Device {
                 } => framebuffer_object,
                 SurfaceObjects::Window { .. } => None,
             },
+            format: Some(SurfaceFormat::RGBA8),
+            depth_format: match surface.objects {
+                SurfaceObjects::HardwareBuffer {
+                    ref renderbuffers, ..
+                } => renderbuffers.depth_format(),
+                SurfaceObjects::Window { .. } => None,
+            },
         }
     }
 
@@ -397,3 +407,18 @@ impl Surface {
 pub struct SurfaceDataGuard<'a> {
     phantom: PhantomData<&'a ()>,
 }
+
+// CPU access is not yet supported on this backend, so this guard is never constructed.
+impl<'a> SurfaceDataGuard<'a> {
+    /// Returns the number of bytes per row of the surface.
+    #[inline]
+    pub fn stride(&self) -> usize {
+        0
+    }
+
+    /// Returns a mutable slice of the pixel data in this surface.
+    #[inline]
+    pub fn data(&mut self) -> &mut [u8] {
+        &mut []
+    }
+}
@@ -2,10 +2,16 @@
 
 use crate::base::egl::ffi::EGLImageKHR;
 use crate::context::ContextID;
-use crate::{Context, Device, Error};
+use crate::gl_utils;
+use crate::readback::ReadbackHandle;
+use crate::{Context, Device, Error, PixelFormat};
 
 use crate::base::egl::device::EGL_FUNCTIONS;
-use euclid::default::Size2D;
+use crate::base::egl::surface::with_window_surface_current;
+use crate::base::egl::surface::{
+    query_buffer_age, set_damage_region, set_swap_interval, swap_buffers_with_damage,
+};
+use euclid::default::{Rect, Size2D};
 use glow::Texture;
 use std::fmt::{self, Debug, Formatter};
 use std::marker::PhantomData;
@@ -101,13 +107,110 @@ impl Device {
     /// The supplied context must match the context the surface was created with, or an
     /// `IncompatibleSurface` error is returned.
     pub fn present_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
-        self.present_surface_inner(context, surface)
+        self.present_surface_inner(context, surface, &[])
+    }
+
+    /// Displays the contents of a widget surface on screen, telling the compositor that only the
+    /// given rectangles have changed since the surface was last presented.
+    ///
+    /// The rectangles are relative to the top left corner of the surface. An empty list damages
+    /// the whole surface. If the display supports neither `EGL_KHR_swap_buffers_with_damage` nor
+    /// `EGL_EXT_swap_buffers_with_damage`, this presents the whole surface like
+    /// `present_surface()`.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        self.present_surface_inner(context, surface, damage)
+    }
+
+    /// Returns how many frames old the contents of the back buffer of a widget surface are.
+    ///
+    /// An age of 1 means that the back buffer holds the contents that were presented last, 2
+    /// means the contents of the frame before that, and so on. An age of 0 means that the
+    /// contents are undefined, and the whole surface must be redrawn. Returns
+    /// `RequiredExtensionUnavailable` if the display supports neither `EGL_EXT_buffer_age` nor
+    /// `EGL_KHR_partial_update`, or `NoWidgetAttached` for generic surfaces.
+    pub fn surface_buffer_age(&self, context: &Context, surface: &Surface) -> Result<u32, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface } => unsafe {
+                with_window_surface_current(
+                    self.egl_display,
+                    egl_surface,
+                    context.egl_context,
+                    |egl| query_buffer_age(egl, self.egl_display, egl_surface),
+                )
+            },
+            _ => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Tells the driver that only the given rectangles of a widget surface will be rendered to
+    /// before it's next presented, so that it can avoid loading the rest of the back buffer.
+    ///
+    /// This must be called at most once per frame, before rendering to the surface. The
+    /// rectangles are relative to the top left corner of the surface, and an empty list damages
+    /// the whole surface. Returns `RequiredExtensionUnavailable` if the display doesn't support
+    /// `EGL_KHR_partial_update`, in which case the whole surface can be rendered to as usual.
+    pub fn set_damage_region(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface } => unsafe {
+                with_window_surface_current(
+                    self.egl_display,
+                    egl_surface,
+                    context.egl_context,
+                    |egl| set_damage_region(egl, self.egl_display, egl_surface, damage),
+                )
+            },
+            _ => Err(Error::NoWidgetAttached),
+        }
+    }
+
+    /// Sets the minimum number of display refreshes between presentations of a widget surface.
+    ///
+    /// An interval of 0 presents immediately without waiting for vertical sync, and 1 waits for
+    /// one refresh. Returns `UnsupportedSwapInterval` if the surface's EGL config doesn't support
+    /// the interval, or `NoWidgetAttached` for generic surfaces.
+    pub fn set_swap_interval(
+        &self,
+        context: &Context,
+        surface: &Surface,
+        interval: i32,
+    ) -> Result<(), Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        match surface.objects {
+            SurfaceObjects::Window { egl_surface } => unsafe {
+                with_window_surface_current(
+                    self.egl_display,
+                    egl_surface,
+                    context.egl_context,
+                    |egl| set_swap_interval(egl, self.egl_display, egl_surface, interval),
+                )
+            },
+            _ => Err(Error::NoWidgetAttached),
+        }
     }
 
     pub(crate) fn present_surface_inner(
         &self,
         context: &Context,
         surface: &Surface,
+        damage: &[Rect<i32>],
     ) -> Result<(), Error> {
         if context.id != surface.context_id {
             return Err(Error::IncompatibleSurface);
@@ -116,7 +219,7 @@ impl Device {
         EGL_FUNCTIONS.with(|egl| unsafe {
             match surface.objects {
                 SurfaceObjects::Window { egl_surface } => {
-                    egl.SwapBuffers(self.egl_display, egl_surface);
+                    swap_buffers_with_damage(egl, self.egl_display, egl_surface, damage);
                     Ok(())
                 }
                 _ => Err(Error::NoWidgetAttached),
@@ -134,4 +237,126 @@ impl Device {
         surface.resize(size);
         Ok(())
     }
+
+    /// Reads back a rectangle of pixels from a generic surface.
+    ///
+    /// The rectangle is relative to the top left corner of the surface, and the returned rows are
+    /// ordered from top to bottom in the requested pixel format.
+    ///
+    /// The supplied context must be the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned. Widget surfaces return a `WidgetAttached` error.
+    pub fn read_surface_pixels(
+        &self,
+        context: &mut Context,
+        surface: &Surface,
+        rect: Rect<i32>,
+        format: PixelFormat,
+    ) -> Result<Vec<u8>, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let framebuffer_object = match surface.objects {
+            SurfaceObjects::HardwareBuffer {
+                framebuffer_object, ..
+            } => framebuffer_object,
+            SurfaceObjects::Window { .. } => return Err(Error::WidgetAttached),
+        };
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        gl_utils::read_framebuffer_pixels(
+            &context.gl,
+            framebuffer_object,
+            surface.size,
+            rect,
+            format,
+        )
+    }
+
+    /// Starts copying the contents of a generic surface into CPU-visible memory.
+    ///
+    /// This does not wait for the GPU. Use `poll_surface_readback()` to check whether the copy
+    /// has finished and `map_surface_readback()` to retrieve the pixels.
+    ///
+    /// The supplied context must be the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned. Widget surfaces return a `WidgetAttached` error.
+    pub fn begin_surface_readback(
+        &self,
+        context: &mut Context,
+        surface: &Surface,
+    ) -> Result<ReadbackHandle, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let framebuffer_object = match surface.objects {
+            SurfaceObjects::HardwareBuffer {
+                framebuffer_object, ..
+            } => framebuffer_object,
+            SurfaceObjects::Window { .. } => return Err(Error::WidgetAttached),
+        };
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        ReadbackHandle::new(&context.gl, context.id, framebuffer_object, surface.size)
+    }
+
+    /// Returns true if the GPU has finished a readback, so that `map_surface_readback()` will not
+    /// block.
+    ///
+    /// The supplied context must be the context the readback was started with, or an
+    /// `IncompatibleContext` error is returned.
+    pub fn poll_surface_readback(
+        &self,
+        context: &Context,
+        readback: &ReadbackHandle,
+    ) -> Result<bool, Error> {
+        if readback.context_id != context.id {
+            return Err(Error::IncompatibleContext);
+        }
+        let _guard = self.temporarily_make_context_current(context)?;
+        Ok(readback.poll(&context.gl))
+    }
+
+    /// Returns the pixels of a readback in RGBA format, with rows ordered from top to bottom, and
+    /// frees the readback.
+    ///
+    /// If the GPU has not yet finished the copy, this waits for it. The supplied context must be
+    /// the context the readback was started with, or an `IncompatibleContext` error is returned.
+    /// On failure, the readback is returned alongside the error, so that it can be mapped again
+    /// or destroyed.
+    pub fn map_surface_readback(
+        &self,
+        context: &mut Context,
+        readback: ReadbackHandle,
+    ) -> Result<Vec<u8>, (Error, ReadbackHandle)> {
+        if readback.context_id != context.id {
+            return Err((Error::IncompatibleContext, readback));
+        }
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, readback)),
+        };
+        readback.map(&context.gl)
+    }
+
+    /// Frees a readback without mapping it.
+    ///
+    /// The supplied context must be the context the readback was started with, or an
+    /// `IncompatibleContext` error is returned alongside the readback.
+    ///
+    /// You must call either this method or `map_surface_readback()` to dispose of a readback.
+    /// Otherwise, a panic occurs in the `drop` method.
+    pub fn destroy_surface_readback(
+        &self,
+        context: &mut Context,
+        mut readback: ReadbackHandle,
+    ) -> Result<(), (Error, ReadbackHandle)> {
+        if readback.context_id != context.id {
+            return Err((Error::IncompatibleContext, readback));
+        }
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, readback)),
+        };
+        readback.destroy(&context.gl);
+        Ok(())
+    }
 }
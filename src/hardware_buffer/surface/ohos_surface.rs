@@ -18,7 +18,7 @@ use crate::egl::types::{EGLSurface, EGLint};
 use crate::gl;
 use crate::gl_utils;
 use crate::renderbuffers::Renderbuffers;
-use crate::{Error, SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceType};
+use crate::{Error, SurfaceAccess, SurfaceFormat, SurfaceID, SurfaceInfo, SurfaceType};
 
 use super::super::context::Context;
 use super::super::device::Device;
@@ -353,6 +353,7 @@ impl Device {
     pub fn surface_info(&self, surface: &Surface) -> SurfaceInfo {
         SurfaceInfo {
             size: surface.size,
+            valid_size: surface.size,
             id: surface.id(),
             context_id: surface.context_id,
             framebuffer_object: match surface.objects {
@@ -361,6 +362,13 @@ impl Device {
                 } => framebuffer_object,
                 SurfaceObjects::Window { .. } => None,
             },
+            format: Some(SurfaceFormat::RGBA8),
+            depth_format: match surface.objects {
+                SurfaceObjects::HardwareBuffer {
+                    ref renderbuffers, ..
+                } => renderbuffers.depth_format(),
+                SurfaceObjects::Window { .. } => None,
+            },
         }
     }
 
@@ -394,3 +402,18 @@ impl Surface {
 pub struct SurfaceDataGuard<'a> {
     phantom: PhantomData<&'a ()>,
 }
+
+// CPU access is not yet supported on this backend, so this guard is never constructed.
+impl<'a> SurfaceDataGuard<'a> {
+    /// Returns the number of bytes per row of the surface.
+    #[inline]
+    pub fn stride(&self) -> usize {
+        0
+    }
+
+    /// Returns a mutable slice of the pixel data in this surface.
+    #[inline]
+    pub fn data(&mut self) -> &mut [u8] {
+        &mut []
+    }
+}
@@ -3,6 +3,8 @@
 use crate::Gl;
 use glow::HasContext;
 
+use std::path::PathBuf;
+
 /// The API (OpenGL or OpenGL ES).
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum GLApi {
@@ -40,3 +42,34 @@ impl GLVersion {
         }
     }
 }
+
+/// Describes an adapter returned by `Connection::enumerate_adapters()`.
+#[derive(Clone, Debug)]
+pub struct AdapterInfo<Adapter> {
+    /// The adapter itself, which can be passed to `Connection::create_device()`.
+    pub adapter: Adapter,
+    /// The path of the DRM render node that the adapter renders with, if known.
+    pub drm_render_node: Option<PathBuf>,
+    /// The name of the company that makes the adapter, if known.
+    pub vendor: Option<String>,
+    /// The name of the adapter, if known.
+    pub renderer: Option<String>,
+    /// True if the adapter renders on the CPU.
+    pub software: bool,
+}
+
+impl<Adapter> AdapterInfo<Adapter> {
+    // Replaces the adapter, keeping the information about it.
+    pub(crate) fn map_adapter<F, NewAdapter>(self, f: F) -> AdapterInfo<NewAdapter>
+    where
+        F: FnOnce(Adapter) -> NewAdapter,
+    {
+        AdapterInfo {
+            adapter: f(self.adapter),
+            drm_render_node: self.drm_render_node,
+            vendor: self.vendor,
+            renderer: self.renderer,
+            software: self.software,
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Sharing surfaces between processes over Unix domain sockets.
+//!
+//! A *producer* process renders into a generic surface and sends it with a `SurfaceSender`. The
+//! surface's contents are exported as a Linux dma-buf, and its file descriptors are passed over
+//! the socket. A *consumer* process receives the buffer with a `SurfaceReceiver`, which wraps it
+//! in a surface texture on the consumer's device, so no pixels are copied.
+//!
+//! The producer and consumer share the buffer's memory, so the producer must not render to a
+//! surface it has sent until the consumer has *released* it. Once the consumer is done with a
+//! surface texture, it destroys it and calls `SurfaceReceiver::release()`, and the producer finds
+//! out with `SurfaceSender::wait_for_release()` or `SurfaceSender::try_receive_release()`.
+
+use crate::device::Device as DeviceAPI;
+use crate::{DmaBufDescriptor, Error};
+
+use euclid::default::Size2D;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::raw::c_void;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::ptr;
+
+// The most planes that a buffer can have.
+const MAX_PLANES: usize = 4;
+
+// Every message has the same size, so that message boundaries can be found on stream sockets.
+//
+// The layout is, in native byte order: the message kind (u32), the plane count (u32), the surface
+// ID (u64), the width and height (i32), the fourcc (u32), padding (u32), the modifier (u64), and
+// the strides and offsets of each plane (u32). Release messages only use the kind and the ID.
+const MESSAGE_SIZE: usize = 40 + MAX_PLANES * 8;
+
+const MESSAGE_KIND_SURFACE: u32 = 1;
+const MESSAGE_KIND_RELEASE: u32 = 2;
+
+/// Identifies a surface that has been sent to another process.
+///
+/// IDs are assigned by the `SurfaceSender` and are unique for the lifetime of the sender.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SharedSurfaceID(pub u64);
+
+/// The producer's end of a connection, which sends surfaces to another process.
+#[derive(Debug)]
+pub struct SurfaceSender {
+    socket: UnixStream,
+    next_id: u64,
+}
+
+/// The consumer's end of a connection, which receives surfaces from another process.
+#[derive(Debug)]
+pub struct SurfaceReceiver {
+    socket: UnixStream,
+}
+
+/// Creates a connected sender and receiver.
+///
+/// This is useful when the consumer process is spawned by the producer (or vice versa), since
+/// the underlying sockets can be inherited across `fork()`.
+pub fn channel() -> Result<(SurfaceSender, SurfaceReceiver), Error> {
+    let (sender_socket, receiver_socket) = UnixStream::pair().map_err(ipc_error)?;
+    Ok((
+        SurfaceSender::new(sender_socket),
+        SurfaceReceiver::new(receiver_socket),
+    ))
+}
+
+impl SurfaceSender {
+    /// Wraps a Unix domain socket that is connected to the consumer process.
+    #[inline]
+    pub fn new(socket: UnixStream) -> SurfaceSender {
+        SurfaceSender { socket, next_id: 0 }
+    }
+
+    /// Returns the underlying socket.
+    #[inline]
+    pub fn socket(&self) -> &UnixStream {
+        &self.socket
+    }
+
+    /// Sends the contents of a generic surface to the consumer.
+    ///
+    /// The surface should be unbound from its context first, so that rendering has been flushed.
+    /// The surface remains owned by the caller, but it must not be rendered to or destroyed until
+    /// the consumer releases it, since the consumer reads from the same memory.
+    pub fn send<Device>(
+        &mut self,
+        device: &Device,
+        surface: &Device::Surface,
+    ) -> Result<SharedSurfaceID, Error>
+    where
+        Device: DeviceAPI,
+    {
+        let descriptor = device.export_surface_dmabuf(surface)?;
+        let plane_count = descriptor.fds.len();
+        if plane_count == 0 || plane_count > MAX_PLANES {
+            return Err(Error::IpcFailed(io::ErrorKind::InvalidData));
+        }
+
+        let id = SharedSurfaceID(self.next_id);
+        self.next_id += 1;
+
+        let mut message = MessageWriter::new(MESSAGE_KIND_SURFACE, plane_count as u32, id);
+        message.write_i32(descriptor.size.width);
+        message.write_i32(descriptor.size.height);
+        message.write_u32(descriptor.fourcc);
+        message.write_u32(0);
+        message.write_u64(descriptor.modifier);
+        for plane in 0..MAX_PLANES {
+            message.write_u32(descriptor.strides.get(plane).copied().unwrap_or(0));
+        }
+        for plane in 0..MAX_PLANES {
+            message.write_u32(descriptor.offsets.get(plane).copied().unwrap_or(0));
+        }
+
+        let fds: Vec<RawFd> = descriptor.fds.iter().map(AsRawFd::as_raw_fd).collect();
+        send_message(&self.socket, &message.finish(), &fds).map_err(ipc_error)?;
+        Ok(id)
+    }
+
+    /// Blocks until the consumer releases a surface, and returns its ID.
+    pub fn wait_for_release(&mut self) -> Result<SharedSurfaceID, Error> {
+        let mut bytes = [0; MESSAGE_SIZE];
+        receive_message(&self.socket, &mut bytes, 0).map_err(ipc_error)?;
+        parse_release(&bytes)
+    }
+
+    /// Returns the ID of a surface that the consumer has released, or `None` if no surface has
+    /// been released since the last call.
+    ///
+    /// This does not block.
+    pub fn try_receive_release(&mut self) -> Result<Option<SharedSurfaceID>, Error> {
+        let mut bytes = [0; MESSAGE_SIZE];
+        match receive_message(&self.socket, &mut bytes, libc::MSG_DONTWAIT) {
+            Ok(_) => parse_release(&bytes).map(Some),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(err) => Err(ipc_error(err)),
+        }
+    }
+}
+
+impl SurfaceReceiver {
+    /// Wraps a Unix domain socket that is connected to the producer process.
+    #[inline]
+    pub fn new(socket: UnixStream) -> SurfaceReceiver {
+        SurfaceReceiver { socket }
+    }
+
+    /// Returns the underlying socket.
+    #[inline]
+    pub fn socket(&self) -> &UnixStream {
+        &self.socket
+    }
+
+    /// Blocks until the producer sends a surface, and wraps it in a surface texture for use with
+    /// the given context.
+    ///
+    /// When the consumer is done with the surface texture, it should destroy it with
+    /// `destroy_surface_texture()`, destroy the returned surface, and then call `release()` with
+    /// the returned ID so that the producer can reuse the surface.
+    pub fn receive<Device>(
+        &mut self,
+        device: &Device,
+        context: &mut Device::Context,
+    ) -> Result<(SharedSurfaceID, Device::SurfaceTexture), Error>
+    where
+        Device: DeviceAPI,
+    {
+        let mut bytes = [0; MESSAGE_SIZE];
+        let fds = receive_message(&self.socket, &mut bytes, 0).map_err(ipc_error)?;
+
+        let mut message = MessageReader::new(&bytes);
+        let (kind, plane_count, id) = message.read_header();
+        if kind != MESSAGE_KIND_SURFACE || plane_count as usize != fds.len() {
+            return Err(Error::IpcFailed(io::ErrorKind::InvalidData));
+        }
+        let size = Size2D::new(message.read_i32(), message.read_i32());
+        let fourcc = message.read_u32();
+        message.read_u32();
+        let modifier = message.read_u64();
+        let strides: Vec<u32> = (0..MAX_PLANES).map(|_| message.read_u32()).collect();
+        let offsets: Vec<u32> = (0..MAX_PLANES).map(|_| message.read_u32()).collect();
+
+        let descriptor = DmaBufDescriptor {
+            size,
+            fourcc,
+            modifier,
+            strides: strides[0..fds.len()].to_vec(),
+            offsets: offsets[0..fds.len()].to_vec(),
+            fds,
+        };
+        let surface_texture = device.create_surface_texture_from_dmabuf(context, &descriptor)?;
+        Ok((id, surface_texture))
+    }
+
+    /// Tells the producer that the consumer is done with a surface.
+    pub fn release(&mut self, id: SharedSurfaceID) -> Result<(), Error> {
+        let message = MessageWriter::new(MESSAGE_KIND_RELEASE, 0, id);
+        send_message(&self.socket, &message.finish(), &[]).map_err(ipc_error)
+    }
+}
+
+fn parse_release(bytes: &[u8; MESSAGE_SIZE]) -> Result<SharedSurfaceID, Error> {
+    match MessageReader::new(bytes).read_header() {
+        (MESSAGE_KIND_RELEASE, _, id) => Ok(id),
+        _ => Err(Error::IpcFailed(io::ErrorKind::InvalidData)),
+    }
+}
+
+fn ipc_error(err: io::Error) -> Error {
+    Error::IpcFailed(err.kind())
+}
+
+struct MessageWriter {
+    bytes: Vec<u8>,
+}
+
+impl MessageWriter {
+    fn new(kind: u32, plane_count: u32, id: SharedSurfaceID) -> MessageWriter {
+        let mut message = MessageWriter {
+            bytes: Vec::with_capacity(MESSAGE_SIZE),
+        };
+        message.write_u32(kind);
+        message.write_u32(plane_count);
+        message.write_u64(id.0);
+        message
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_ne_bytes());
+    }
+
+    fn write_i32(&mut self, value: i32) {
+        self.bytes.extend_from_slice(&value.to_ne_bytes());
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_ne_bytes());
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.bytes.resize(MESSAGE_SIZE, 0);
+        self.bytes
+    }
+}
+
+struct MessageReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> MessageReader<'a> {
+    fn new(bytes: &'a [u8]) -> MessageReader<'a> {
+        MessageReader { bytes }
+    }
+
+    fn read_header(&mut self) -> (u32, u32, SharedSurfaceID) {
+        (
+            self.read_u32(),
+            self.read_u32(),
+            SharedSurfaceID(self.read_u64()),
+        )
+    }
+
+    fn read_u32(&mut self) -> u32 {
+        u32::from_ne_bytes(self.read())
+    }
+
+    fn read_i32(&mut self) -> i32 {
+        i32::from_ne_bytes(self.read())
+    }
+
+    fn read_u64(&mut self) -> u64 {
+        u64::from_ne_bytes(self.read())
+    }
+
+    fn read<const N: usize>(&mut self) -> [u8; N] {
+        let (value, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        value.try_into().unwrap()
+    }
+}
+
+// Sends a message, with the given file descriptors attached as `SCM_RIGHTS` ancillary data.
+fn send_message(socket: &UnixStream, bytes: &[u8], fds: &[RawFd]) -> io::Result<()> {
+    unsafe {
+        let mut iov = libc::iovec {
+            iov_base: bytes.as_ptr() as *mut c_void,
+            iov_len: bytes.len(),
+        };
+        let mut message: libc::msghdr = mem::zeroed();
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+
+        // Use `u64`s so that the control buffer is aligned for `cmsghdr`.
+        let fds_size = mem::size_of_val(fds) as u32;
+        let mut control = vec![0u64; (libc::CMSG_SPACE(fds_size) as usize).div_ceil(8)];
+        if !fds.is_empty() {
+            message.msg_control = control.as_mut_ptr() as *mut c_void;
+            message.msg_controllen = libc::CMSG_SPACE(fds_size) as _;
+            let header = libc::CMSG_FIRSTHDR(&message);
+            (*header).cmsg_level = libc::SOL_SOCKET;
+            (*header).cmsg_type = libc::SCM_RIGHTS;
+            (*header).cmsg_len = libc::CMSG_LEN(fds_size) as _;
+            ptr::copy_nonoverlapping(
+                fds.as_ptr(),
+                libc::CMSG_DATA(header) as *mut RawFd,
+                fds.len(),
+            );
+        }
+
+        let sent = libc::sendmsg(socket.as_raw_fd(), &message, libc::MSG_NOSIGNAL);
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // The file descriptors went out with the first byte, so the rest can be written normally.
+        (&*socket).write_all(&bytes[sent as usize..])
+    }
+}
+
+// Receives a message, and returns the file descriptors that were attached to it.
+fn receive_message(
+    socket: &UnixStream,
+    bytes: &mut [u8],
+    flags: libc::c_int,
+) -> io::Result<Vec<OwnedFd>> {
+    unsafe {
+        let mut iov = libc::iovec {
+            iov_base: bytes.as_mut_ptr() as *mut c_void,
+            iov_len: bytes.len(),
+        };
+        let mut message: libc::msghdr = mem::zeroed();
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+
+        let fds_size = (MAX_PLANES * mem::size_of::<RawFd>()) as u32;
+        let mut control = vec![0u64; (libc::CMSG_SPACE(fds_size) as usize).div_ceil(8)];
+        message.msg_control = control.as_mut_ptr() as *mut c_void;
+        message.msg_controllen = libc::CMSG_SPACE(fds_size) as _;
+
+        let received = libc::recvmsg(
+            socket.as_raw_fd(),
+            &mut message,
+            flags | libc::MSG_CMSG_CLOEXEC,
+        );
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Take ownership of the file descriptors first, so that they're closed on error.
+        let mut fds = vec![];
+        let mut header = libc::CMSG_FIRSTHDR(&message);
+        while !header.is_null() {
+            if (*header).cmsg_level == libc::SOL_SOCKET && (*header).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(header) as *const RawFd;
+                let data_size = (*header).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                for index in 0..(data_size / mem::size_of::<RawFd>()) {
+                    fds.push(OwnedFd::from_raw_fd(ptr::read_unaligned(data.add(index))));
+                }
+            }
+            header = libc::CMSG_NXTHDR(&message, header);
+        }
+
+        if received == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if message.msg_flags & libc::MSG_CTRUNC != 0 {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+
+        (&*socket).read_exact(&mut bytes[received as usize..])?;
+        Ok(fds)
+    }
+}
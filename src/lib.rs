@@ -24,15 +24,24 @@ pub mod chains;
 pub mod connection;
 mod context;
 pub mod device;
+#[cfg(free_unix)]
+mod dmabuf;
+#[cfg(free_unix)]
+pub mod egl_device;
 pub mod error;
+#[cfg(gbm_platform)]
+pub mod gbm;
 mod gl_utils;
 #[cfg(any(android_platform, ohos_platform))]
 pub mod hardware_buffer;
 mod info;
+#[cfg(free_unix)]
+pub mod ipc;
 pub mod macros;
 #[cfg(free_unix)]
 pub mod mesa_surfaceless;
 pub mod multi;
+mod readback;
 mod renderbuffers;
 mod surface;
 #[cfg(all(x11_platform, not(wayland_default)))]
@@ -57,14 +66,20 @@ pub use wayland as default;
 #[cfg(all(windows_platform, not(angle_default)))]
 pub use wgl as default;
 
-pub use crate::context::{ContextAttributeFlags, ContextAttributes, ContextID};
+pub use crate::context::{ContextAttributeFlags, ContextAttributes, ContextID, ContextResetStatus};
+#[cfg(free_unix)]
+pub use crate::dmabuf::{DmaBufDescriptor, DmaBufFormat, DmaBufModifier};
 pub use crate::error::{Error, WindowingApiError};
-pub use crate::info::{GLApi, GLVersion};
-pub use crate::surface::{SurfaceAccess, SurfaceID, SurfaceInfo, SurfaceType, SystemSurfaceInfo};
+pub use crate::info::{AdapterInfo, GLApi, GLVersion};
+pub use crate::readback::ReadbackHandle;
+pub use crate::surface::{
+    DepthFormat, PixelFormat, SurfaceAccess, SurfaceFormat, SurfaceID, SurfaceInfo, SurfaceType,
+    SystemSurfaceInfo,
+};
 pub use default::connection::{Connection, NativeConnection};
-pub use default::context::{Context, ContextDescriptor, NativeContext};
+pub use default::context::{Context, ContextDescriptor, Fence, NativeContext};
 pub use default::device::{Adapter, Device, NativeDevice};
-pub use default::surface::{NativeWidget, Surface, SurfaceTexture};
+pub use default::surface::{NativeWidget, Surface, SurfaceDataGuard, SurfaceTexture};
 pub(crate) use glow::{self as gl, Context as Gl};
 pub(crate) use macros::implement_interfaces;
 
@@ -35,17 +35,20 @@ macro_rules! implement_interfaces {
     () => {
         mod implementation {
             use super::connection::{Connection, NativeConnection};
-            use super::context::{Context, ContextDescriptor, NativeContext};
+            use super::context::{Context, ContextDescriptor, Fence, NativeContext};
             use super::device::{Adapter, Device, NativeDevice};
-            use super::surface::{NativeWidget, Surface, SurfaceTexture};
-            use euclid::default::Size2D;
+            use super::surface::{NativeWidget, Surface, SurfaceDataGuard, SurfaceTexture};
+            use euclid::default::{Rect, Size2D};
             use glow::Texture;
             use std::os::raw::c_void;
+            use std::time::Duration;
             use $crate::connection::Connection as ConnectionInterface;
             use $crate::device::Device as DeviceInterface;
-            use $crate::info::GLApi;
+            use $crate::device::SurfaceDataGuard as SurfaceDataGuardInterface;
+            use $crate::info::{AdapterInfo, GLApi};
             use $crate::Error;
-            use $crate::{ContextAttributes, ContextID, SurfaceAccess, SurfaceInfo, SurfaceType};
+            use $crate::{ContextAttributes, ContextID, ContextResetStatus};
+            use $crate::{PixelFormat, ReadbackHandle, SurfaceAccess, SurfaceInfo, SurfaceType};
 
             impl ConnectionInterface for Connection {
                 type Adapter = Adapter;
@@ -89,6 +92,11 @@ macro_rules! implement_interfaces {
                     Connection::create_software_adapter(self)
                 }
 
+                #[inline]
+                fn enumerate_adapters(&self) -> Vec<AdapterInfo<Self::Adapter>> {
+                    Connection::enumerate_adapters(self)
+                }
+
                 #[inline]
                 fn create_device(&self, adapter: &Adapter) -> Result<Self::Device, Error> {
                     Connection::create_device(self, adapter)
@@ -153,6 +161,8 @@ macro_rules! implement_interfaces {
                 type NativeContext = NativeContext;
                 type Surface = Surface;
                 type SurfaceTexture = SurfaceTexture;
+                type SurfaceDataGuard<'a> = SurfaceDataGuard<'a>;
+                type Fence = Fence;
 
                 // device.rs
 
@@ -232,6 +242,19 @@ macro_rules! implement_interfaces {
                     Device::context_descriptor_attributes(self, context_descriptor)
                 }
 
+                #[inline]
+                fn max_surface_samples(&self, context: &Self::Context) -> Result<u32, Error> {
+                    Device::max_surface_samples(self, context)
+                }
+
+                #[inline]
+                fn context_reset_status(
+                    &self,
+                    context: &Self::Context,
+                ) -> Result<ContextResetStatus, Error> {
+                    Device::context_reset_status(self, context)
+                }
+
                 #[inline]
                 fn get_proc_address(
                     &self,
@@ -276,6 +299,53 @@ macro_rules! implement_interfaces {
                     Device::native_context(self, context)
                 }
 
+                #[inline]
+                fn create_fence(&self, context: &Self::Context) -> Result<Self::Fence, Error> {
+                    Device::create_fence(self, context)
+                }
+
+                #[inline]
+                fn client_wait_fence(
+                    &self,
+                    fence: &Self::Fence,
+                    timeout: Option<Duration>,
+                ) -> Result<bool, Error> {
+                    Device::client_wait_fence(self, fence, timeout)
+                }
+
+                #[inline]
+                fn server_wait_fence(
+                    &self,
+                    context: &Self::Context,
+                    fence: &Self::Fence,
+                ) -> Result<(), Error> {
+                    Device::server_wait_fence(self, context, fence)
+                }
+
+                #[inline]
+                fn destroy_fence(&self, fence: &mut Self::Fence) -> Result<(), Error> {
+                    Device::destroy_fence(self, fence)
+                }
+
+                #[cfg(free_unix)]
+                #[inline]
+                fn export_native_fence_fd(
+                    &self,
+                    fence: &Self::Fence,
+                ) -> Result<std::os::unix::io::OwnedFd, Error> {
+                    Device::export_native_fence_fd(self, fence)
+                }
+
+                #[cfg(free_unix)]
+                #[inline]
+                fn import_native_fence_fd(
+                    &self,
+                    context: &Self::Context,
+                    fd: std::os::unix::io::OwnedFd,
+                ) -> Result<Self::Fence, Error> {
+                    Device::import_native_fence_fd(self, context, fd)
+                }
+
                 // surface.rs
 
                 #[inline]
@@ -334,6 +404,45 @@ macro_rules! implement_interfaces {
                     Device::present_surface(self, context, surface)
                 }
 
+                #[inline]
+                fn present_surface_with_damage(
+                    &self,
+                    context: &Self::Context,
+                    surface: &mut Self::Surface,
+                    damage: &[Rect<i32>],
+                ) -> Result<(), Error> {
+                    Device::present_surface_with_damage(self, context, surface, damage)
+                }
+
+                #[inline]
+                fn surface_buffer_age(
+                    &self,
+                    context: &Self::Context,
+                    surface: &Self::Surface,
+                ) -> Result<u32, Error> {
+                    Device::surface_buffer_age(self, context, surface)
+                }
+
+                #[inline]
+                fn set_damage_region(
+                    &self,
+                    context: &Self::Context,
+                    surface: &mut Self::Surface,
+                    damage: &[Rect<i32>],
+                ) -> Result<(), Error> {
+                    Device::set_damage_region(self, context, surface, damage)
+                }
+
+                #[inline]
+                fn set_swap_interval(
+                    &self,
+                    context: &Self::Context,
+                    surface: &Self::Surface,
+                    interval: i32,
+                ) -> Result<(), Error> {
+                    Device::set_swap_interval(self, context, surface, interval)
+                }
+
                 #[inline]
                 fn resize_bound_surface(
                     &self,
@@ -365,6 +474,118 @@ macro_rules! implement_interfaces {
                 ) -> Option<Texture> {
                     Device::surface_texture_object(self, surface_texture)
                 }
+
+                #[inline]
+                fn lock_surface_data<'s>(
+                    &self,
+                    surface: &'s mut Self::Surface,
+                ) -> Result<Self::SurfaceDataGuard<'s>, Error> {
+                    Device::lock_surface_data(self, surface)
+                }
+
+                #[inline]
+                fn read_surface_pixels(
+                    &self,
+                    context: &mut Self::Context,
+                    surface: &Self::Surface,
+                    rect: Rect<i32>,
+                    format: PixelFormat,
+                ) -> Result<Vec<u8>, Error> {
+                    Device::read_surface_pixels(self, context, surface, rect, format)
+                }
+
+                #[inline]
+                fn begin_surface_readback(
+                    &self,
+                    context: &mut Self::Context,
+                    surface: &Self::Surface,
+                ) -> Result<ReadbackHandle, Error> {
+                    Device::begin_surface_readback(self, context, surface)
+                }
+
+                #[inline]
+                fn poll_surface_readback(
+                    &self,
+                    context: &Self::Context,
+                    readback: &ReadbackHandle,
+                ) -> Result<bool, Error> {
+                    Device::poll_surface_readback(self, context, readback)
+                }
+
+                #[inline]
+                fn map_surface_readback(
+                    &self,
+                    context: &mut Self::Context,
+                    readback: ReadbackHandle,
+                ) -> Result<Vec<u8>, (Error, ReadbackHandle)> {
+                    Device::map_surface_readback(self, context, readback)
+                }
+
+                #[inline]
+                fn destroy_surface_readback(
+                    &self,
+                    context: &mut Self::Context,
+                    readback: ReadbackHandle,
+                ) -> Result<(), (Error, ReadbackHandle)> {
+                    Device::destroy_surface_readback(self, context, readback)
+                }
+
+                #[cfg(free_unix)]
+                #[inline]
+                fn export_surface_dmabuf(
+                    &self,
+                    surface: &Self::Surface,
+                ) -> Result<$crate::DmaBufDescriptor, Error> {
+                    Device::export_surface_dmabuf(self, surface)
+                }
+
+                #[cfg(free_unix)]
+                #[inline]
+                fn create_surface_from_dmabuf(
+                    &self,
+                    context: &Self::Context,
+                    surface_access: SurfaceAccess,
+                    descriptor: &$crate::DmaBufDescriptor,
+                ) -> Result<Self::Surface, Error> {
+                    Device::create_surface_from_dmabuf(self, context, surface_access, descriptor)
+                }
+
+                #[cfg(free_unix)]
+                #[inline]
+                fn create_surface_texture_from_dmabuf(
+                    &self,
+                    context: &mut Self::Context,
+                    descriptor: &$crate::DmaBufDescriptor,
+                ) -> Result<Self::SurfaceTexture, Error> {
+                    Device::create_surface_texture_from_dmabuf(self, context, descriptor)
+                }
+
+                #[cfg(free_unix)]
+                #[inline]
+                fn dmabuf_formats(&self) -> Result<Vec<$crate::DmaBufFormat>, Error> {
+                    Device::dmabuf_formats(self)
+                }
+
+                #[cfg(free_unix)]
+                #[inline]
+                fn surface_texture_gl_texture_target(
+                    &self,
+                    surface_texture: &Self::SurfaceTexture,
+                ) -> u32 {
+                    Device::surface_texture_gl_texture_target(self, surface_texture)
+                }
+            }
+
+            impl<'a> SurfaceDataGuardInterface for SurfaceDataGuard<'a> {
+                #[inline]
+                fn stride(&self) -> usize {
+                    SurfaceDataGuard::stride(self)
+                }
+
+                #[inline]
+                fn data(&mut self) -> &mut [u8] {
+                    SurfaceDataGuard::data(self)
+                }
             }
         }
     };
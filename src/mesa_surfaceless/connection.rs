@@ -6,7 +6,7 @@ use crate::base::egl::device::EGL_FUNCTIONS;
 use crate::base::egl::ffi::EGL_PLATFORM_SURFACELESS_MESA;
 use crate::egl;
 use crate::egl::types::{EGLAttrib, EGLDisplay};
-use crate::info::GLApi;
+use crate::info::{AdapterInfo, GLApi};
 use crate::Error;
 
 use euclid::default::Size2D;
@@ -115,6 +115,15 @@ impl Connection {
         Ok(Adapter::software())
     }
 
+    /// Returns an adapter for each EGL device on this system, along with what is known about it.
+    ///
+    /// This requires `EGL_EXT_device_enumeration` and `EGL_EXT_platform_device`; without them,
+    /// only the device that the surfaceless Mesa display renders on is returned, if
+    /// `EGL_EXT_device_query` can tell which one that is.
+    pub fn enumerate_adapters(&self) -> Vec<AdapterInfo<Adapter>> {
+        Adapter::enumerate(self.native_connection.egl_display)
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
     /// Device handles are local to a single thread.
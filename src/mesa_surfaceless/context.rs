@@ -2,6 +2,7 @@
 
 use crate::base::egl::context::EGLBackedContext;
 pub use crate::base::egl::context::{ContextDescriptor, NativeContext};
+use crate::base::egl::fence::EGLFence;
 use crate::Gl;
 
 /// Represents an OpenGL rendering context.
@@ -21,3 +22,11 @@ use crate::Gl;
 ///
 /// A context must be explicitly destroyed with `destroy_context()`, or a panic will occur.
 pub struct Context(pub(crate) EGLBackedContext, pub(crate) Gl);
+
+/// Represents a GPU fence, which is signaled once the GPU finishes all commands issued before it.
+///
+/// Fences can be waited on from any context on a device that shares the same connection, on any
+/// thread.
+///
+/// A fence must be explicitly destroyed with `destroy_fence()`, or a panic will occur.
+pub struct Fence(pub(crate) EGLFence);
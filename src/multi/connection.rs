@@ -4,8 +4,7 @@ use super::device::{Adapter, Device, NativeDevice};
 use super::surface::NativeWidget;
 use crate::connection::Connection as ConnectionInterface;
 use crate::device::Device as DeviceInterface;
-use crate::Error;
-use crate::GLApi;
+use crate::{AdapterInfo, Error, GLApi};
 
 use euclid::default::Size2D;
 
@@ -140,6 +139,23 @@ where
         }
     }
 
+    /// Returns every adapter on this system that can be chosen individually, along with what is
+    /// known about each one.
+    pub fn enumerate_adapters(&self) -> Vec<AdapterInfo<Adapter<Def, Alt>>> {
+        match *self {
+            Connection::Default(ref connection) => connection
+                .enumerate_adapters()
+                .into_iter()
+                .map(|info| info.map_adapter(Adapter::Default))
+                .collect(),
+            Connection::Alternate(ref connection) => connection
+                .enumerate_adapters()
+                .into_iter()
+                .map(|info| info.map_adapter(Adapter::Alternate))
+                .collect(),
+        }
+    }
+
     /// Opens the hardware device corresponding to the given adapter.
     ///
     /// Device handles are local to a single thread.
@@ -300,6 +316,11 @@ where
         Connection::create_software_adapter(self)
     }
 
+    #[inline]
+    fn enumerate_adapters(&self) -> Vec<AdapterInfo<Adapter<Def, Alt>>> {
+        Connection::enumerate_adapters(self)
+    }
+
     #[inline]
     fn create_device(&self, adapter: &Adapter<Def, Alt>) -> Result<Device<Def, Alt>, Error> {
         Connection::create_device(self, adapter)
@@ -5,9 +5,12 @@ use euclid::default::Size2D;
 use super::device::Device;
 use super::surface::Surface;
 use crate::device::Device as DeviceInterface;
-use crate::{ContextAttributes, ContextID, Error, SurfaceInfo};
+use crate::{ContextAttributes, ContextID, ContextResetStatus, Error, SurfaceInfo};
 
 use std::os::raw::c_void;
+#[cfg(free_unix)]
+use std::os::unix::io::OwnedFd;
+use std::time::Duration;
 
 /// Represents an OpenGL rendering context.
 ///
@@ -63,6 +66,20 @@ where
     Alternate(Alt::NativeContext),
 }
 
+/// Represents a GPU fence, which is signaled once the GPU finishes all commands issued before it.
+///
+/// A fence must be explicitly destroyed with `destroy_fence()`, or a panic will occur.
+pub enum Fence<Def, Alt>
+where
+    Def: DeviceInterface,
+    Alt: DeviceInterface,
+{
+    /// The default fence type.
+    Default(Def::Fence),
+    /// The alternate fence type.
+    Alternate(Alt::Fence),
+}
+
 impl<Def, Alt> Device<Def, Alt>
 where
     Def: DeviceInterface,
@@ -337,6 +354,135 @@ where
         }
     }
 
+    /// Returns the largest number of samples per pixel that generic surfaces created with this
+    /// context can use.
+    ///
+    /// A result of 0 means that generic surfaces can't be multisampled on this context.
+    pub fn max_surface_samples(&self, context: &Context<Def, Alt>) -> Result<u32, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => {
+                device.max_surface_samples(context)
+            }
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.max_surface_samples(context)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns whether the context has been lost because of a GPU reset, and if so, whether this
+    /// context caused it.
+    pub fn context_reset_status(
+        &self,
+        context: &Context<Def, Alt>,
+    ) -> Result<ContextResetStatus, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => {
+                device.context_reset_status(context)
+            }
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.context_reset_status(context)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Inserts a fence into the command stream of the given context and flushes it.
+    ///
+    /// The fence is signaled once the GPU finishes all commands issued before it.
+    pub fn create_fence(&self, context: &Context<Def, Alt>) -> Result<Fence<Def, Alt>, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => {
+                device.create_fence(context).map(Fence::Default)
+            }
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.create_fence(context).map(Fence::Alternate)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Blocks the calling thread until the fence is signaled, or until the timeout expires if
+    /// one is given.
+    ///
+    /// Returns true if the fence was signaled and false if the timeout expired first.
+    pub fn client_wait_fence(
+        &self,
+        fence: &Fence<Def, Alt>,
+        timeout: Option<Duration>,
+    ) -> Result<bool, Error> {
+        match (self, fence) {
+            (Device::Default(device), Fence::Default(fence)) => {
+                device.client_wait_fence(fence, timeout)
+            }
+            (Device::Alternate(device), Fence::Alternate(fence)) => {
+                device.client_wait_fence(fence, timeout)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Makes the GPU wait until the fence is signaled before executing any further commands
+    /// issued by the given context.
+    pub fn server_wait_fence(
+        &self,
+        context: &Context<Def, Alt>,
+        fence: &Fence<Def, Alt>,
+    ) -> Result<(), Error> {
+        match (self, context, fence) {
+            (Device::Default(device), Context::Default(context), Fence::Default(fence)) => {
+                device.server_wait_fence(context, fence)
+            }
+            (Device::Alternate(device), Context::Alternate(context), Fence::Alternate(fence)) => {
+                device.server_wait_fence(context, fence)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Destroys a fence.
+    pub fn destroy_fence(&self, fence: &mut Fence<Def, Alt>) -> Result<(), Error> {
+        match (self, fence) {
+            (Device::Default(device), Fence::Default(fence)) => device.destroy_fence(fence),
+            (Device::Alternate(device), Fence::Alternate(fence)) => device.destroy_fence(fence),
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns a new file descriptor for the native sync file behind the fence.
+    #[cfg(free_unix)]
+    pub fn export_native_fence_fd(&self, fence: &Fence<Def, Alt>) -> Result<OwnedFd, Error> {
+        match (self, fence) {
+            (Device::Default(device), Fence::Default(fence)) => {
+                device.export_native_fence_fd(fence)
+            }
+            (Device::Alternate(device), Fence::Alternate(fence)) => {
+                device.export_native_fence_fd(fence)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Wraps a native sync file in a fence that can be waited on in the given context.
+    ///
+    /// This takes ownership of the file descriptor.
+    #[cfg(free_unix)]
+    pub fn import_native_fence_fd(
+        &self,
+        context: &Context<Def, Alt>,
+        fd: OwnedFd,
+    ) -> Result<Fence<Def, Alt>, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => device
+                .import_native_fence_fd(context, fd)
+                .map(Fence::Default),
+            (Device::Alternate(device), Context::Alternate(context)) => device
+                .import_native_fence_fd(context, fd)
+                .map(Fence::Alternate),
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
     /// Returns a unique ID representing a context.
     ///
     /// This ID is unique to all currently-allocated contexts. If you destroy a context and create
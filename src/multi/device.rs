@@ -1,16 +1,22 @@
 //! A device abstraction that allows the choice of backends dynamically.
 
 use super::connection::Connection;
-use super::context::{Context, ContextDescriptor, NativeContext};
-use super::surface::{NativeWidget, Surface, SurfaceTexture};
+use super::context::{Context, ContextDescriptor, Fence, NativeContext};
+use super::surface::{NativeWidget, Surface, SurfaceDataGuard, SurfaceTexture};
 use crate::connection::Connection as ConnectionInterface;
 use crate::context::ContextAttributes;
 use crate::device::Device as DeviceInterface;
-use crate::{ContextID, Error, GLApi, SurfaceAccess, SurfaceInfo, SurfaceType};
-use euclid::default::Size2D;
+use crate::{ContextID, ContextResetStatus, Error, GLApi, PixelFormat, ReadbackHandle};
+#[cfg(free_unix)]
+use crate::{DmaBufDescriptor, DmaBufFormat};
+use crate::{SurfaceAccess, SurfaceInfo, SurfaceType};
+use euclid::default::{Rect, Size2D};
 use glow::Texture;
 
 use std::os::raw::c_void;
+#[cfg(free_unix)]
+use std::os::unix::io::OwnedFd;
+use std::time::Duration;
 
 /// Represents a hardware display adapter that can be used for rendering (including the CPU).
 ///
@@ -120,6 +126,8 @@ where
     type NativeContext = NativeContext<Def, Alt>;
     type Surface = Surface<Def, Alt>;
     type SurfaceTexture = SurfaceTexture<Def, Alt>;
+    type SurfaceDataGuard<'a> = SurfaceDataGuard<'a, Def, Alt>;
+    type Fence = Fence<Def, Alt>;
 
     // device.rs
 
@@ -203,6 +211,63 @@ where
         Device::context_descriptor_attributes(self, context_descriptor)
     }
 
+    #[inline]
+    fn max_surface_samples(&self, context: &Context<Def, Alt>) -> Result<u32, Error> {
+        Device::max_surface_samples(self, context)
+    }
+
+    #[inline]
+    fn context_reset_status(
+        &self,
+        context: &Context<Def, Alt>,
+    ) -> Result<ContextResetStatus, Error> {
+        Device::context_reset_status(self, context)
+    }
+
+    #[inline]
+    fn create_fence(&self, context: &Context<Def, Alt>) -> Result<Fence<Def, Alt>, Error> {
+        Device::create_fence(self, context)
+    }
+
+    #[inline]
+    fn client_wait_fence(
+        &self,
+        fence: &Fence<Def, Alt>,
+        timeout: Option<Duration>,
+    ) -> Result<bool, Error> {
+        Device::client_wait_fence(self, fence, timeout)
+    }
+
+    #[inline]
+    fn server_wait_fence(
+        &self,
+        context: &Context<Def, Alt>,
+        fence: &Fence<Def, Alt>,
+    ) -> Result<(), Error> {
+        Device::server_wait_fence(self, context, fence)
+    }
+
+    #[inline]
+    fn destroy_fence(&self, fence: &mut Fence<Def, Alt>) -> Result<(), Error> {
+        Device::destroy_fence(self, fence)
+    }
+
+    #[cfg(free_unix)]
+    #[inline]
+    fn export_native_fence_fd(&self, fence: &Fence<Def, Alt>) -> Result<OwnedFd, Error> {
+        Device::export_native_fence_fd(self, fence)
+    }
+
+    #[cfg(free_unix)]
+    #[inline]
+    fn import_native_fence_fd(
+        &self,
+        context: &Context<Def, Alt>,
+        fd: OwnedFd,
+    ) -> Result<Fence<Def, Alt>, Error> {
+        Device::import_native_fence_fd(self, context, fd)
+    }
+
     #[inline]
     fn get_proc_address(&self, context: &Context<Def, Alt>, symbol_name: &str) -> *const c_void {
         Device::get_proc_address(self, context, symbol_name)
@@ -305,6 +370,45 @@ where
         Device::present_surface(self, context, surface)
     }
 
+    #[inline]
+    fn present_surface_with_damage(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        Device::present_surface_with_damage(self, context, surface, damage)
+    }
+
+    #[inline]
+    fn surface_buffer_age(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+    ) -> Result<u32, Error> {
+        Device::surface_buffer_age(self, context, surface)
+    }
+
+    #[inline]
+    fn set_damage_region(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        Device::set_damage_region(self, context, surface, damage)
+    }
+
+    #[inline]
+    fn set_swap_interval(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+        interval: i32,
+    ) -> Result<(), Error> {
+        Device::set_swap_interval(self, context, surface, interval)
+    }
+
     #[inline]
     fn resize_surface(
         &self,
@@ -327,4 +431,101 @@ where
     ) -> Option<Texture> {
         Device::surface_texture_object(self, surface_texture)
     }
+
+    #[inline]
+    fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Surface<Def, Alt>,
+    ) -> Result<SurfaceDataGuard<'s, Def, Alt>, Error> {
+        Device::lock_surface_data(self, surface)
+    }
+
+    #[inline]
+    fn read_surface_pixels(
+        &self,
+        context: &mut Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+        rect: Rect<i32>,
+        format: PixelFormat,
+    ) -> Result<Vec<u8>, Error> {
+        Device::read_surface_pixels(self, context, surface, rect, format)
+    }
+
+    #[inline]
+    fn begin_surface_readback(
+        &self,
+        context: &mut Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+    ) -> Result<ReadbackHandle, Error> {
+        Device::begin_surface_readback(self, context, surface)
+    }
+
+    #[inline]
+    fn poll_surface_readback(
+        &self,
+        context: &Context<Def, Alt>,
+        readback: &ReadbackHandle,
+    ) -> Result<bool, Error> {
+        Device::poll_surface_readback(self, context, readback)
+    }
+
+    #[inline]
+    fn map_surface_readback(
+        &self,
+        context: &mut Context<Def, Alt>,
+        readback: ReadbackHandle,
+    ) -> Result<Vec<u8>, (Error, ReadbackHandle)> {
+        Device::map_surface_readback(self, context, readback)
+    }
+
+    #[inline]
+    fn destroy_surface_readback(
+        &self,
+        context: &mut Context<Def, Alt>,
+        readback: ReadbackHandle,
+    ) -> Result<(), (Error, ReadbackHandle)> {
+        Device::destroy_surface_readback(self, context, readback)
+    }
+
+    #[cfg(free_unix)]
+    #[inline]
+    fn export_surface_dmabuf(
+        &self,
+        surface: &Surface<Def, Alt>,
+    ) -> Result<DmaBufDescriptor, Error> {
+        Device::export_surface_dmabuf(self, surface)
+    }
+
+    #[cfg(free_unix)]
+    #[inline]
+    fn create_surface_from_dmabuf(
+        &self,
+        context: &Context<Def, Alt>,
+        surface_access: SurfaceAccess,
+        descriptor: &DmaBufDescriptor,
+    ) -> Result<Surface<Def, Alt>, Error> {
+        Device::create_surface_from_dmabuf(self, context, surface_access, descriptor)
+    }
+
+    #[cfg(free_unix)]
+    #[inline]
+    fn create_surface_texture_from_dmabuf(
+        &self,
+        context: &mut Context<Def, Alt>,
+        descriptor: &DmaBufDescriptor,
+    ) -> Result<SurfaceTexture<Def, Alt>, Error> {
+        Device::create_surface_texture_from_dmabuf(self, context, descriptor)
+    }
+
+    #[cfg(free_unix)]
+    #[inline]
+    fn dmabuf_formats(&self) -> Result<Vec<DmaBufFormat>, Error> {
+        Device::dmabuf_formats(self)
+    }
+
+    #[cfg(free_unix)]
+    #[inline]
+    fn surface_texture_gl_texture_target(&self, surface_texture: &SurfaceTexture<Def, Alt>) -> u32 {
+        Device::surface_texture_gl_texture_target(self, surface_texture)
+    }
 }
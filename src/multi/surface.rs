@@ -4,8 +4,11 @@ use super::context::Context;
 use super::device::Device;
 use crate::connection::Connection as ConnectionInterface;
 use crate::device::Device as DeviceInterface;
-use crate::{Error, SurfaceAccess, SurfaceInfo, SurfaceType};
-use euclid::default::Size2D;
+use crate::device::SurfaceDataGuard as SurfaceDataGuardInterface;
+#[cfg(free_unix)]
+use crate::{DmaBufDescriptor, DmaBufFormat};
+use crate::{Error, PixelFormat, ReadbackHandle, SurfaceAccess, SurfaceInfo, SurfaceType};
+use euclid::default::{Rect, Size2D};
 use glow::Texture;
 
 use std::fmt::{self, Debug, Formatter};
@@ -57,6 +60,20 @@ where
     Alternate(Alt::SurfaceTexture),
 }
 
+/// Represents the CPU view of the pixel data of a surface.
+///
+/// The surface stays locked until this guard is dropped.
+pub enum SurfaceDataGuard<'a, Def, Alt>
+where
+    Def: DeviceInterface,
+    Alt: DeviceInterface,
+{
+    /// The default surface data guard type.
+    Default(Def::SurfaceDataGuard<'a>),
+    /// The alternate surface data guard type.
+    Alternate(Alt::SurfaceDataGuard<'a>),
+}
+
 /// A native widget/window type that can dynamically switch between backends.
 pub enum NativeWidget<Def, Alt>
 where
@@ -89,6 +106,48 @@ where
     }
 }
 
+impl<'a, Def, Alt> SurfaceDataGuard<'a, Def, Alt>
+where
+    Def: DeviceInterface,
+    Alt: DeviceInterface,
+{
+    /// Returns the number of bytes per row of the surface.
+    #[inline]
+    pub fn stride(&self) -> usize {
+        match *self {
+            SurfaceDataGuard::Default(ref guard) => guard.stride(),
+            SurfaceDataGuard::Alternate(ref guard) => guard.stride(),
+        }
+    }
+
+    /// Returns a mutable slice of the pixel data in this surface.
+    ///
+    /// The pixel format depends on the backend in use.
+    #[inline]
+    pub fn data(&mut self) -> &mut [u8] {
+        match *self {
+            SurfaceDataGuard::Default(ref mut guard) => guard.data(),
+            SurfaceDataGuard::Alternate(ref mut guard) => guard.data(),
+        }
+    }
+}
+
+impl<'a, Def, Alt> SurfaceDataGuardInterface for SurfaceDataGuard<'a, Def, Alt>
+where
+    Def: DeviceInterface,
+    Alt: DeviceInterface,
+{
+    #[inline]
+    fn stride(&self) -> usize {
+        SurfaceDataGuard::stride(self)
+    }
+
+    #[inline]
+    fn data(&mut self) -> &mut [u8] {
+        SurfaceDataGuard::data(self)
+    }
+}
+
 impl<Def, Alt> Device<Def, Alt>
 where
     Def: DeviceInterface,
@@ -271,6 +330,104 @@ where
         }
     }
 
+    /// Displays the contents of a widget surface on screen, telling the compositor that only the
+    /// given rectangles have changed since the surface was last presented.
+    ///
+    /// The rectangles are relative to the top left corner of the surface. An empty list damages
+    /// the whole surface.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => match *surface {
+                Surface::Default(ref mut surface) => {
+                    device.present_surface_with_damage(context, surface, damage)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (Device::Alternate(device), Context::Alternate(context)) => match *surface {
+                Surface::Alternate(ref mut surface) => {
+                    device.present_surface_with_damage(context, surface, damage)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns how many frames old the contents of the back buffer of a widget surface are.
+    ///
+    /// An age of 0 means that the contents are undefined, and the whole surface must be redrawn.
+    pub fn surface_buffer_age(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+    ) -> Result<u32, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => match *surface {
+                Surface::Default(ref surface) => device.surface_buffer_age(context, surface),
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (Device::Alternate(device), Context::Alternate(context)) => match *surface {
+                Surface::Alternate(ref surface) => device.surface_buffer_age(context, surface),
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Tells the driver that only the given rectangles of a widget surface will be rendered to
+    /// before it's next presented.
+    pub fn set_damage_region(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &mut Surface<Def, Alt>,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => match *surface {
+                Surface::Default(ref mut surface) => {
+                    device.set_damage_region(context, surface, damage)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (Device::Alternate(device), Context::Alternate(context)) => match *surface {
+                Surface::Alternate(ref mut surface) => {
+                    device.set_damage_region(context, surface, damage)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Sets the minimum number of display refreshes between presentations of a widget surface.
+    pub fn set_swap_interval(
+        &self,
+        context: &Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+        interval: i32,
+    ) -> Result<(), Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => match *surface {
+                Surface::Default(ref surface) => {
+                    device.set_swap_interval(context, surface, interval)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (Device::Alternate(device), Context::Alternate(context)) => match *surface {
+                Surface::Alternate(ref surface) => {
+                    device.set_swap_interval(context, surface, interval)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
     /// Resizes a widget surface.
     pub fn resize_surface(
         &self,
@@ -339,4 +496,242 @@ where
             _ => panic!("Incompatible context!"),
         }
     }
+
+    /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
+    ///
+    /// The surface must have been created on this device, or an `IncompatibleSurface` error is
+    /// returned.
+    pub fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Surface<Def, Alt>,
+    ) -> Result<SurfaceDataGuard<'s, Def, Alt>, Error> {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(ref mut surface)) => device
+                .lock_surface_data(surface)
+                .map(SurfaceDataGuard::Default),
+            (Device::Alternate(device), Surface::Alternate(ref mut surface)) => device
+                .lock_surface_data(surface)
+                .map(SurfaceDataGuard::Alternate),
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
+    /// Exports the contents of a generic surface as a Linux dma-buf.
+    ///
+    /// The surface must have been created on this device, or an `IncompatibleSurface` error is
+    /// returned.
+    #[cfg(free_unix)]
+    pub fn export_surface_dmabuf(
+        &self,
+        surface: &Surface<Def, Alt>,
+    ) -> Result<DmaBufDescriptor, Error> {
+        match (self, surface) {
+            (Device::Default(device), Surface::Default(ref surface)) => {
+                device.export_surface_dmabuf(surface)
+            }
+            (Device::Alternate(device), Surface::Alternate(ref surface)) => {
+                device.export_surface_dmabuf(surface)
+            }
+            _ => Err(Error::IncompatibleSurface),
+        }
+    }
+
+    /// Creates a generic surface that renders into an existing Linux dma-buf.
+    ///
+    /// The context must have been created on this device, or an `IncompatibleContext` error is
+    /// returned.
+    #[cfg(free_unix)]
+    pub fn create_surface_from_dmabuf(
+        &self,
+        context: &Context<Def, Alt>,
+        surface_access: SurfaceAccess,
+        descriptor: &DmaBufDescriptor,
+    ) -> Result<Surface<Def, Alt>, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(ref context)) => device
+                .create_surface_from_dmabuf(context, surface_access, descriptor)
+                .map(Surface::Default),
+            (Device::Alternate(device), Context::Alternate(ref context)) => device
+                .create_surface_from_dmabuf(context, surface_access, descriptor)
+                .map(Surface::Alternate),
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Wraps an existing Linux dma-buf in a surface texture for use with the given context.
+    ///
+    /// The context must have been created on this device, or an `IncompatibleContext` error is
+    /// returned.
+    #[cfg(free_unix)]
+    pub fn create_surface_texture_from_dmabuf(
+        &self,
+        context: &mut Context<Def, Alt>,
+        descriptor: &DmaBufDescriptor,
+    ) -> Result<SurfaceTexture<Def, Alt>, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(ref mut context)) => device
+                .create_surface_texture_from_dmabuf(context, descriptor)
+                .map(SurfaceTexture::Default),
+            (Device::Alternate(device), Context::Alternate(ref mut context)) => device
+                .create_surface_texture_from_dmabuf(context, descriptor)
+                .map(SurfaceTexture::Alternate),
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns the dma-buf formats, and the modifiers for each format, that this device can
+    /// import.
+    #[cfg(free_unix)]
+    pub fn dmabuf_formats(&self) -> Result<Vec<DmaBufFormat>, Error> {
+        match *self {
+            Device::Default(ref device) => device.dmabuf_formats(),
+            Device::Alternate(ref device) => device.dmabuf_formats(),
+        }
+    }
+
+    /// Returns the OpenGL texture target needed to read from the given surface texture.
+    ///
+    /// This is `GL_TEXTURE_EXTERNAL_OES` for surface textures imported from dma-bufs that the
+    /// driver can only sample as external textures, and the same as
+    /// `surface_gl_texture_target()` otherwise.
+    #[cfg(free_unix)]
+    pub fn surface_texture_gl_texture_target(
+        &self,
+        surface_texture: &SurfaceTexture<Def, Alt>,
+    ) -> u32 {
+        match (self, surface_texture) {
+            (Device::Default(device), SurfaceTexture::Default(ref surface_texture)) => {
+                device.surface_texture_gl_texture_target(surface_texture)
+            }
+            (Device::Alternate(device), SurfaceTexture::Alternate(ref surface_texture)) => {
+                device.surface_texture_gl_texture_target(surface_texture)
+            }
+            _ => panic!("Incompatible context!"),
+        }
+    }
+
+    /// Reads back a rectangle of pixels from a generic surface.
+    ///
+    /// The rectangle is relative to the top left corner of the surface, and the returned rows are
+    /// ordered from top to bottom in the requested pixel format.
+    ///
+    /// The supplied context must be the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned.
+    pub fn read_surface_pixels(
+        &self,
+        context: &mut Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+        rect: Rect<i32>,
+        format: PixelFormat,
+    ) -> Result<Vec<u8>, Error> {
+        match (self, &mut *context) {
+            (Device::Default(device), &mut Context::Default(ref mut context)) => match *surface {
+                Surface::Default(ref surface) => {
+                    device.read_surface_pixels(context, surface, rect, format)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (Device::Alternate(device), &mut Context::Alternate(ref mut context)) => match *surface
+            {
+                Surface::Alternate(ref surface) => {
+                    device.read_surface_pixels(context, surface, rect, format)
+                }
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Starts copying the contents of a generic surface into CPU-visible memory.
+    ///
+    /// This does not wait for the GPU. Use `poll_surface_readback()` to check whether the copy
+    /// has finished and `map_surface_readback()` to retrieve the pixels.
+    ///
+    /// The supplied context must be the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned.
+    pub fn begin_surface_readback(
+        &self,
+        context: &mut Context<Def, Alt>,
+        surface: &Surface<Def, Alt>,
+    ) -> Result<ReadbackHandle, Error> {
+        match (self, &mut *context) {
+            (Device::Default(device), &mut Context::Default(ref mut context)) => match *surface {
+                Surface::Default(ref surface) => device.begin_surface_readback(context, surface),
+                _ => Err(Error::IncompatibleSurface),
+            },
+            (Device::Alternate(device), &mut Context::Alternate(ref mut context)) => match *surface
+            {
+                Surface::Alternate(ref surface) => device.begin_surface_readback(context, surface),
+                _ => Err(Error::IncompatibleSurface),
+            },
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns true if the GPU has finished a readback, so that `map_surface_readback()` will not
+    /// block.
+    ///
+    /// The supplied context must be the context the readback was started with, or an
+    /// `IncompatibleContext` error is returned.
+    pub fn poll_surface_readback(
+        &self,
+        context: &Context<Def, Alt>,
+        readback: &ReadbackHandle,
+    ) -> Result<bool, Error> {
+        match (self, context) {
+            (Device::Default(device), Context::Default(context)) => {
+                device.poll_surface_readback(context, readback)
+            }
+            (Device::Alternate(device), Context::Alternate(context)) => {
+                device.poll_surface_readback(context, readback)
+            }
+            _ => Err(Error::IncompatibleContext),
+        }
+    }
+
+    /// Returns the pixels of a readback in RGBA format, with rows ordered from top to bottom, and
+    /// frees the readback.
+    ///
+    /// If the GPU has not yet finished the copy, this waits for it. The supplied context must be
+    /// the context the readback was started with, or an `IncompatibleContext` error is returned.
+    /// On failure, the readback is returned alongside the error, so that it can be mapped again
+    /// or destroyed.
+    pub fn map_surface_readback(
+        &self,
+        context: &mut Context<Def, Alt>,
+        readback: ReadbackHandle,
+    ) -> Result<Vec<u8>, (Error, ReadbackHandle)> {
+        match (self, &mut *context) {
+            (Device::Default(device), &mut Context::Default(ref mut context)) => {
+                device.map_surface_readback(context, readback)
+            }
+            (Device::Alternate(device), &mut Context::Alternate(ref mut context)) => {
+                device.map_surface_readback(context, readback)
+            }
+            _ => Err((Error::IncompatibleContext, readback)),
+        }
+    }
+
+    /// Frees a readback without mapping it.
+    ///
+    /// The supplied context must be the context the readback was started with, or an
+    /// `IncompatibleContext` error is returned alongside the readback.
+    ///
+    /// You must call either this method or `map_surface_readback()` to dispose of a readback.
+    /// Otherwise, a panic occurs in the `drop` method.
+    pub fn destroy_surface_readback(
+        &self,
+        context: &mut Context<Def, Alt>,
+        readback: ReadbackHandle,
+    ) -> Result<(), (Error, ReadbackHandle)> {
+        match (self, &mut *context) {
+            (Device::Default(device), &mut Context::Default(ref mut context)) => {
+                device.destroy_surface_readback(context, readback)
+            }
+            (Device::Alternate(device), &mut Context::Alternate(ref mut context)) => {
+                device.destroy_surface_readback(context, readback)
+            }
+            _ => Err((Error::IncompatibleContext, readback)),
+        }
+    }
 }
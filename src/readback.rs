@@ -0,0 +1,203 @@
+//! Asynchronous readback of surface contents through pixel pack buffers.
+
+use crate::context::ContextID;
+use crate::gl;
+use crate::gl_utils;
+use crate::{Error, Gl};
+
+use euclid::default::Size2D;
+use glow::{Buffer, Fence, HasContext, NativeFramebuffer};
+use std::fmt::{self, Debug, Formatter};
+use std::slice;
+use std::thread;
+
+// The longest time, in nanoseconds, that a single `glClientWaitSync()` call may block for.
+const MAX_WAIT_TIMEOUT: i32 = i32::MAX;
+
+/// An in-flight copy of a surface's contents into CPU-visible memory.
+///
+/// Readbacks are started with `begin_surface_readback()`. The copy happens asynchronously on the
+/// GPU; `poll_surface_readback()` reports whether it has finished without blocking, and
+/// `map_surface_readback()` returns the pixels, waiting for the copy to finish if necessary.
+///
+/// Readback handles are local to the context that created them. A handle that is not mapped must
+/// be destroyed with `destroy_surface_readback()`, which frees its pixel pack buffer and fence, or
+/// a panic will occur.
+pub struct ReadbackHandle {
+    pub(crate) context_id: ContextID,
+    size: Size2D<i32>,
+    buffer: Option<Buffer>,
+    fence: Option<Fence>,
+}
+
+impl Drop for ReadbackHandle {
+    fn drop(&mut self) {
+        if self.buffer.is_some() && !thread::panicking() {
+            panic!(
+                "Readbacks must be mapped or destroyed explicitly with `destroy_surface_readback`!"
+            )
+        }
+    }
+}
+
+impl Debug for ReadbackHandle {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "ReadbackHandle({:?})", self.size)
+    }
+}
+
+impl ReadbackHandle {
+    // Copies the contents of a framebuffer into a new pixel pack buffer and inserts a fence after
+    // the copy.
+    //
+    // The context that owns the framebuffer must be current.
+    pub(crate) fn new(
+        gl: &Gl,
+        context_id: ContextID,
+        framebuffer_object: Option<NativeFramebuffer>,
+        size: Size2D<i32>,
+    ) -> Result<ReadbackHandle, Error> {
+        if !supports_async_readback(gl) {
+            return Err(Error::RequiredExtensionUnavailable);
+        }
+        let length = (size.width.max(0) as usize)
+            .checked_mul(size.height.max(0) as usize)
+            .and_then(|pixels| pixels.checked_mul(4))
+            .and_then(|length| i32::try_from(length).ok())
+            .ok_or(Error::Failed)?;
+
+        unsafe {
+            let old_read_framebuffer = gl.get_parameter_framebuffer(gl::READ_FRAMEBUFFER_BINDING);
+            let old_pack_buffer = gl.get_parameter_buffer(gl::PIXEL_PACK_BUFFER_BINDING);
+            let old_pack_alignment = gl.get_parameter_i32(gl::PACK_ALIGNMENT);
+
+            let buffer = gl.create_buffer().map_err(|_| Error::Failed)?;
+            gl.bind_buffer(gl::PIXEL_PACK_BUFFER, Some(buffer));
+            gl.buffer_data_size(gl::PIXEL_PACK_BUFFER, length, gl::STREAM_READ);
+            gl.bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffer_object);
+            gl.pixel_store_i32(gl::PACK_ALIGNMENT, 1);
+            gl.read_pixels(
+                0,
+                0,
+                size.width,
+                size.height,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                glow::PixelPackData::BufferOffset(0),
+            );
+            let fence = gl.fence_sync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+
+            gl.pixel_store_i32(gl::PACK_ALIGNMENT, old_pack_alignment);
+            gl.bind_buffer(gl::PIXEL_PACK_BUFFER, old_pack_buffer);
+            gl.bind_framebuffer(gl::READ_FRAMEBUFFER, old_read_framebuffer);
+
+            let fence = match fence {
+                Ok(fence) => fence,
+                Err(_) => {
+                    gl.delete_buffer(buffer);
+                    return Err(Error::Failed);
+                }
+            };
+
+            // Make sure the fence reaches the GPU so that polling from the CPU can make progress.
+            gl.flush();
+
+            Ok(ReadbackHandle {
+                context_id,
+                size,
+                buffer: Some(buffer),
+                fence: Some(fence),
+            })
+        }
+    }
+
+    /// Returns the size of the region being read back, in device pixels.
+    #[inline]
+    pub fn size(&self) -> Size2D<i32> {
+        self.size
+    }
+
+    // Returns true if the GPU has finished copying the pixels, without blocking.
+    pub(crate) fn poll(&self, gl: &Gl) -> bool {
+        match self.fence {
+            None => true,
+            Some(fence) => unsafe { gl.get_sync_status(fence) == gl::SIGNALED },
+        }
+    }
+
+    // Waits for the copy to finish and returns the pixels in RGBA format, top row first, then
+    // frees the buffer and fence. On failure, the handle is handed back so that it can be mapped
+    // again or destroyed.
+    //
+    // The context that began the readback must be current.
+    pub(crate) fn map(mut self, gl: &Gl) -> Result<Vec<u8>, (Error, ReadbackHandle)> {
+        let stride = self.size.width as usize * 4;
+        let length = stride * self.size.height as usize;
+        let buffer = match self.buffer {
+            Some(buffer) => buffer,
+            None => return Err((Error::Failed, self)),
+        };
+
+        unsafe {
+            if let Some(fence) = self.fence {
+                loop {
+                    match gl.client_wait_sync(fence, 0, MAX_WAIT_TIMEOUT) {
+                        gl::ALREADY_SIGNALED | gl::CONDITION_SATISFIED => break,
+                        gl::TIMEOUT_EXPIRED => continue,
+                        _ => return Err((Error::Failed, self)),
+                    }
+                }
+                gl.delete_sync(fence);
+                self.fence = None;
+            }
+
+            let mut data = vec![0; length];
+            if length > 0 {
+                let old_pack_buffer = gl.get_parameter_buffer(gl::PIXEL_PACK_BUFFER_BINDING);
+                gl.bind_buffer(gl::PIXEL_PACK_BUFFER, Some(buffer));
+                let ptr =
+                    gl.map_buffer_range(gl::PIXEL_PACK_BUFFER, 0, length as i32, gl::MAP_READ_BIT);
+                if !ptr.is_null() {
+                    data.copy_from_slice(slice::from_raw_parts(ptr, length));
+                    gl.unmap_buffer(gl::PIXEL_PACK_BUFFER);
+                }
+                gl.bind_buffer(gl::PIXEL_PACK_BUFFER, old_pack_buffer);
+                if ptr.is_null() {
+                    return Err((Error::Failed, self));
+                }
+                gl_utils::flip_rows(&mut data, stride);
+            }
+            self.destroy(gl);
+            Ok(data)
+        }
+    }
+
+    // Frees the buffer and fence.
+    //
+    // The context that began the readback must be current.
+    pub(crate) fn destroy(&mut self, gl: &Gl) {
+        unsafe {
+            if let Some(fence) = self.fence.take() {
+                gl.delete_sync(fence);
+            }
+            if let Some(buffer) = self.buffer.take() {
+                gl.delete_buffer(buffer);
+            }
+        }
+    }
+}
+
+// Pixel pack buffers need OpenGL 2.1 or OpenGL ES 3.0; fence sync objects need OpenGL 3.2,
+// `GL_ARB_sync`, or OpenGL ES 3.0; mapping buffers needs OpenGL 3.0,
+// `GL_ARB_map_buffer_range`, or OpenGL ES 3.0.
+fn supports_async_readback(gl: &Gl) -> bool {
+    let version = gl.version();
+    if version.is_embedded {
+        return version.major >= 3;
+    }
+    let version = (version.major, version.minor);
+    let extensions = gl.supported_extensions();
+    version >= (2, 1)
+        && (version >= (3, 2) || extensions.contains("GL_ARB_sync"))
+        && (version >= (3, 0) || extensions.contains("GL_ARB_map_buffer_range"))
+}
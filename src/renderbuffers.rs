@@ -2,7 +2,7 @@
 
 use crate::context::{ContextAttributeFlags, ContextAttributes};
 use crate::gl;
-use crate::Gl;
+use crate::{DepthFormat, Gl};
 use std::thread;
 
 use euclid::default::Size2D;
@@ -13,8 +13,9 @@ pub(crate) enum Renderbuffers {
     IndividualDepthStencil {
         depth: Option<Renderbuffer>,
         stencil: Option<Renderbuffer>,
+        depth_format: DepthFormat,
     },
-    CombinedDepthStencil(Option<Renderbuffer>),
+    CombinedDepthStencil(Option<Renderbuffer>, DepthFormat),
 }
 
 impl Drop for Renderbuffers {
@@ -23,8 +24,9 @@ impl Drop for Renderbuffers {
             Renderbuffers::IndividualDepthStencil {
                 depth: None,
                 stencil: None,
+                ..
             }
-            | Renderbuffers::CombinedDepthStencil(None) => {}
+            | Renderbuffers::CombinedDepthStencil(None, _) => {}
             _ => {
                 if !thread::panicking() {
                     panic!("Should have destroyed the FBO renderbuffers with `destroy()`!")
@@ -35,62 +37,97 @@ impl Drop for Renderbuffers {
 }
 
 impl Renderbuffers {
+    #[allow(dead_code)]
     pub(crate) fn new(
         gl: &Gl,
         size: &Size2D<i32>,
         attributes: &ContextAttributes,
     ) -> Renderbuffers {
+        Renderbuffers::new_multisampled(gl, size, attributes, 0)
+    }
+
+    // Creates depth and/or stencil renderbuffers with the given number of samples per pixel, for
+    // attaching to a framebuffer with a multisampled color buffer. A sample count of 0 creates
+    // ordinary single-sampled storage.
+    pub(crate) fn new_multisampled(
+        gl: &Gl,
+        size: &Size2D<i32>,
+        attributes: &ContextAttributes,
+        samples: u32,
+    ) -> Renderbuffers {
+        let mut depth_format = attributes.depth_format;
+        if depth_format == DepthFormat::Depth32F && !supports_float_depth(gl) {
+            depth_format = DepthFormat::Depth24;
+        }
+
         unsafe {
             if attributes
                 .flags
                 .contains(ContextAttributeFlags::DEPTH | ContextAttributeFlags::STENCIL)
             {
-                let renderbuffer = gl.create_renderbuffer().unwrap();
-                gl.bind_renderbuffer(gl::RENDERBUFFER, Some(renderbuffer));
-                gl.renderbuffer_storage(
-                    gl::RENDERBUFFER,
-                    gl::DEPTH24_STENCIL8,
-                    size.width,
-                    size.height,
-                );
-                gl.bind_renderbuffer(gl::RENDERBUFFER, None);
-                return Renderbuffers::CombinedDepthStencil(Some(renderbuffer));
+                // There's no packed format with a 16-bit depth buffer.
+                let (internal_format, depth_format) = match depth_format {
+                    DepthFormat::Depth16 | DepthFormat::Depth24 => {
+                        (gl::DEPTH24_STENCIL8, DepthFormat::Depth24)
+                    }
+                    DepthFormat::Depth32F => (gl::DEPTH32F_STENCIL8, DepthFormat::Depth32F),
+                };
+                let renderbuffer = create_renderbuffer(gl, internal_format, size, samples).unwrap();
+                return Renderbuffers::CombinedDepthStencil(Some(renderbuffer), depth_format);
             }
 
             let (mut depth_renderbuffer, mut stencil_renderbuffer) = (None, None);
             if attributes.flags.contains(ContextAttributeFlags::DEPTH) {
-                depth_renderbuffer = Some(gl.create_renderbuffer().unwrap());
-                gl.bind_renderbuffer(gl::RENDERBUFFER, depth_renderbuffer);
-                gl.renderbuffer_storage(
-                    gl::RENDERBUFFER,
-                    gl::DEPTH_COMPONENT24,
-                    size.width,
-                    size.height,
-                );
+                let internal_format = match depth_format {
+                    DepthFormat::Depth16 => gl::DEPTH_COMPONENT16,
+                    DepthFormat::Depth24 => gl::DEPTH_COMPONENT24,
+                    DepthFormat::Depth32F => gl::DEPTH_COMPONENT32F,
+                };
+                depth_renderbuffer =
+                    Some(create_renderbuffer(gl, internal_format, size, samples).unwrap());
             }
             if attributes.flags.contains(ContextAttributeFlags::STENCIL) {
-                stencil_renderbuffer = Some(gl.create_renderbuffer().unwrap());
-                gl.bind_renderbuffer(gl::RENDERBUFFER, stencil_renderbuffer);
-                gl.renderbuffer_storage(
-                    gl::RENDERBUFFER,
-                    gl::STENCIL_INDEX8,
-                    size.width,
-                    size.height,
-                );
+                stencil_renderbuffer =
+                    Some(create_renderbuffer(gl, gl::STENCIL_INDEX8, size, samples).unwrap());
             }
-            gl.bind_renderbuffer(gl::RENDERBUFFER, None);
 
             Renderbuffers::IndividualDepthStencil {
                 depth: depth_renderbuffer,
                 stencil: stencil_renderbuffer,
+                depth_format,
+            }
+        }
+    }
+
+    // Returns an empty set of renderbuffers, for surfaces that are never rendered to.
+    #[allow(dead_code)]
+    pub(crate) fn none() -> Renderbuffers {
+        Renderbuffers::IndividualDepthStencil {
+            depth: None,
+            stencil: None,
+            depth_format: DepthFormat::Depth24,
+        }
+    }
+
+    // Returns the format of the depth buffer, if there is one.
+    #[allow(dead_code)]
+    pub(crate) fn depth_format(&self) -> Option<DepthFormat> {
+        match *self {
+            Renderbuffers::IndividualDepthStencil {
+                depth: Some(_),
+                depth_format,
+                ..
             }
+            | Renderbuffers::CombinedDepthStencil(Some(_), depth_format) => Some(depth_format),
+            Renderbuffers::IndividualDepthStencil { depth: None, .. }
+            | Renderbuffers::CombinedDepthStencil(None, _) => None,
         }
     }
 
     pub(crate) fn bind_to_current_framebuffer(&self, gl: &Gl) {
         unsafe {
             match *self {
-                Renderbuffers::CombinedDepthStencil(renderbuffer) => {
+                Renderbuffers::CombinedDepthStencil(renderbuffer, _) => {
                     if renderbuffer.is_some() {
                         gl.framebuffer_renderbuffer(
                             gl::FRAMEBUFFER,
@@ -103,6 +140,7 @@ impl Renderbuffers {
                 Renderbuffers::IndividualDepthStencil {
                     depth: depth_renderbuffer,
                     stencil: stencil_renderbuffer,
+                    ..
                 } => {
                     if depth_renderbuffer.is_some() {
                         gl.framebuffer_renderbuffer(
@@ -130,7 +168,7 @@ impl Renderbuffers {
             gl.bind_renderbuffer(gl::RENDERBUFFER, None);
 
             match *self {
-                Renderbuffers::CombinedDepthStencil(ref mut renderbuffer) => {
+                Renderbuffers::CombinedDepthStencil(ref mut renderbuffer, _) => {
                     if let Some(renderbuffer) = renderbuffer.take() {
                         gl.delete_renderbuffer(renderbuffer);
                     }
@@ -138,6 +176,7 @@ impl Renderbuffers {
                 Renderbuffers::IndividualDepthStencil {
                     depth: ref mut depth_renderbuffer,
                     stencil: ref mut stencil_renderbuffer,
+                    ..
                 } => {
                     if let Some(stencil_renderbuffer) = stencil_renderbuffer.take() {
                         gl.delete_renderbuffer(stencil_renderbuffer);
@@ -150,3 +189,38 @@ impl Renderbuffers {
         }
     }
 }
+
+// Floating-point depth buffers need OpenGL 3.0, OpenGL ES 3.0, or `GL_ARB_depth_buffer_float`.
+fn supports_float_depth(gl: &Gl) -> bool {
+    let version = gl.version();
+    version.major >= 3
+        || (!version.is_embedded
+            && gl
+                .supported_extensions()
+                .contains("GL_ARB_depth_buffer_float"))
+}
+
+// Creates a renderbuffer with the given internal format, using multisampled storage if `samples` is
+// greater than 0.
+pub(crate) unsafe fn create_renderbuffer(
+    gl: &Gl,
+    internal_format: u32,
+    size: &Size2D<i32>,
+    samples: u32,
+) -> Result<Renderbuffer, String> {
+    let renderbuffer = gl.create_renderbuffer()?;
+    gl.bind_renderbuffer(gl::RENDERBUFFER, Some(renderbuffer));
+    if samples > 0 {
+        gl.renderbuffer_storage_multisample(
+            gl::RENDERBUFFER,
+            samples as i32,
+            internal_format,
+            size.width,
+            size.height,
+        );
+    } else {
+        gl.renderbuffer_storage(gl::RENDERBUFFER, internal_format, size.width, size.height);
+    }
+    gl.bind_renderbuffer(gl::RENDERBUFFER, None);
+    Ok(renderbuffer)
+}
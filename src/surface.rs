@@ -17,6 +17,13 @@ pub struct SystemSurfaceInfo {
 pub struct SurfaceInfo {
     /// The surface's size, in device pixels.
     pub size: Size2D<i32>,
+    /// The size of the part of the surface that holds valid contents, starting at the origin of
+    /// its framebuffer.
+    ///
+    /// This is the whole surface, except for surfaces from swap chains that reuse surfaces larger
+    /// than they need while resizing. Those report their valid size through
+    /// `SwapChain::surface_info()`.
+    pub valid_size: Size2D<i32>,
     /// The ID of the surface. This should be globally unique for each currently-allocated surface.
     pub id: SurfaceID,
     /// The ID of the context that this surface belongs to.
@@ -25,6 +32,17 @@ pub struct SurfaceInfo {
     ///
     /// This is only valid when the surface is actually attached to a context.
     pub framebuffer_object: Option<glow::Framebuffer>,
+    /// The format that the surface's pixels are stored in.
+    ///
+    /// This may differ from the format requested in the context attributes on backends whose
+    /// surfaces always use a single native format. It is `None` for surfaces that wrap buffers in
+    /// formats that no `SurfaceFormat` describes, such as multi-planar YUV dma-bufs.
+    pub format: Option<SurfaceFormat>,
+    /// The format of the depth buffer that `surfman` allocated for this surface, if any.
+    ///
+    /// This is `None` for surfaces without a depth buffer, and for surfaces whose depth buffer
+    /// belongs to the native surface, such as widget surfaces.
+    pub depth_format: Option<DepthFormat>,
 }
 
 // The default framebuffer for a context.
@@ -41,7 +59,7 @@ pub(crate) enum Framebuffer<S, E> {
 /// A unique ID per allocated surface.
 ///
 /// If you destroy a surface and then create a new one, the ID may be reused.
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct SurfaceID(pub usize);
 
 impl Display for SurfaceID {
@@ -89,6 +107,62 @@ pub enum SurfaceAccess {
     GPUCPUWriteCombined,
 }
 
+/// The layout of pixel data read back from a surface with `read_surface_pixels()`.
+///
+/// All formats use 8 bits per channel, and rows are tightly packed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PixelFormat {
+    /// Red, green, blue, and alpha channels, in that order.
+    RGBA8,
+    /// Blue, green, red, and alpha channels, in that order.
+    BGRA8,
+    /// Red, green, and blue channels, in that order. The alpha channel is discarded.
+    RGB8,
+}
+
+/// The format that a surface's pixels are stored in on the GPU.
+///
+/// This is selected with the `surface_format` field of the context attributes, and applies to the
+/// generic surfaces created from that context. Formats other than `RGBA8` need OpenGL 3.0 or OpenGL
+/// ES 3.0, plus extensions in some cases; creating a surface returns an `UnsupportedSurfaceFormat`
+/// error if the device can't render to the format or share it through an EGL image.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SurfaceFormat {
+    /// 8-bit red, green, blue, and alpha channels, in that order.
+    RGBA8,
+    /// 8-bit blue, green, red, and alpha channels, in that order.
+    BGRA8,
+    /// 8-bit red, green, blue, and alpha channels, with the color channels stored in the sRGB
+    /// encoding.
+    SRGBA8,
+    /// 10-bit red, green, and blue channels and a 2-bit alpha channel.
+    RGB10A2,
+    /// 16-bit floating-point red, green, blue, and alpha channels, for high dynamic range and
+    /// linear-light rendering.
+    RGBA16F,
+}
+
+/// The format of a surface's depth buffer.
+///
+/// This is selected with the `depth_format` field of the context attributes, and only matters if
+/// the context has the `DEPTH` flag. The stencil buffer, present if the context has the `STENCIL`
+/// flag, always has 8 bits.
+///
+/// Surfaces with both depth and stencil buffers use packed depth/stencil storage, and there is no
+/// packed format with a 16-bit depth buffer, so `Depth16` is widened to `Depth24` when the context
+/// has both the `DEPTH` and `STENCIL` flags. `Depth32F` falls back to `Depth24` if the device
+/// doesn't support floating-point depth buffers. The format that was actually used is reported in
+/// `SurfaceInfo::depth_format`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DepthFormat {
+    /// A 16-bit normalized depth buffer.
+    Depth16,
+    /// A 24-bit normalized depth buffer.
+    Depth24,
+    /// A 32-bit floating-point depth buffer, suitable for reverse-Z rendering.
+    Depth32F,
+}
+
 /// Information specific to the type of surface: generic or widget.
 #[derive(Clone)]
 pub enum SurfaceType<NativeWidget> {
@@ -123,3 +197,14 @@ impl SurfaceAccess {
         }
     }
 }
+
+impl PixelFormat {
+    /// Returns the number of bytes that each pixel occupies in this format.
+    #[inline]
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::RGBA8 | PixelFormat::BGRA8 => 4,
+            PixelFormat::RGB8 => 3,
+        }
+    }
+}
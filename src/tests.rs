@@ -11,13 +11,15 @@ use super::context::{Context, ContextDescriptor, NativeContext};
 use super::device::{Adapter, Device};
 use super::surface::Surface;
 use crate::gl;
-use crate::{ContextAttributeFlags, ContextAttributes, Error, GLApi, GLVersion, Gl, SurfaceAccess};
+use crate::{ContextAttributeFlags, ContextAttributes, ContextResetStatus, Error, GLApi};
+use crate::{DepthFormat, GLVersion, Gl, PixelFormat, SurfaceAccess, SurfaceFormat};
 use crate::{SurfaceType, WindowingApiError};
 
-use euclid::default::Size2D;
+use euclid::default::{Point2D, Rect, Size2D};
 use glow::{Framebuffer, HasContext, PixelPackData, Texture};
 use std::sync::mpsc;
 use std::thread;
+use std::time::Duration;
 
 static GL_VERSIONS: [GLVersion; 6] = [
     GLVersion { major: 2, minor: 0 },
@@ -37,15 +39,67 @@ static GL_ES_VERSIONS: [GLVersion; 4] = [
 
 #[cfg_attr(not(feature = "sm-test"), test)]
 pub fn test_adapter_creation() {
-    let connection = Connection::new().unwrap();
+    let connection = match create_connection() {
+        Some(connection) => connection,
+        None => return,
+    };
     connection.create_hardware_adapter().unwrap();
     connection.create_low_power_adapter().unwrap();
     connection.create_software_adapter().unwrap();
 }
 
+// Tests that every enumerated adapter can be used to render.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_adapter_enumeration() {
+    let connection = match create_connection() {
+        Some(connection) => connection,
+        None => return,
+    };
+    for adapter_info in connection.enumerate_adapters() {
+        let mut device = match connection.create_device(&adapter_info.adapter) {
+            Ok(device) => device,
+            Err(Error::RequiredExtensionUnavailable) | Err(Error::ConnectionFailed) => {
+                // Not every EGL device can be opened by this backend.
+                continue;
+            }
+            Err(err) => panic!("Failed to create device: {:?}", err),
+        };
+
+        let context_descriptor = device
+            .create_context_descriptor(&ContextAttributes {
+                version: GLVersion::new(3, 0),
+                flags: ContextAttributeFlags::empty(),
+                samples: 0,
+                surface_format: SurfaceFormat::RGBA8,
+                depth_format: DepthFormat::Depth24,
+            })
+            .unwrap();
+        let mut context = device.create_context(&context_descriptor, None).unwrap();
+        let surface = make_surface(&mut device, &context);
+        device
+            .bind_surface_to_context(&mut context, surface)
+            .unwrap();
+        device.make_context_current(&context).unwrap();
+
+        let gl =
+            unsafe { Gl::from_loader_function(|symbol| device.get_proc_address(&context, symbol)) };
+        bind_context_fbo(&gl, &device, &context);
+        unsafe {
+            gl.viewport(0, 0, 640, 480);
+        }
+        clear(&gl, &[0, 255, 0, 255]);
+        assert_eq!(get_pixel_from_bottom_row(&gl), [0, 255, 0, 255]);
+
+        device.destroy_context(&mut context).unwrap();
+    }
+}
+
 #[cfg_attr(not(feature = "sm-test"), test)]
 pub fn test_device_creation() {
-    let connection = Connection::new().unwrap();
+    let connection = match create_connection() {
+        Some(connection) => connection,
+        None => return,
+    };
     let adapter = connection
         .create_low_power_adapter()
         .expect("Failed to create adapter!");
@@ -60,7 +114,10 @@ pub fn test_device_creation() {
 
 #[cfg_attr(not(feature = "sm-test"), test)]
 pub fn test_device_accessors() {
-    let connection = Connection::new().unwrap();
+    let connection = match create_connection() {
+        Some(connection) => connection,
+        None => return,
+    };
     let adapter = connection.create_low_power_adapter().unwrap();
     let device = match connection.create_device(&adapter) {
         Ok(device) => device,
@@ -78,7 +135,10 @@ pub fn test_device_accessors() {
 // contexts.
 #[cfg_attr(not(feature = "sm-test"), test)]
 pub fn test_context_creation() {
-    let connection = Connection::new().unwrap();
+    let connection = match create_connection() {
+        Some(connection) => connection,
+        None => return,
+    };
     let adapter = connection
         .create_low_power_adapter()
         .expect("Failed to create adapter!");
@@ -100,7 +160,13 @@ pub fn test_context_creation() {
     for &version in versions {
         for flag_bits in 0..(ContextAttributeFlags::all().bits() + 1) {
             let flags = ContextAttributeFlags::from_bits_truncate(flag_bits);
-            let attributes = ContextAttributes { version, flags };
+            let attributes = ContextAttributes {
+                version,
+                flags,
+                samples: 0,
+                surface_format: SurfaceFormat::RGBA8,
+                depth_format: DepthFormat::Depth24,
+            };
             let descriptor = match device.create_context_descriptor(&attributes) {
                 Ok(descriptor) => descriptor,
                 Err(Error::UnsupportedGLProfile) | Err(Error::UnsupportedGLVersion) => {
@@ -166,7 +232,10 @@ pub fn test_context_creation() {
 // Tests that newly-created contexts are made current (https://github.com/pcwalton/surfman/issues/7).
 #[cfg_attr(not(feature = "sm-test"), test)]
 pub fn test_newly_created_contexts_are_current() {
-    let connection = Connection::new().unwrap();
+    let connection = match create_connection() {
+        Some(connection) => connection,
+        None => return,
+    };
     let adapter = connection
         .create_low_power_adapter()
         .expect("Failed to create adapter!");
@@ -183,6 +252,9 @@ pub fn test_newly_created_contexts_are_current() {
         .create_context_descriptor(&ContextAttributes {
             version: GLVersion::new(3, 0),
             flags: ContextAttributeFlags::empty(),
+            samples: 0,
+            surface_format: SurfaceFormat::RGBA8,
+            depth_format: DepthFormat::Depth24,
         })
         .unwrap();
 
@@ -232,7 +304,10 @@ pub fn test_newly_created_contexts_are_current() {
 // Tests a simple case of one context being shared with another.
 #[cfg_attr(not(feature = "sm-test"), test)]
 pub fn test_context_sharing() {
-    let connection = Connection::new().unwrap();
+    let connection = match create_connection() {
+        Some(connection) => connection,
+        None => return,
+    };
     let adapter = connection
         .create_low_power_adapter()
         .expect("Failed to create adapter!");
@@ -249,6 +324,9 @@ pub fn test_context_sharing() {
         .create_context_descriptor(&ContextAttributes {
             version: GLVersion::new(3, 0),
             flags: ContextAttributeFlags::empty(),
+            samples: 0,
+            surface_format: SurfaceFormat::RGBA8,
+            depth_format: DepthFormat::Depth24,
         })
         .unwrap();
 
@@ -272,7 +350,10 @@ pub fn test_context_sharing() {
 // Tests that generic surfaces can be created.
 #[cfg_attr(not(feature = "sm-test"), test)]
 pub fn test_generic_surface_creation() {
-    let connection = Connection::new().unwrap();
+    let connection = match create_connection() {
+        Some(connection) => connection,
+        None => return,
+    };
     let adapter = connection
         .create_low_power_adapter()
         .expect("Failed to create adapter!");
@@ -289,6 +370,9 @@ pub fn test_generic_surface_creation() {
         .create_context_descriptor(&ContextAttributes {
             version: GLVersion::new(3, 0),
             flags: ContextAttributeFlags::empty(),
+            samples: 0,
+            surface_format: SurfaceFormat::RGBA8,
+            depth_format: DepthFormat::Depth24,
         })
         .unwrap();
 
@@ -746,7 +830,10 @@ pub fn test_depth_and_stencil() {
 
     use glow::PixelPackData;
 
-    let connection = Connection::new().unwrap();
+    let connection = match create_connection() {
+        Some(connection) => connection,
+        None => return,
+    };
     let adapter = connection
         .create_low_power_adapter()
         .expect("Failed to create adapter!");
@@ -770,6 +857,9 @@ pub fn test_depth_and_stencil() {
         .create_context_descriptor(&ContextAttributes {
             version: GLVersion::new(3, 0),
             flags: ContextAttributeFlags::DEPTH,
+            samples: 0,
+            surface_format: SurfaceFormat::RGBA8,
+            depth_format: DepthFormat::Depth24,
         })
         .unwrap();
 
@@ -826,6 +916,9 @@ pub fn test_depth_and_stencil() {
         .create_context_descriptor(&ContextAttributes {
             version: GLVersion::new(3, 0),
             flags: ContextAttributeFlags::STENCIL,
+            samples: 0,
+            surface_format: SurfaceFormat::RGBA8,
+            depth_format: DepthFormat::Depth24,
         })
         .unwrap();
 
@@ -870,135 +963,1681 @@ pub fn test_depth_and_stencil() {
     device.destroy_context(&mut stencil_context).unwrap();
 }
 
-// Make sure that the current native context can be fetched and that they can be correctly wrapped
-// in `surfman` contexts.
+// Tests that the CPU can read and write the pixels of generic surfaces.
 #[cfg_attr(not(feature = "sm-test"), test)]
-pub fn test_get_native_context() {
+pub fn test_surface_data_lock() {
     let mut env = match BasicEnvironment::new() {
         None => return,
         Some(env) => env,
     };
 
-    let native_context = NativeContext::current().unwrap();
+    // GPU-only surfaces can't be locked.
+    let mut gpu_surface = make_surface(&mut env.device, &env.context);
+    match env.device.lock_surface_data(&mut gpu_surface) {
+        Err(Error::SurfaceDataInaccessible) | Err(Error::Unimplemented) => {}
+        Err(err) => panic!("Unexpected error locking a GPU-only surface: {:?}", err),
+        Ok(_) => panic!("GPU-only surfaces shouldn't be accessible from the CPU!"),
+    }
+    env.device
+        .destroy_surface(&mut env.context, &mut gpu_surface)
+        .unwrap();
 
     unsafe {
-        clear(&env.gl, &[0, 255, 0, 255]);
-
-        let mut other_context = env
+        // Render a red surface with a green bottom row.
+        let cpu_surface = env
             .device
-            .create_context_from_native_context(native_context)
+            .create_surface(
+                &env.context,
+                SurfaceAccess::GPUCPU,
+                SurfaceType::Generic {
+                    size: Size2D::new(640, 480),
+                },
+            )
+            .unwrap();
+        let main_surface = env
+            .device
+            .unbind_surface_from_context(&mut env.context)
+            .unwrap()
+            .unwrap();
+        env.device
+            .bind_surface_to_context(&mut env.context, cpu_surface)
+            .unwrap();
+        bind_context_fbo(&env.gl, &env.device, &env.context);
+        clear(&env.gl, &[255, 0, 0, 255]);
+        clear_bottom_row(&env.gl, &[0, 255, 0, 255]);
+        let mut cpu_surface = env
+            .device
+            .unbind_surface_from_context(&mut env.context)
+            .unwrap()
+            .unwrap();
+        env.device
+            .bind_surface_to_context(&mut env.context, main_surface)
             .unwrap();
 
-        env.device.make_context_current(&other_context).unwrap();
+        // Read the pixels back, and paint the top row blue.
+        let locked = match env.device.lock_surface_data(&mut cpu_surface) {
+            Ok(mut surface_data) => {
+                let stride = surface_data.stride();
+                assert_eq!(stride, 640 * 4);
+                let data = surface_data.data();
+                assert_eq!(data[0..4], [255, 0, 0, 255]);
+                assert_eq!(data[(479 * stride)..(479 * stride + 4)], [0, 255, 0, 255]);
+                for pixel in data[0..stride].chunks_mut(4) {
+                    pixel.copy_from_slice(&[0, 0, 255, 255]);
+                }
+                true
+            }
+            Err(Error::Unimplemented) => false,
+            Err(err) => panic!("Failed to lock surface data: {:?}", err),
+        };
+        if !locked {
+            env.device
+                .destroy_surface(&mut env.context, &mut cpu_surface)
+                .unwrap();
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
+        }
+
+        // Make sure the CPU writes made it back to the GPU.
+        let cpu_surface_texture = env
+            .device
+            .create_surface_texture(&mut env.context, cpu_surface)
+            .unwrap();
+        let cpu_framebuffer_object = make_fbo(
+            &env.gl,
+            env.device.surface_gl_texture_target(),
+            env.device.surface_texture_object(&cpu_surface_texture),
+        );
+        let main_framebuffer_object = context_fbo(&env.device, &env.context);
+        blit_fbo(
+            &env.gl,
+            main_framebuffer_object,
+            Some(cpu_framebuffer_object),
+        );
+        env.gl
+            .bind_framebuffer(gl::FRAMEBUFFER, main_framebuffer_object);
+        check_gl(&env.gl);
         assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+        assert_eq!(get_pixel_from_top_row(&env.gl), [0, 0, 255, 255]);
 
-        env.device.destroy_context(&mut other_context).unwrap();
-    }
+        // Clean up.
+        env.gl.bind_framebuffer(gl::FRAMEBUFFER, None);
+        check_gl(&env.gl);
+        env.gl.delete_framebuffer(cpu_framebuffer_object);
 
-    env.device.destroy_context(&mut env.context).unwrap();
+        let mut cpu_surface = env
+            .device
+            .destroy_surface_texture(&mut env.context, cpu_surface_texture)
+            .unwrap();
+        env.device
+            .destroy_surface(&mut env.context, &mut cpu_surface)
+            .unwrap();
+        env.device.destroy_context(&mut env.context).unwrap();
+    }
 }
 
-fn bind_context_fbo(gl: &Gl, device: &Device, context: &Context) {
-    unsafe {
-        gl.bind_framebuffer(gl::FRAMEBUFFER, context_fbo(device, context));
-        check_gl(gl);
+// Tests that surface pixels are read back top-down in the requested format.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_read_surface_pixels() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Render a red surface with a blue bottom row.
+    bind_context_fbo(&env.gl, &env.device, &env.context);
+    clear(&env.gl, &[255, 0, 0, 255]);
+    clear_bottom_row(&env.gl, &[0, 0, 255, 255]);
+    let mut surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
+        .unwrap()
+        .unwrap();
+
+    let pixels = match env.device.read_surface_pixels(
+        &mut env.context,
+        &surface,
+        Rect::new(Point2D::zero(), Size2D::new(640, 480)),
+        PixelFormat::RGBA8,
+    ) {
+        Ok(pixels) => pixels,
+        Err(Error::Unimplemented) => {
+            env.device
+                .destroy_surface(&mut env.context, &mut surface)
+                .unwrap();
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
+        }
+        Err(err) => panic!("Failed to read surface pixels: {:?}", err),
+    };
+    assert_eq!(pixels.len(), 640 * 480 * 4);
+    assert_eq!(pixels[0..4], [255, 0, 0, 255]);
+    assert_eq!(
+        pixels[(479 * 640 * 4)..(479 * 640 * 4 + 4)],
+        [0, 0, 255, 255]
+    );
+
+    // Read the bottom two rows of a small region, swizzled.
+    let pixels = env
+        .device
+        .read_surface_pixels(
+            &mut env.context,
+            &surface,
+            Rect::new(Point2D::new(8, 478), Size2D::new(2, 2)),
+            PixelFormat::BGRA8,
+        )
+        .unwrap();
+    assert_eq!(
+        pixels,
+        [0, 0, 255, 255, 0, 0, 255, 255, 255, 0, 0, 255, 255, 0, 0, 255]
+    );
+
+    let pixels = env
+        .device
+        .read_surface_pixels(
+            &mut env.context,
+            &surface,
+            Rect::new(Point2D::new(0, 479), Size2D::new(2, 1)),
+            PixelFormat::RGB8,
+        )
+        .unwrap();
+    assert_eq!(pixels, [0, 0, 255, 0, 0, 255]);
+
+    match env.device.read_surface_pixels(
+        &mut env.context,
+        &surface,
+        Rect::new(Point2D::new(0, 479), Size2D::new(1, 2)),
+        PixelFormat::RGBA8,
+    ) {
+        Err(Error::InvalidRect) => {}
+        Err(err) => panic!("Unexpected error reading outside the surface: {:?}", err),
+        Ok(_) => panic!("Reading outside the surface should fail!"),
+    }
+    match env.device.read_surface_pixels(
+        &mut env.context,
+        &surface,
+        Rect::new(Point2D::new(1, 0), Size2D::new(i32::MAX, 1)),
+        PixelFormat::RGBA8,
+    ) {
+        Err(Error::InvalidRect) => {}
+        Err(err) => panic!("Unexpected error reading an overflowing rect: {:?}", err),
+        Ok(_) => panic!("Reading an overflowing rect should fail!"),
     }
+
+    // Clean up.
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
 }
 
-fn context_fbo(device: &Device, context: &Context) -> Option<Framebuffer> {
-    device
-        .context_surface_info(context)
+// Tests that surfaces can be read back asynchronously through pixel pack buffers.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_readback() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Render a red surface with a green bottom row.
+    bind_context_fbo(&env.gl, &env.device, &env.context);
+    clear(&env.gl, &[255, 0, 0, 255]);
+    clear_bottom_row(&env.gl, &[0, 255, 0, 255]);
+    let mut surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
         .unwrap()
+        .unwrap();
+
+    let readback = match env
+        .device
+        .begin_surface_readback(&mut env.context, &surface)
+    {
+        Ok(readback) => readback,
+        Err(Error::RequiredExtensionUnavailable) | Err(Error::Unimplemented) => {
+            env.device
+                .destroy_surface(&mut env.context, &mut surface)
+                .unwrap();
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
+        }
+        Err(err) => panic!("Failed to begin surface readback: {:?}", err),
+    };
+    assert_eq!(readback.size(), Size2D::new(640, 480));
+
+    // Changes made after the readback began must not show up in it.
+    env.device
+        .bind_surface_to_context(&mut env.context, surface)
+        .unwrap();
+    bind_context_fbo(&env.gl, &env.device, &env.context);
+    clear(&env.gl, &[0, 0, 255, 255]);
+    let mut surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
         .unwrap()
-        .framebuffer_object
+        .unwrap();
+
+    env.device
+        .poll_surface_readback(&env.context, &readback)
+        .unwrap();
+    let pixels = env
+        .device
+        .map_surface_readback(&mut env.context, readback)
+        .unwrap();
+    assert_eq!(pixels.len(), 640 * 480 * 4);
+    assert_eq!(pixels[0..4], [255, 0, 0, 255]);
+    assert_eq!(
+        pixels[(479 * 640 * 4)..(479 * 640 * 4 + 4)],
+        [0, 255, 0, 255]
+    );
+
+    // Readbacks that are never mapped can be destroyed instead.
+    let readback = env
+        .device
+        .begin_surface_readback(&mut env.context, &surface)
+        .unwrap();
+    env.device
+        .destroy_surface_readback(&mut env.context, readback)
+        .unwrap();
+
+    // Clean up.
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
 }
 
-fn make_surface(device: &mut Device, context: &Context) -> Surface {
-    device
-        .create_surface(
-            context,
-            SurfaceAccess::GPUOnly,
-            SurfaceType::Generic {
-                size: Size2D::new(640, 480),
-            },
-        )
+#[cfg(free_unix)]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_export_surface_dmabuf() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    bind_context_fbo(&env.gl, &env.device, &env.context);
+    clear(&env.gl, &[255, 0, 0, 255]);
+    let mut surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
         .unwrap()
-}
+        .unwrap();
 
-fn blit_fbo(gl: &Gl, dest_fbo: Option<Framebuffer>, src_fbo: Option<Framebuffer>) {
-    unsafe {
-        gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, dest_fbo);
-        check_gl(gl);
-        gl.bind_framebuffer(gl::READ_FRAMEBUFFER, src_fbo);
-        check_gl(gl);
-        gl.blit_framebuffer(
-            0,
-            0,
-            640,
-            480,
-            0,
-            0,
-            640,
-            480,
-            gl::COLOR_BUFFER_BIT,
-            gl::NEAREST,
-        );
-        check_gl(gl);
+    match env.device.export_surface_dmabuf(&surface) {
+        Ok(descriptor) => {
+            assert_eq!(descriptor.size, Size2D::new(640, 480));
+            // `DRM_FORMAT_ABGR8888`, which has the same layout as `SurfaceFormat::RGBA8`.
+            assert_eq!(descriptor.fourcc, u32::from_le_bytes(*b"AB24"));
+            assert!(!descriptor.fds.is_empty());
+            assert_eq!(descriptor.fds.len(), descriptor.strides.len());
+            assert_eq!(descriptor.fds.len(), descriptor.offsets.len());
+            assert!(descriptor.strides[0] >= 640 * 4);
+        }
+        Err(Error::RequiredExtensionUnavailable) => {}
+        Err(err) => panic!("Failed to export surface: {:?}", err),
     }
-}
 
-fn make_fbo(gl: &Gl, texture_target: u32, texture: Option<Texture>) -> Framebuffer {
-    unsafe {
-        let framebuffer_object = gl.create_framebuffer().unwrap();
-        check_gl(gl);
-        gl.bind_framebuffer(gl::FRAMEBUFFER, Some(framebuffer_object));
-        check_gl(gl);
-        gl.framebuffer_texture_2d(
-            gl::FRAMEBUFFER,
-            gl::COLOR_ATTACHMENT0,
-            texture_target,
-            texture,
-            0,
-        );
-        check_gl(gl);
-        assert_eq!(
-            gl.check_framebuffer_status(gl::FRAMEBUFFER),
-            gl::FRAMEBUFFER_COMPLETE
-        );
-        framebuffer_object
-    }
+    // Clean up.
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
 }
 
-struct BasicEnvironment {
-    connection: Connection,
-    adapter: Adapter,
-    device: Device,
-    context_descriptor: ContextDescriptor,
-    context: Context,
-    gl: Gl,
-}
+#[cfg(free_unix)]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_import_surface_dmabuf() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
 
-impl BasicEnvironment {
-    fn new() -> Option<BasicEnvironment> {
-        let connection = Connection::new().unwrap();
-        let adapter = connection
-            .create_low_power_adapter()
-            .expect("Failed to create adapter!");
-        let mut device = match connection.create_device(&adapter) {
-            Ok(device) => device,
-            Err(Error::RequiredExtensionUnavailable) => {
-                // Can't run these tests on this hardware.
-                return None;
+    match env.device.dmabuf_formats() {
+        Ok(formats) => {
+            for format in formats {
+                assert_ne!(format.fourcc, 0);
             }
-            Err(err) => panic!("Failed to create device: {:?}", err),
-        };
+        }
+        Err(Error::RequiredExtensionUnavailable) => {}
+        Err(err) => panic!("Failed to query dma-buf formats: {:?}", err),
+    }
 
-        let context_descriptor = device
-            .create_context_descriptor(&ContextAttributes {
-                version: GLVersion::new(3, 0),
-                flags: ContextAttributeFlags::empty(),
-            })
-            .unwrap();
+    bind_context_fbo(&env.gl, &env.device, &env.context);
+    clear(&env.gl, &[255, 0, 0, 255]);
+    let mut surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
+        .unwrap()
+        .unwrap();
+
+    let descriptor = match env.device.export_surface_dmabuf(&surface) {
+        Ok(descriptor) => descriptor,
+        Err(Error::RequiredExtensionUnavailable) => {
+            env.device
+                .destroy_surface(&mut env.context, &mut surface)
+                .unwrap();
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
+        }
+        Err(err) => panic!("Failed to export surface: {:?}", err),
+    };
+
+    // Rendering to a surface that wraps the buffer changes the original surface.
+    let imported_surface = env
+        .device
+        .create_surface_from_dmabuf(&env.context, SurfaceAccess::GPUOnly, &descriptor)
+        .unwrap();
+    env.device
+        .bind_surface_to_context(&mut env.context, imported_surface)
+        .unwrap();
+    bind_context_fbo(&env.gl, &env.device, &env.context);
+    clear(&env.gl, &[0, 255, 0, 255]);
+    let mut imported_surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
+        .unwrap()
+        .unwrap();
+    let pixels = env
+        .device
+        .read_surface_pixels(
+            &mut env.context,
+            &surface,
+            Rect::new(Point2D::zero(), Size2D::new(1, 1)),
+            PixelFormat::RGBA8,
+        )
+        .unwrap();
+    assert_eq!(pixels, [0, 255, 0, 255]);
+
+    // Buffers imported as surface textures can be sampled, but not rendered to. Errors that the
+    // caller left pending mustn't be mistaken for a failure to import.
+    unsafe {
+        env.gl.enable(gl::INVALID_ENUM);
+    }
+    let surface_texture = env
+        .device
+        .create_surface_texture_from_dmabuf(&mut env.context, &descriptor)
+        .unwrap();
+    assert!(env
+        .device
+        .surface_texture_object(&surface_texture)
+        .is_some());
+    assert_eq!(
+        env.device
+            .surface_texture_gl_texture_target(&surface_texture),
+        env.device.surface_gl_texture_target()
+    );
+    let texture_surface = env
+        .device
+        .destroy_surface_texture(&mut env.context, surface_texture)
+        .unwrap();
+    let mut texture_surface = match env
+        .device
+        .bind_surface_to_context(&mut env.context, texture_surface)
+    {
+        Err((Error::IncompatibleSurface, texture_surface)) => texture_surface,
+        _ => panic!("Surfaces imported for sampling shouldn't be renderable!"),
+    };
+    assert_eq!(
+        env.device.surface_info(&texture_surface).format,
+        Some(SurfaceFormat::RGBA8)
+    );
+
+    // Clean up.
+    env.device
+        .destroy_surface(&mut env.context, &mut texture_surface)
+        .unwrap();
+    env.device
+        .destroy_surface(&mut env.context, &mut imported_surface)
+        .unwrap();
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg(free_unix)]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_ipc_surface_sharing() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let (mut sender, mut receiver) = crate::ipc::channel().unwrap();
+    assert_eq!(sender.try_receive_release().unwrap(), None);
+
+    bind_context_fbo(&env.gl, &env.device, &env.context);
+    clear(&env.gl, &[255, 0, 0, 255]);
+    let mut surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
+        .unwrap()
+        .unwrap();
+
+    let id = match sender.send(&env.device, &surface) {
+        Ok(id) => id,
+        Err(Error::RequiredExtensionUnavailable) => {
+            env.device
+                .destroy_surface(&mut env.context, &mut surface)
+                .unwrap();
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
+        }
+        Err(err) => panic!("Failed to send surface: {:?}", err),
+    };
+
+    let (received_id, surface_texture) = receiver.receive(&env.device, &mut env.context).unwrap();
+    assert_eq!(received_id, id);
+    let framebuffer_object = make_fbo(
+        &env.gl,
+        env.device.surface_gl_texture_target(),
+        env.device.surface_texture_object(&surface_texture),
+    );
+    unsafe {
+        env.gl
+            .bind_framebuffer(gl::FRAMEBUFFER, Some(framebuffer_object));
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [255, 0, 0, 255]);
+        env.gl.bind_framebuffer(gl::FRAMEBUFFER, None);
+        env.gl.delete_framebuffer(framebuffer_object);
+    }
+    let mut received_surface = env
+        .device
+        .destroy_surface_texture(&mut env.context, surface_texture)
+        .unwrap();
+    env.device
+        .destroy_surface(&mut env.context, &mut received_surface)
+        .unwrap();
+
+    // The producer finds out that the surface can be reused.
+    receiver.release(received_id).unwrap();
+    assert_eq!(sender.wait_for_release().unwrap(), id);
+    assert_eq!(sender.try_receive_release().unwrap(), None);
+
+    // Clean up.
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Make sure that the current native context can be fetched and that they can be correctly wrapped
+// in `surfman` contexts.
+// Tests that a fence inserted after rendering is signaled, and that other contexts can wait on it.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_fences() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    bind_context_fbo(&env.gl, &env.device, &env.context);
+    clear(&env.gl, &[0, 255, 0, 255]);
+    let mut fence = match env.device.create_fence(&env.context) {
+        Ok(fence) => fence,
+        Err(Error::RequiredExtensionUnavailable) => {
+            env.device.destroy_context(&mut env.context).unwrap();
+            return;
+        }
+        Err(err) => panic!("Failed to create fence: {:?}", err),
+    };
+    assert!(env
+        .device
+        .client_wait_fence(&fence, Some(Duration::from_secs(10)))
+        .unwrap());
+    // Waiting again on a signaled fence returns immediately.
+    assert!(env.device.client_wait_fence(&fence, None).unwrap());
+
+    let mut other_context = env
+        .device
+        .create_context(&env.context_descriptor, None)
+        .unwrap();
+    match env.device.server_wait_fence(&other_context, &fence) {
+        Ok(()) | Err(Error::RequiredExtensionUnavailable) => {}
+        Err(err) => panic!("Failed to wait on fence: {:?}", err),
+    }
+
+    #[cfg(free_unix)]
+    match env.device.export_native_fence_fd(&fence) {
+        Ok(fd) => {
+            let mut imported_fence = env
+                .device
+                .import_native_fence_fd(&other_context, fd)
+                .unwrap();
+            assert!(env
+                .device
+                .client_wait_fence(&imported_fence, Some(Duration::from_secs(10)))
+                .unwrap());
+            env.device.destroy_fence(&mut imported_fence).unwrap();
+        }
+        Err(Error::RequiredExtensionUnavailable) => {}
+        Err(err) => panic!("Failed to export fence: {:?}", err),
+    }
+
+    // Clean up.
+    env.device.destroy_fence(&mut fence).unwrap();
+    env.device.destroy_context(&mut other_context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that generic surfaces can't be presented, with or without damage.
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_present_generic_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let mut surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
+        .unwrap()
+        .unwrap();
+    match env.device.present_surface(&env.context, &mut surface) {
+        Err(Error::NoWidgetAttached) => {}
+        result => panic!("Presenting a generic surface should fail: {:?}", result),
+    }
+    let damage = [Rect::new(Point2D::new(0, 0), Size2D::new(16, 16))];
+    match env
+        .device
+        .present_surface_with_damage(&env.context, &mut surface, &damage)
+    {
+        Err(Error::NoWidgetAttached) => {}
+        result => panic!("Presenting a generic surface should fail: {:?}", result),
+    }
+    match env.device.set_swap_interval(&env.context, &surface, 0) {
+        Err(Error::NoWidgetAttached) | Err(Error::Unimplemented) => {}
+        result => panic!(
+            "Generic surfaces shouldn't have a swap interval: {:?}",
+            result
+        ),
+    }
+
+    // Clean up.
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+// Tests that swap intervals are checked against the range that the EGL config of a window surface
+// supports. A pbuffer stands in for the window, since there may be no window system to create one
+// with.
+#[cfg(free_unix)]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_swap_interval_range() {
+    use crate::base::egl::context as egl_context;
+    use crate::base::egl::device::EGL_FUNCTIONS;
+    use crate::base::egl::surface::{EGLBackedSurface, EGLSurfaceObjects};
+    use crate::egl;
+    use crate::egl::types::EGLint;
+    use std::cell::Cell;
+    use std::ptr;
+
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let egl_context = env.device.native_context(&env.context).egl_context;
+    let (egl_display, egl_config, egl_surface) = EGL_FUNCTIONS.with(|egl| unsafe {
+        let egl_display = egl.GetCurrentDisplay();
+        let mut egl_config_id = 0;
+        egl.QueryContext(
+            egl_display,
+            egl_context,
+            egl::CONFIG_ID as EGLint,
+            &mut egl_config_id,
+        );
+        let egl_config = egl_context::egl_config_from_id(egl_display, egl_config_id);
+        let attributes = [
+            egl::WIDTH as EGLint,
+            16,
+            egl::HEIGHT as EGLint,
+            16,
+            egl::NONE as EGLint,
+        ];
+        let egl_surface = egl.CreatePbufferSurface(egl_display, egl_config, attributes.as_ptr());
+        (egl_display, egl_config, egl_surface)
+    });
+    if egl_surface == egl::NO_SURFACE {
+        // The context's config can't create pbuffers.
+        env.device.destroy_context(&mut env.context).unwrap();
+        return;
+    }
+
+    let context_id = env.device.context_id(&env.context);
+    let mut surface = EGLBackedSurface {
+        context_id,
+        size: Size2D::new(16, 16),
+        access: SurfaceAccess::GPUOnly,
+        format: Some(SurfaceFormat::RGBA8),
+        objects: EGLSurfaceObjects::Window {
+            native_window: ptr::null(),
+            egl_surface,
+            swap_interval: Cell::new(None),
+        },
+        destroyed: false,
+    };
+
+    let (min_interval, max_interval) = unsafe {
+        (
+            egl_context::get_config_attr(egl_display, egl_config, egl::MIN_SWAP_INTERVAL as EGLint),
+            egl_context::get_config_attr(egl_display, egl_config, egl::MAX_SWAP_INTERVAL as EGLint),
+        )
+    };
+    for interval in [min_interval, max_interval] {
+        surface
+            .set_swap_interval(egl_display, egl_context, interval)
+            .unwrap();
+        // Presenting reapplies the interval.
+        surface.present(egl_display, egl_context, &[]).unwrap();
+    }
+    for interval in [min_interval - 1, max_interval + 1] {
+        match surface.set_swap_interval(egl_display, egl_context, interval) {
+            Err(Error::UnsupportedSwapInterval) => {}
+            result => panic!(
+                "Swap interval {} is outside {}..={}: {:?}",
+                interval, min_interval, max_interval, result
+            ),
+        }
+    }
+
+    // Clean up.
+    surface.destroy(&env.gl, egl_display, context_id).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_generic_surface_buffer_age() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Buffer ages and damage regions only make sense for widget surfaces.
+    let mut surface = env
+        .device
+        .unbind_surface_from_context(&mut env.context)
+        .unwrap()
+        .unwrap();
+    match env.device.surface_buffer_age(&env.context, &surface) {
+        Err(Error::NoWidgetAttached) | Err(Error::Unimplemented) => {}
+        result => panic!("Generic surfaces shouldn't have a buffer age: {:?}", result),
+    }
+    let damage = [Rect::new(Point2D::new(0, 0), Size2D::new(16, 16))];
+    match env
+        .device
+        .set_damage_region(&env.context, &mut surface, &damage)
+    {
+        Err(Error::NoWidgetAttached) | Err(Error::Unimplemented) => {}
+        result => panic!(
+            "Generic surfaces shouldn't take a damage region: {:?}",
+            result
+        ),
+    }
+
+    // Clean up.
+    env.device
+        .destroy_surface(&mut env.context, &mut surface)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_swap_chain_fifo_present_mode() {
+    use crate::chains::{PresentMode, PreserveBuffer, SwapChain, SwapChainAPI};
+
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let swap_chain = SwapChain::create_detached(
+        &env.device,
+        &mut env.context,
+        SurfaceAccess::GPUOnly,
+        Size2D::new(16, 16),
+    )
+    .unwrap();
+    swap_chain
+        .set_present_mode(PresentMode::Fifo { queue_depth: 2 })
+        .unwrap();
+    swap_chain.set_max_surfaces(Some(3)).unwrap();
+
+    // Frames queue up rather than being dropped, until the queue is full.
+    for _ in 0..2 {
+        swap_chain
+            .swap_buffers(&env.device, &mut env.context, PreserveBuffer::No)
+            .unwrap();
+    }
+    match swap_chain.swap_buffers(&env.device, &mut env.context, PreserveBuffer::No) {
+        Err(Error::WouldBlock) => {}
+        result => panic!("Swapping with a full queue should block: {:?}", result),
+    }
+    let first = swap_chain.take_surface().unwrap();
+    let second = swap_chain.take_surface().unwrap();
+    assert_ne!(
+        env.device.surface_info(&first).id,
+        env.device.surface_info(&second).id
+    );
+    assert!(swap_chain.take_pending_surface().is_none());
+
+    // The consumer holds every surface but the back buffer, so the surface limit is reached.
+    match swap_chain.swap_buffers(&env.device, &mut env.context, PreserveBuffer::No) {
+        Err(Error::WouldBlock) => {}
+        result => panic!("Swapping with no free surfaces should block: {:?}", result),
+    }
+    swap_chain.recycle_surface(first);
+    swap_chain
+        .swap_buffers(&env.device, &mut env.context, PreserveBuffer::No)
+        .unwrap();
+
+    // Clean up.
+    swap_chain.recycle_surface(second);
+    swap_chain.destroy(&env.device, &mut env.context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_swap_chain_wait_for_surface() {
+    use crate::chains::{PreserveBuffer, SwapChainAPI, SwapChains, SwapChainsAPI};
+
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let swap_chains = SwapChains::new();
+    for id in 0..2 {
+        swap_chains
+            .create_detached_swap_chain(
+                id,
+                Size2D::new(16, 16),
+                &env.device,
+                &mut env.context,
+                SurfaceAccess::GPUOnly,
+            )
+            .unwrap();
+    }
+    let first = swap_chains.get(0).unwrap();
+    let second = swap_chains.get(1).unwrap();
+
+    // Nothing has been swapped yet.
+    assert!(first
+        .wait_for_surface(Some(Duration::from_millis(10)))
+        .is_none());
+    assert!(swap_chains
+        .wait_for_surface(Some(Duration::from_millis(10)))
+        .is_none());
+
+    // A consumer on another thread is woken up by the swap.
+    let consumer = {
+        let second = second.clone();
+        thread::spawn(move || second.wait_for_surface(None))
+    };
+    thread::sleep(Duration::from_millis(10));
+    second
+        .swap_buffers(&env.device, &mut env.context, PreserveBuffer::No)
+        .unwrap();
+    let (surface, metadata) = consumer.join().unwrap().unwrap();
+    assert_eq!(metadata.frame_number, 1);
+    second.recycle_surface(surface);
+
+    // Waiting on the collection returns the swap chain that swapped first.
+    second
+        .swap_buffers(&env.device, &mut env.context, PreserveBuffer::No)
+        .unwrap();
+    first
+        .swap_buffers(&env.device, &mut env.context, PreserveBuffer::No)
+        .unwrap();
+    let (id, surface, metadata) = swap_chains.wait_for_surface(None).unwrap();
+    assert_eq!(id, 1);
+    assert_eq!(metadata.frame_number, 2);
+    second.recycle_surface(surface);
+    let (id, surface, metadata) = swap_chains.wait_for_surface(None).unwrap();
+    assert_eq!(id, 0);
+    assert_eq!(metadata.frame_number, 1);
+    first.recycle_surface(surface);
+
+    // Clean up.
+    for id in 0..2 {
+        swap_chains
+            .destroy(id, &env.device, &mut env.context)
+            .unwrap();
+    }
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_swap_chain_next_surface() {
+    use crate::chains::{PreserveBuffer, SwapChain, SwapChainAPI};
+    use futures_core::Stream;
+    use std::future;
+    use std::pin::Pin;
+
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let swap_chain = SwapChain::create_detached(
+        &env.device,
+        &mut env.context,
+        SurfaceAccess::GPUOnly,
+        Size2D::new(16, 16),
+    )
+    .unwrap();
+
+    // A task on another thread is woken up by the swap, and receives its metadata.
+    let consumer = {
+        let swap_chain = swap_chain.clone();
+        thread::spawn(move || block_on(swap_chain.next_surface()))
+    };
+    thread::sleep(Duration::from_millis(10));
+    swap_chain
+        .swap_buffers_with_user_data(&env.device, &mut env.context, PreserveBuffer::No, 7u32)
+        .unwrap();
+    let (surface, metadata) = consumer.join().unwrap().unwrap();
+    assert_eq!(metadata.frame_number, 1);
+    assert_eq!(metadata.valid_size, Size2D::new(16, 16));
+    assert_eq!(metadata.user_data::<u32>(), Some(&7));
+    swap_chain.recycle_surface(surface);
+
+    // The stream yields front buffers until the swap chain is destroyed.
+    let mut surfaces = swap_chain.surfaces();
+    let mut next = || {
+        block_on(future::poll_fn(|task_context| {
+            Pin::new(&mut surfaces).poll_next(task_context)
+        }))
+    };
+    swap_chain
+        .swap_buffers(&env.device, &mut env.context, PreserveBuffer::No)
+        .unwrap();
+    let (surface, metadata) = next().unwrap();
+    assert_eq!(metadata.frame_number, 2);
+    assert!(metadata.user_data.is_none());
+    swap_chain.recycle_surface(surface);
+    swap_chain.destroy(&env.device, &mut env.context).unwrap();
+    assert!(next().is_none());
+
+    // Clean up.
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_swap_chain_memory_budget() {
+    use crate::chains::{PresentMode, PreserveBuffer, SwapChainAPI, SwapChains, SwapChainsAPI};
+
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let swap_chains = SwapChains::new();
+    swap_chains
+        .create_detached_swap_chain(
+            0,
+            Size2D::new(16, 16),
+            &env.device,
+            &mut env.context,
+            SurfaceAccess::GPUOnly,
+        )
+        .unwrap();
+    let swap_chain = swap_chains.get(0).unwrap();
+    swap_chain
+        .set_present_mode(PresentMode::Fifo { queue_depth: 3 })
+        .unwrap();
+
+    // Queue up three frames, then recycle them all.
+    for _ in 0..3 {
+        swap_chain
+            .swap_buffers(&env.device, &mut env.context, PreserveBuffer::No)
+            .unwrap();
+    }
+    let surfaces: Vec<_> = (0..3).map(|_| swap_chain.take_surface().unwrap()).collect();
+    for surface in surfaces {
+        swap_chain.recycle_surface(surface);
+    }
+    let surface_memory = swap_chain.recycled_memory(&env.device) / 3;
+    assert!(surface_memory > 0);
+    assert_eq!(swap_chains.recycled_memory(), surface_memory * 3);
+
+    // Swapping reuses one recycled surface, and evicts another to fit in the budget.
+    swap_chain.set_memory_budget(Some(surface_memory));
+    swap_chain
+        .swap_buffers(&env.device, &mut env.context, PreserveBuffer::No)
+        .unwrap();
+    assert_eq!(swap_chain.recycled_memory(&env.device), surface_memory);
+    assert_eq!(swap_chains.recycled_memory(), surface_memory);
+
+    // The collection's budget applies too.
+    swap_chain.set_memory_budget(None);
+    swap_chains.set_memory_budget(Some(0));
+    swap_chain
+        .swap_buffers(&env.device, &mut env.context, PreserveBuffer::No)
+        .unwrap();
+    assert_eq!(swap_chain.recycled_memory(&env.device), 0);
+    assert_eq!(swap_chains.recycled_memory(), 0);
+
+    // Trimming destroys every recycled surface.
+    swap_chains.set_memory_budget(None);
+    let surface = swap_chain.take_surface().unwrap();
+    swap_chain.recycle_surface(surface);
+    assert_eq!(swap_chain.recycled_memory(&env.device), surface_memory);
+    assert_eq!(swap_chains.recycled_memory(), surface_memory);
+    swap_chains.trim(&env.device, &mut env.context).unwrap();
+    assert_eq!(swap_chain.recycled_memory(&env.device), 0);
+    assert_eq!(swap_chains.recycled_memory(), 0);
+
+    // Clean up.
+    swap_chains
+        .destroy(0, &env.device, &mut env.context)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_swap_chain_resize_policy() {
+    use crate::chains::{PreserveBuffer, ResizePolicy, SwapChainAPI, SwapChains, SwapChainsAPI};
+
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let swap_chains = SwapChains::new();
+    swap_chains
+        .create_detached_swap_chain(
+            0,
+            Size2D::new(16, 16),
+            &env.device,
+            &mut env.context,
+            SurfaceAccess::GPUOnly,
+        )
+        .unwrap();
+    let swap_chain = swap_chains.get(0).unwrap();
+    assert!(swap_chain
+        .set_resize_policy(ResizePolicy::Tolerant {
+            bucket_size: 0,
+            max_slack: 0,
+        })
+        .is_err());
+    swap_chain
+        .set_resize_policy(ResizePolicy::Tolerant {
+            bucket_size: 64,
+            max_slack: 0,
+        })
+        .unwrap();
+
+    // Growing past the back buffer allocates a surface rounded up to the bucket size,
+    // which later resizes within the bucket keep using.
+    swap_chain
+        .resize(&env.device, &mut env.context, Size2D::new(20, 20))
+        .unwrap();
+    swap_chain
+        .resize(&env.device, &mut env.context, Size2D::new(30, 40))
+        .unwrap();
+    swap_chain
+        .swap_buffers(&env.device, &mut env.context, PreserveBuffer::No)
+        .unwrap();
+    let (surface, metadata) = swap_chain.take_surface_with_metadata().unwrap();
+    let surface_info = swap_chain.surface_info(&env.device, &surface);
+    assert_eq!(surface_info.size, Size2D::new(64, 64));
+    assert_eq!(surface_info.valid_size, Size2D::new(30, 40));
+    assert_eq!(metadata.unwrap().valid_size, surface_info.valid_size);
+    assert_eq!(
+        env.device.surface_info(&surface).valid_size,
+        surface_info.size
+    );
+    swap_chain.recycle_surface(surface);
+
+    // The recycled surface is reused after shrinking, as long as it is within the bucket.
+    swap_chain
+        .resize(&env.device, &mut env.context, Size2D::new(8, 8))
+        .unwrap();
+    swap_chain
+        .swap_buffers(&env.device, &mut env.context, PreserveBuffer::No)
+        .unwrap();
+    let surface = swap_chain.take_surface().unwrap();
+    let surface_info = swap_chain.surface_info(&env.device, &surface);
+    assert_eq!(surface_info.size, Size2D::new(64, 64));
+    assert_eq!(surface_info.valid_size, Size2D::new(8, 8));
+    swap_chain.recycle_surface(surface);
+
+    // A recycled surface taken without metadata still reports its valid size.
+    let surface = swap_chain.take_surface().unwrap();
+    let surface_info = swap_chain.surface_info(&env.device, &surface);
+    assert_eq!(surface_info.valid_size, Size2D::new(8, 8));
+    swap_chain.recycle_surface(surface);
+
+    // Clean up.
+    swap_chains
+        .destroy(0, &env.device, &mut env.context)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg(feature = "chains")]
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_swap_chain_frame_metadata() {
+    use crate::chains::{PresentMode, PreserveBuffer, SwapChainAPI, SwapChains, SwapChainsAPI};
+
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let swap_chains = SwapChains::new();
+    swap_chains
+        .create_detached_swap_chain(
+            0,
+            Size2D::new(16, 16),
+            &env.device,
+            &mut env.context,
+            SurfaceAccess::GPUOnly,
+        )
+        .unwrap();
+    let swap_chain = swap_chains.get(0).unwrap();
+    swap_chain
+        .set_present_mode(PresentMode::Fifo { queue_depth: 2 })
+        .unwrap();
+
+    // Each front buffer carries the data it was swapped with.
+    swap_chain
+        .swap_buffers(&env.device, &mut env.context, PreserveBuffer::No)
+        .unwrap();
+    swap_chain
+        .swap_buffers_with_user_data(&env.device, &mut env.context, PreserveBuffer::No, 7u32)
+        .unwrap();
+
+    let (surface, metadata) = swap_chain.take_surface_with_metadata().unwrap();
+    let first = metadata.unwrap();
+    assert_eq!(first.frame_number, 1);
+    assert!(first.user_data.is_none());
+    swap_chain.recycle_surface(surface);
+
+    let (surface, metadata) = swap_chain.take_surface_with_metadata().unwrap();
+    let second = metadata.unwrap();
+    assert_eq!(second.frame_number, 2);
+    assert!(second.timestamp >= first.timestamp);
+    assert_eq!(second.user_data::<u32>(), Some(&7));
+    assert_eq!(second.user_data::<u64>(), None);
+    swap_chain.recycle_surface(surface);
+
+    // Recycled surfaces have no metadata.
+    let (surface, metadata) = swap_chain.take_surface_with_metadata().unwrap();
+    assert!(metadata.is_none());
+    swap_chain.recycle_surface(surface);
+
+    // Clean up.
+    swap_chains
+        .destroy(0, &env.device, &mut env.context)
+        .unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_multisampled_surface() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Multisampling isn't available everywhere.
+    let max_samples = env.device.max_surface_samples(&env.context).unwrap();
+    env.device.destroy_context(&mut env.context).unwrap();
+    if max_samples < 2 {
+        return;
+    }
+
+    let context_descriptor = env
+        .device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::DEPTH | ContextAttributeFlags::STENCIL,
+            samples: max_samples.min(4),
+            surface_format: SurfaceFormat::RGBA8,
+            depth_format: DepthFormat::Depth24,
+        })
+        .unwrap();
+    let mut context = env
+        .device
+        .create_context(&context_descriptor, None)
+        .unwrap();
+    let surface = make_surface(&mut env.device, &context);
+    env.device
+        .bind_surface_to_context(&mut context, surface)
+        .unwrap();
+    env.device.make_context_current(&context).unwrap();
+
+    let gl =
+        unsafe { Gl::from_loader_function(|symbol| env.device.get_proc_address(&context, symbol)) };
+
+    unsafe {
+        // Render to the multisampled framebuffer.
+        bind_context_fbo(&gl, &env.device, &context);
+        gl.viewport(0, 0, 640, 480);
+        assert!(gl.get_parameter_i32(gl::SAMPLES) > 1);
+        clear(&gl, &[255, 0, 0, 255]);
+        clear_bottom_row(&gl, &[0, 0, 255, 255]);
+        check_gl(&gl);
+
+        // Unbinding the surface should resolve it into its texture.
+        let surface = env
+            .device
+            .unbind_surface_from_context(&mut context)
+            .unwrap()
+            .unwrap();
+        let surface_texture = env
+            .device
+            .create_surface_texture(&mut context, surface)
+            .unwrap();
+
+        let framebuffer_object = make_fbo(
+            &gl,
+            env.device.surface_gl_texture_target(),
+            env.device.surface_texture_object(&surface_texture),
+        );
+        assert_eq!(get_pixel_from_bottom_row(&gl), [0, 0, 255, 255]);
+        assert_eq!(get_pixel_from_top_row(&gl), [255, 0, 0, 255]);
+
+        // CPU writes would be lost on the next resolve, so multisampled surfaces can't be locked.
+        let mut cpu_surface = env
+            .device
+            .create_surface(
+                &context,
+                SurfaceAccess::GPUCPU,
+                SurfaceType::Generic {
+                    size: Size2D::new(640, 480),
+                },
+            )
+            .unwrap();
+        assert!(matches!(
+            env.device.lock_surface_data(&mut cpu_surface),
+            Err(Error::MultisampledSurface)
+        ));
+
+        // Clean up.
+        gl.bind_framebuffer(gl::FRAMEBUFFER, None);
+        gl.delete_framebuffer(framebuffer_object);
+        let mut surface = env
+            .device
+            .destroy_surface_texture(&mut context, surface_texture)
+            .unwrap();
+        env.device
+            .destroy_surface(&mut context, &mut surface)
+            .unwrap();
+        env.device
+            .destroy_surface(&mut context, &mut cpu_surface)
+            .unwrap();
+    }
+
+    env.device.destroy_context(&mut context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_surface_formats() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    env.device.destroy_context(&mut env.context).unwrap();
+
+    for &format in &[
+        SurfaceFormat::RGBA8,
+        SurfaceFormat::BGRA8,
+        SurfaceFormat::SRGBA8,
+        SurfaceFormat::RGB10A2,
+        SurfaceFormat::RGBA16F,
+    ] {
+        let context_descriptor = match env.device.create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 0),
+            flags: ContextAttributeFlags::empty(),
+            samples: 0,
+            surface_format: format,
+            depth_format: DepthFormat::Depth24,
+        }) {
+            Ok(context_descriptor) => context_descriptor,
+            Err(Error::UnsupportedSurfaceFormat) => continue,
+            Err(err) => panic!("Failed to create context descriptor: {:?}", err),
+        };
+        let mut context = env
+            .device
+            .create_context(&context_descriptor, None)
+            .unwrap();
+
+        let surface_type = SurfaceType::Generic {
+            size: Size2D::new(640, 480),
+        };
+        let surface =
+            match env
+                .device
+                .create_surface(&context, SurfaceAccess::GPUOnly, surface_type)
+            {
+                Ok(surface) => surface,
+                Err(Error::UnsupportedSurfaceFormat) => {
+                    env.device.destroy_context(&mut context).unwrap();
+                    continue;
+                }
+                Err(err) => panic!("Failed to create {:?} surface: {:?}", format, err),
+            };
+        let surface_info = env.device.surface_info(&surface);
+        assert!(format == SurfaceFormat::RGBA8 || surface_info.format == Some(format));
+
+        // Full-intensity colors should survive any format.
+        env.device
+            .bind_surface_to_context(&mut context, surface)
+            .unwrap();
+        env.device.make_context_current(&context).unwrap();
+        let gl = unsafe {
+            Gl::from_loader_function(|symbol| env.device.get_proc_address(&context, symbol))
+        };
+        bind_context_fbo(&gl, &env.device, &context);
+        unsafe {
+            gl.viewport(0, 0, 640, 480);
+        }
+        clear(&gl, &[255, 0, 0, 255]);
+        let mut surface = env
+            .device
+            .unbind_surface_from_context(&mut context)
+            .unwrap()
+            .unwrap();
+
+        match env.device.read_surface_pixels(
+            &mut context,
+            &surface,
+            Rect::new(Point2D::zero(), Size2D::new(1, 1)),
+            PixelFormat::RGBA8,
+        ) {
+            Ok(pixels) => assert_eq!(pixels, [255, 0, 0, 255], "{:?}", format),
+            Err(Error::Unimplemented) | Err(Error::UnsupportedSurfaceFormat) => {}
+            Err(err) => panic!("Failed to read {:?} surface: {:?}", format, err),
+        }
+
+        env.device
+            .destroy_surface(&mut context, &mut surface)
+            .unwrap();
+        env.device.destroy_context(&mut context).unwrap();
+    }
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_depth_formats() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    env.device.destroy_context(&mut env.context).unwrap();
+
+    for &flags in &[
+        ContextAttributeFlags::DEPTH,
+        ContextAttributeFlags::DEPTH | ContextAttributeFlags::STENCIL,
+    ] {
+        for &depth_format in &[
+            DepthFormat::Depth16,
+            DepthFormat::Depth24,
+            DepthFormat::Depth32F,
+        ] {
+            let context_descriptor = env
+                .device
+                .create_context_descriptor(&ContextAttributes {
+                    version: GLVersion::new(3, 0),
+                    flags,
+                    samples: 0,
+                    surface_format: SurfaceFormat::RGBA8,
+                    depth_format,
+                })
+                .unwrap();
+            let mut context = env
+                .device
+                .create_context(&context_descriptor, None)
+                .unwrap();
+            let surface = make_surface(&mut env.device, &context);
+
+            // Backends may substitute a different format, but must report the one they used.
+            let actual_depth_format = match env.device.surface_info(&surface).depth_format {
+                Some(actual_depth_format) => actual_depth_format,
+                None => {
+                    // This backend doesn't report the format of its depth buffer.
+                    let mut surface = surface;
+                    env.device
+                        .destroy_surface(&mut context, &mut surface)
+                        .unwrap();
+                    env.device.destroy_context(&mut context).unwrap();
+                    continue;
+                }
+            };
+            // There's no packed depth/stencil format with a 16-bit depth buffer.
+            if depth_format == DepthFormat::Depth16
+                && flags.contains(ContextAttributeFlags::STENCIL)
+            {
+                assert_eq!(actual_depth_format, DepthFormat::Depth24);
+            }
+            env.device
+                .bind_surface_to_context(&mut context, surface)
+                .unwrap();
+            env.device.make_context_current(&context).unwrap();
+            let gl = unsafe {
+                Gl::from_loader_function(|symbol| env.device.get_proc_address(&context, symbol))
+            };
+            bind_context_fbo(&gl, &env.device, &context);
+
+            unsafe {
+                let component_type = gl.get_framebuffer_attachment_parameter_i32(
+                    gl::FRAMEBUFFER,
+                    gl::DEPTH_ATTACHMENT,
+                    gl::FRAMEBUFFER_ATTACHMENT_COMPONENT_TYPE,
+                );
+                let depth_size = gl.get_framebuffer_attachment_parameter_i32(
+                    gl::FRAMEBUFFER,
+                    gl::DEPTH_ATTACHMENT,
+                    gl::FRAMEBUFFER_ATTACHMENT_DEPTH_SIZE,
+                );
+                check_gl(&gl);
+                match actual_depth_format {
+                    DepthFormat::Depth16 => assert!(depth_size >= 16),
+                    DepthFormat::Depth24 => assert!(depth_size >= 24),
+                    DepthFormat::Depth32F => {
+                        assert_eq!(component_type as u32, gl::FLOAT);
+                        assert_eq!(depth_size, 32);
+                    }
+                }
+            }
+
+            let mut surface = env
+                .device
+                .unbind_surface_from_context(&mut context)
+                .unwrap()
+                .unwrap();
+            env.device
+                .destroy_surface(&mut context, &mut surface)
+                .unwrap();
+            env.device.destroy_context(&mut context).unwrap();
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_robust_context() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    // Contexts that weren't created as robust may or may not be able to report resets, but they
+    // shouldn't report one now.
+    match env.device.context_reset_status(&env.context) {
+        Ok(status) => assert_eq!(status, ContextResetStatus::NoReset),
+        Err(Error::RequiredExtensionUnavailable) => {}
+        Err(err) => panic!("Failed to query the context reset status: {:?}", err),
+    }
+    env.device.destroy_context(&mut env.context).unwrap();
+
+    // Robust contexts aren't available everywhere.
+    let context_descriptor = match env.device.create_context_descriptor(&ContextAttributes {
+        version: GLVersion::new(3, 0),
+        flags: ContextAttributeFlags::ROBUST_ACCESS,
+        samples: 0,
+        surface_format: SurfaceFormat::RGBA8,
+        depth_format: DepthFormat::Depth24,
+    }) {
+        Ok(context_descriptor) => context_descriptor,
+        Err(Error::RequiredExtensionUnavailable) | Err(Error::UnsupportedOnThisPlatform) => return,
+        Err(err) => panic!("Failed to create a robust context descriptor: {:?}", err),
+    };
+    assert!(env
+        .device
+        .context_descriptor_attributes(&context_descriptor)
+        .flags
+        .contains(ContextAttributeFlags::ROBUST_ACCESS));
+
+    let mut context = env
+        .device
+        .create_context(&context_descriptor, None)
+        .unwrap();
+    let context_descriptor = env.device.context_descriptor(&context);
+    assert!(env
+        .device
+        .context_descriptor_attributes(&context_descriptor)
+        .flags
+        .contains(ContextAttributeFlags::ROBUST_ACCESS));
+    assert_eq!(
+        env.device.context_reset_status(&context).unwrap(),
+        ContextResetStatus::NoReset
+    );
+    env.device.destroy_context(&mut context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_debug_context() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+    env.device.destroy_context(&mut env.context).unwrap();
+
+    // Debug contexts aren't available everywhere.
+    let context_descriptor = match env.device.create_context_descriptor(&ContextAttributes {
+        version: GLVersion::new(3, 0),
+        flags: ContextAttributeFlags::DEBUG,
+        samples: 0,
+        surface_format: SurfaceFormat::RGBA8,
+        depth_format: DepthFormat::Depth24,
+    }) {
+        Ok(context_descriptor) => context_descriptor,
+        Err(Error::RequiredExtensionUnavailable) | Err(Error::UnsupportedOnThisPlatform) => return,
+        Err(err) => panic!("Failed to create a debug context descriptor: {:?}", err),
+    };
+    assert!(env
+        .device
+        .context_descriptor_attributes(&context_descriptor)
+        .flags
+        .contains(ContextAttributeFlags::DEBUG));
+
+    let mut context = env
+        .device
+        .create_context(&context_descriptor, None)
+        .unwrap();
+    let context_descriptor = env.device.context_descriptor(&context);
+    assert!(env
+        .device
+        .context_descriptor_attributes(&context_descriptor)
+        .flags
+        .contains(ContextAttributeFlags::DEBUG));
+
+    // Messages reported to the context should reach its callback without disturbing the GL error
+    // state.
+    env.device.make_context_current(&context).unwrap();
+    let gl =
+        unsafe { Gl::from_loader_function(|symbol| env.device.get_proc_address(&context, symbol)) };
+    unsafe {
+        gl.debug_message_insert(
+            gl::DEBUG_SOURCE_APPLICATION,
+            gl::DEBUG_TYPE_MARKER,
+            0,
+            gl::DEBUG_SEVERITY_NOTIFICATION,
+            "surfman debug context test",
+        );
+        check_gl(&gl);
+    }
+
+    env.device.destroy_context(&mut context).unwrap();
+}
+
+#[cfg_attr(not(feature = "sm-test"), test)]
+pub fn test_get_native_context() {
+    let mut env = match BasicEnvironment::new() {
+        None => return,
+        Some(env) => env,
+    };
+
+    let native_context = NativeContext::current().unwrap();
+
+    unsafe {
+        clear(&env.gl, &[0, 255, 0, 255]);
+
+        let mut other_context = env
+            .device
+            .create_context_from_native_context(native_context)
+            .unwrap();
+
+        env.device.make_context_current(&other_context).unwrap();
+        assert_eq!(get_pixel_from_bottom_row(&env.gl), [0, 255, 0, 255]);
+
+        env.device.destroy_context(&mut other_context).unwrap();
+    }
+
+    env.device.destroy_context(&mut env.context).unwrap();
+}
+
+fn bind_context_fbo(gl: &Gl, device: &Device, context: &Context) {
+    unsafe {
+        gl.bind_framebuffer(gl::FRAMEBUFFER, context_fbo(device, context));
+        check_gl(gl);
+    }
+}
+
+fn context_fbo(device: &Device, context: &Context) -> Option<Framebuffer> {
+    device
+        .context_surface_info(context)
+        .unwrap()
+        .unwrap()
+        .framebuffer_object
+}
+
+fn make_surface(device: &mut Device, context: &Context) -> Surface {
+    device
+        .create_surface(
+            context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Generic {
+                size: Size2D::new(640, 480),
+            },
+        )
+        .unwrap()
+}
+
+fn blit_fbo(gl: &Gl, dest_fbo: Option<Framebuffer>, src_fbo: Option<Framebuffer>) {
+    unsafe {
+        gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, dest_fbo);
+        check_gl(gl);
+        gl.bind_framebuffer(gl::READ_FRAMEBUFFER, src_fbo);
+        check_gl(gl);
+        gl.blit_framebuffer(
+            0,
+            0,
+            640,
+            480,
+            0,
+            0,
+            640,
+            480,
+            gl::COLOR_BUFFER_BIT,
+            gl::NEAREST,
+        );
+        check_gl(gl);
+    }
+}
+
+fn make_fbo(gl: &Gl, texture_target: u32, texture: Option<Texture>) -> Framebuffer {
+    unsafe {
+        let framebuffer_object = gl.create_framebuffer().unwrap();
+        check_gl(gl);
+        gl.bind_framebuffer(gl::FRAMEBUFFER, Some(framebuffer_object));
+        check_gl(gl);
+        gl.framebuffer_texture_2d(
+            gl::FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            texture_target,
+            texture,
+            0,
+        );
+        check_gl(gl);
+        assert_eq!(
+            gl.check_framebuffer_status(gl::FRAMEBUFFER),
+            gl::FRAMEBUFFER_COMPLETE
+        );
+        framebuffer_object
+    }
+}
+
+// Runs a future to completion on the current thread.
+#[cfg(feature = "chains")]
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    use std::pin::pin;
+    use std::sync::Arc;
+    use std::task::{Context as TaskContext, Poll, Wake};
+
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut task_context = TaskContext::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut task_context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+// Returns `None` if there's nothing for this backend to connect to on this machine, such as a
+// display server or a DRM render node.
+fn create_connection() -> Option<Connection> {
+    match Connection::new() {
+        Ok(connection) => Some(connection),
+        Err(Error::ConnectionFailed) => {
+            // Can't run these tests on this machine.
+            None
+        }
+        Err(err) => panic!("Failed to create connection: {:?}", err),
+    }
+}
+
+struct BasicEnvironment {
+    connection: Connection,
+    adapter: Adapter,
+    device: Device,
+    context_descriptor: ContextDescriptor,
+    context: Context,
+    gl: Gl,
+}
+
+impl BasicEnvironment {
+    fn new() -> Option<BasicEnvironment> {
+        let connection = create_connection()?;
+        let adapter = connection
+            .create_low_power_adapter()
+            .expect("Failed to create adapter!");
+        let mut device = match connection.create_device(&adapter) {
+            Ok(device) => device,
+            Err(Error::RequiredExtensionUnavailable) => {
+                // Can't run these tests on this hardware.
+                return None;
+            }
+            Err(err) => panic!("Failed to create device: {:?}", err),
+        };
+
+        let context_descriptor = device
+            .create_context_descriptor(&ContextAttributes {
+                version: GLVersion::new(3, 0),
+                flags: ContextAttributeFlags::empty(),
+                samples: 0,
+                surface_format: SurfaceFormat::RGBA8,
+                depth_format: DepthFormat::Depth24,
+            })
+            .unwrap();
 
         let mut context = device.create_context(&context_descriptor, None).unwrap();
         let surface = make_surface(&mut device, &context);
@@ -1091,6 +2730,22 @@ fn get_pixel_from_second_from_bottom_row(gl: &Gl) -> [u8; 4] {
     }
 }
 
+fn get_pixel_from_top_row(gl: &Gl) -> [u8; 4] {
+    unsafe {
+        let mut pixel: [u8; 4] = [0; 4];
+        gl.read_pixels(
+            0,
+            479,
+            1,
+            1,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            PixelPackData::Slice(Some(&mut pixel)),
+        );
+        pixel
+    }
+}
+
 fn check_gl(gl: &Gl) {
     unsafe {
         assert_eq!(gl.get_error(), gl::NO_ERROR);
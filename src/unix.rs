@@ -1,16 +1,19 @@
 // surfman/src/platform/unix/default.rs
 //
-//! The default backend for Unix, which dynamically switches between Wayland, X11 and surfaceless.
+//! The default backend for Unix, which dynamically switches between Wayland, X11, surfaceless Mesa
+//! and EGL devices.
 
 /// Wayland or X11 display server connections.
 pub mod connection {
-    use crate::mesa_surfaceless::device::Device as SWDevice;
+    use crate::egl_device::device::Device as EGLDeviceDevice;
+    use crate::mesa_surfaceless::device::Device as SurfacelessDevice;
     use crate::multi::connection::Connection as MultiConnection;
     use crate::multi::connection::NativeConnection as MultiNativeConnection;
     use crate::multi::device::Device as MultiDevice;
     use crate::wayland::device::Device as WaylandDevice;
     use crate::x11::device::Device as X11Device;
     type HWDevice = MultiDevice<WaylandDevice, X11Device>;
+    type SWDevice = MultiDevice<SurfacelessDevice, EGLDeviceDevice>;
 
     /// Either a Wayland or an X11 display server connection.
     pub type Connection = MultiConnection<HWDevice, SWDevice>;
@@ -21,14 +24,17 @@ pub mod connection {
 
 /// OpenGL rendering contexts.
 pub mod context {
-    use crate::mesa_surfaceless::device::Device as SWDevice;
+    use crate::egl_device::device::Device as EGLDeviceDevice;
+    use crate::mesa_surfaceless::device::Device as SurfacelessDevice;
     use crate::multi::context::Context as MultiContext;
     use crate::multi::context::ContextDescriptor as MultiContextDescriptor;
+    use crate::multi::context::Fence as MultiFence;
     use crate::multi::context::NativeContext as MultiNativeContext;
     use crate::multi::device::Device as MultiDevice;
     use crate::wayland::device::Device as WaylandDevice;
     use crate::x11::device::Device as X11Device;
     type HWDevice = MultiDevice<WaylandDevice, X11Device>;
+    type SWDevice = MultiDevice<SurfacelessDevice, EGLDeviceDevice>;
 
     /// Represents an OpenGL rendering context.
     ///
@@ -56,11 +62,18 @@ pub mod context {
 
     /// Either a Wayland or an X11 native context
     pub type NativeContext = MultiNativeContext<HWDevice, SWDevice>;
+
+    /// Represents a GPU fence, which is signaled once the GPU finishes all commands issued before
+    /// it.
+    ///
+    /// A fence must be explicitly destroyed with `destroy_fence()`, or a panic will occur.
+    pub type Fence = MultiFence<HWDevice, SWDevice>;
 }
 
 /// Thread-local handles to devices.
 pub mod device {
-    use crate::mesa_surfaceless::device::Device as SWDevice;
+    use crate::egl_device::device::Device as EGLDeviceDevice;
+    use crate::mesa_surfaceless::device::Device as SurfacelessDevice;
     use crate::multi::device::Adapter as MultiAdapter;
     use crate::multi::device::NativeDevice as MultiNativeDevice;
     use crate::wayland::device::Device as WaylandDevice;
@@ -68,6 +81,7 @@ pub mod device {
 
     use crate::multi::device::Device as MultiDevice;
     type HWDevice = MultiDevice<WaylandDevice, X11Device>;
+    type SWDevice = MultiDevice<SurfacelessDevice, EGLDeviceDevice>;
 
     /// Represents a hardware display adapter that can be used for rendering (including the CPU).
     ///
@@ -86,14 +100,17 @@ pub mod device {
 
 /// Hardware buffers of pixels.
 pub mod surface {
-    use crate::mesa_surfaceless::device::Device as SWDevice;
+    use crate::egl_device::device::Device as EGLDeviceDevice;
+    use crate::mesa_surfaceless::device::Device as SurfacelessDevice;
     use crate::multi::device::Device as MultiDevice;
     use crate::multi::surface::NativeWidget as MultiNativeWidget;
     use crate::multi::surface::Surface as MultiSurface;
+    use crate::multi::surface::SurfaceDataGuard as MultiSurfaceDataGuard;
     use crate::multi::surface::SurfaceTexture as MultiSurfaceTexture;
     use crate::wayland::device::Device as WaylandDevice;
     use crate::x11::device::Device as X11Device;
     type HWDevice = MultiDevice<WaylandDevice, X11Device>;
+    type SWDevice = MultiDevice<SurfacelessDevice, EGLDeviceDevice>;
 
     /// A wrapper for a Wayland surface or an X11 `Window`, as appropriate.
     pub type NativeWidget = MultiNativeWidget<HWDevice, SWDevice>;
@@ -127,7 +144,8 @@ pub mod surface {
     /// the `destroy_surface_texture()` method, or a panic will occur.
     pub type SurfaceTexture = MultiSurfaceTexture<HWDevice, SWDevice>;
 
-    // FIXME(pcwalton): Revamp how this works.
-    #[doc(hidden)]
-    pub struct SurfaceDataGuard {}
+    /// Represents the CPU view of the pixel data of a surface.
+    ///
+    /// The surface stays locked until this guard is dropped.
+    pub type SurfaceDataGuard<'a> = MultiSurfaceDataGuard<'a, HWDevice, SWDevice>;
 }
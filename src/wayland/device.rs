@@ -1,22 +1,29 @@
 //! A wrapper around Wayland `EGLDisplay`s.
 
 use super::connection::{Connection, NativeConnectionWrapper};
-use super::context::{Context, ContextDescriptor, NativeContext};
+use super::context::{Context, ContextDescriptor, Fence, NativeContext};
 use super::surface::{NativeWidget, Surface, SurfaceDataGuard, SurfaceTexture};
 use crate::base::egl::context::{self, CurrentContextGuard, EGLBackedContext};
+use crate::base::egl::dmabuf;
+use crate::base::egl::fence::EGLFence;
 use crate::base::egl::surface::EGLBackedSurface;
 use crate::context::ContextID;
 use crate::egl;
-use crate::egl::types::EGLint;
+use crate::egl::types::{EGLDisplay, EGLint};
 use crate::gl;
+use crate::gl_utils;
 pub use crate::mesa_surfaceless::device::Adapter;
+use crate::readback::ReadbackHandle;
 use crate::surface::Framebuffer;
-use crate::{ContextAttributes, Gl, SurfaceInfo};
-use crate::{Error, GLApi, SurfaceAccess, SurfaceType};
-use euclid::default::Size2D;
+use crate::{ContextAttributes, ContextResetStatus, Gl, SurfaceInfo};
+use crate::{DmaBufDescriptor, DmaBufFormat};
+use crate::{Error, GLApi, PixelFormat, SurfaceAccess, SurfaceType};
+use euclid::default::{Rect, Size2D};
 use glow::Texture;
 use std::os::raw::c_void;
+use std::os::unix::io::OwnedFd;
 use std::sync::Arc;
+use std::time::Duration;
 use wayland_sys::client::wl_proxy;
 use wayland_sys::egl::{wayland_egl_handle, wl_egl_window};
 
@@ -28,6 +35,9 @@ const SURFACE_GL_TEXTURE_TARGET: u32 = gl::TEXTURE_2D;
 /// Devices contain most of the relevant surface management methods.
 pub struct Device {
     pub(crate) native_connection: Arc<NativeConnectionWrapper>,
+    // The connection's display, or a display on the adapter's EGL device if the connection's
+    // display doesn't render on it.
+    pub(crate) egl_display: EGLDisplay,
     pub(crate) adapter: Adapter,
 }
 
@@ -43,8 +53,11 @@ pub struct NativeDevice {
 impl Device {
     #[inline]
     pub(crate) fn new(connection: &Connection, adapter: &Adapter) -> Result<Device, Error> {
+        let egl_display =
+            unsafe { adapter.open_display(connection.native_connection.egl_display)? };
         Ok(Device {
             native_connection: connection.native_connection.clone(),
+            egl_display,
             adapter: (*adapter).clone(),
         })
     }
@@ -97,7 +110,8 @@ impl Device {
 
         unsafe {
             ContextDescriptor::new(
-                self.native_connection.egl_display,
+                self.egl_display,
+                self.gl_api(),
                 attributes,
                 &[
                     egl::SURFACE_TYPE as EGLint,
@@ -121,16 +135,17 @@ impl Device {
     ) -> Result<Context, Error> {
         unsafe {
             let context = EGLBackedContext::new(
-                self.native_connection.egl_display,
+                self.egl_display,
                 descriptor,
                 share_with.map(|ctx| &ctx.0),
                 self.gl_api(),
             )?;
-            context.make_current(self.native_connection.egl_display)?;
-            Ok(Context(
-                context,
-                Gl::from_loader_function(context::get_proc_address),
-            ))
+            context.make_current(self.egl_display)?;
+            let mut gl = Gl::from_loader_function(context::get_proc_address);
+            if descriptor.debug {
+                gl_utils::install_debug_message_callback(&mut gl, context.id);
+            }
+            Ok(Context(context, gl))
         }
     }
 
@@ -159,7 +174,7 @@ impl Device {
         }
 
         unsafe {
-            context.0.destroy(self.native_connection.egl_display);
+            context.0.destroy(self.egl_display);
             Ok(())
         }
     }
@@ -176,8 +191,9 @@ impl Device {
         unsafe {
             ContextDescriptor::from_egl_context(
                 &context.1,
-                self.native_connection.egl_display,
+                self.egl_display,
                 context.0.egl_context,
+                context.0.surface_attributes,
             )
         }
     }
@@ -187,7 +203,7 @@ impl Device {
     /// After calling this function, it is valid to use OpenGL rendering commands.
     #[inline]
     pub fn make_context_current(&self, context: &Context) -> Result<(), Error> {
-        unsafe { context.0.make_current(self.native_connection.egl_display) }
+        unsafe { context.0.make_current(self.egl_display) }
     }
 
     /// Removes the current OpenGL context from this thread.
@@ -196,7 +212,7 @@ impl Device {
     /// made current.
     #[inline]
     pub fn make_no_context_current(&self) -> Result<(), Error> {
-        unsafe { context::make_no_context_current(self.native_connection.egl_display) }
+        unsafe { context::make_no_context_current(self.egl_display) }
     }
 
     #[inline]
@@ -215,7 +231,75 @@ impl Device {
         &self,
         context_descriptor: &ContextDescriptor,
     ) -> ContextAttributes {
-        unsafe { context_descriptor.attributes(self.native_connection.egl_display) }
+        unsafe { context_descriptor.attributes(self.egl_display) }
+    }
+
+    /// Returns the largest number of samples per pixel that generic surfaces created with this
+    /// context can use.
+    pub fn max_surface_samples(&self, context: &Context) -> Result<u32, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        Ok(gl_utils::max_samples(&context.1))
+    }
+
+    /// Returns whether the context has been lost because of a GPU reset, and if so, whether this
+    /// context caused it.
+    pub fn context_reset_status(&self, context: &Context) -> Result<ContextResetStatus, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        gl_utils::context_reset_status(&context.1, context::get_proc_address)
+    }
+
+    /// Inserts a fence into the command stream of the given context and flushes it.
+    ///
+    /// The fence is signaled once the GPU finishes all commands issued before it. Returns
+    /// `RequiredExtensionUnavailable` if the display doesn't support `EGL_KHR_fence_sync`.
+    pub fn create_fence(&self, context: &Context) -> Result<Fence, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        unsafe { EGLFence::new(&context.1, self.egl_display).map(Fence) }
+    }
+
+    /// Blocks the calling thread until the fence is signaled, or until the timeout expires if
+    /// one is given.
+    ///
+    /// Returns true if the fence was signaled and false if the timeout expired first.
+    pub fn client_wait_fence(
+        &self,
+        fence: &Fence,
+        timeout: Option<Duration>,
+    ) -> Result<bool, Error> {
+        unsafe { fence.0.client_wait(self.egl_display, timeout) }
+    }
+
+    /// Makes the GPU wait until the fence is signaled before executing any further commands
+    /// issued by the given context.
+    ///
+    /// Returns `RequiredExtensionUnavailable` if the display doesn't support `EGL_KHR_wait_sync`.
+    pub fn server_wait_fence(&self, context: &Context, fence: &Fence) -> Result<(), Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        unsafe { fence.0.server_wait(self.egl_display) }
+    }
+
+    /// Destroys a fence.
+    pub fn destroy_fence(&self, fence: &mut Fence) -> Result<(), Error> {
+        unsafe {
+            fence.0.destroy(self.egl_display);
+        }
+        Ok(())
+    }
+
+    /// Returns a new file descriptor for the native sync file behind the fence.
+    ///
+    /// Returns `RequiredExtensionUnavailable` unless the display supports
+    /// `EGL_ANDROID_native_fence_sync`.
+    pub fn export_native_fence_fd(&self, fence: &Fence) -> Result<OwnedFd, Error> {
+        unsafe { fence.0.export_native_fd(self.egl_display) }
+    }
+
+    /// Wraps a native sync file in a fence that can be waited on in the given context.
+    ///
+    /// This takes ownership of the file descriptor.
+    pub fn import_native_fence_fd(&self, context: &Context, fd: OwnedFd) -> Result<Fence, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        unsafe { EGLFence::from_native_fd(self.egl_display, fd).map(Fence) }
     }
 
     /// Fetches the address of an OpenGL function associated with this context.
@@ -249,7 +333,7 @@ impl Device {
         unsafe {
             context
                 .0
-                .bind_surface(self.native_connection.egl_display, surface.0)
+                .bind_surface(self.egl_display, surface.0)
                 .map_err(|(err, surface)| (err, Surface(surface)))
         }
     }
@@ -265,7 +349,7 @@ impl Device {
         unsafe {
             context
                 .0
-                .unbind_surface(&context.1, self.native_connection.egl_display)
+                .unbind_surface(&context.1, self.egl_display)
                 .map(|maybe_surface| maybe_surface.map(Surface))
         }
     }
@@ -276,9 +360,7 @@ impl Device {
     /// Widget surfaces are internally double-buffered, so changes to them don't
     /// show up in their associated widgets until this method is called.
     pub fn present_bound_surface(&self, context: &mut Context) -> Result<(), Error> {
-        context
-            .0
-            .present_bound_surface(self.native_connection.egl_display)
+        context.0.present_bound_surface(self.egl_display)
     }
 
     /// If the currently bound surface is a widget surface, resize it,
@@ -314,14 +396,19 @@ impl Device {
     ///
     /// Only the given context may ever render to the surface, but generic surfaces can be wrapped
     /// up in a `SurfaceTexture` for reading by other contexts.
+    ///
+    /// Widget surfaces can only be created on the EGL device that the compositor renders on; other
+    /// devices return an `IncompatibleNativeWidget` error.
     pub fn create_surface(
         &self,
         context: &Context,
-        _: SurfaceAccess,
+        surface_access: SurfaceAccess,
         surface_type: SurfaceType<NativeWidget>,
     ) -> Result<Surface, Error> {
         match surface_type {
-            SurfaceType::Generic { size } => self.create_generic_surface(context, &size),
+            SurfaceType::Generic { size } => {
+                self.create_generic_surface(context, surface_access, &size)
+            }
             SurfaceType::Widget { native_widget } => unsafe {
                 self.create_window_surface(
                     context,
@@ -335,20 +422,23 @@ impl Device {
     fn create_generic_surface(
         &self,
         context: &Context,
+        surface_access: SurfaceAccess,
         size: &Size2D<i32>,
     ) -> Result<Surface, Error> {
         let _guard = self.temporarily_make_context_current(context)?;
         let context_descriptor = self.context_descriptor(context);
         let context_attributes = self.context_descriptor_attributes(&context_descriptor);
 
-        Ok(Surface(EGLBackedSurface::new_generic(
+        EGLBackedSurface::new_generic(
             &context.1,
-            self.native_connection.egl_display,
+            self.egl_display,
             context.0.egl_context,
             context.0.id,
             &context_attributes,
+            surface_access,
             size,
-        )))
+        )
+        .map(Surface)
     }
 
     unsafe fn create_window_surface(
@@ -357,18 +447,20 @@ impl Device {
         wayland_surface: *mut wl_proxy,
         size: &Size2D<i32>,
     ) -> Result<Surface, Error> {
+        // Only the display server's own display can show windows.
+        if self.egl_display != self.native_connection.egl_display {
+            return Err(Error::IncompatibleNativeWidget);
+        }
         let egl_window =
             (wayland_egl_handle().wl_egl_window_create)(wayland_surface, size.width, size.height);
         assert!(!egl_window.is_null());
 
         let context_descriptor = self.context_descriptor(context);
-        let egl_config = context::egl_config_from_id(
-            self.native_connection.egl_display,
-            context_descriptor.egl_config_id,
-        );
+        let egl_config =
+            context::egl_config_from_id(self.egl_display, context_descriptor.egl_config_id);
 
         Ok(Surface(EGLBackedSurface::new_window(
-            self.native_connection.egl_display,
+            self.egl_display,
             egl_config,
             egl_window as *mut c_void,
             context.0.id,
@@ -414,7 +506,7 @@ impl Device {
         context: &mut Context,
         surface: &mut Surface,
     ) -> Result<(), Error> {
-        let egl_display = self.native_connection.egl_display;
+        let egl_display = self.egl_display;
         if let Some(wayland_egl_window) =
             surface.0.destroy(&context.1, egl_display, context.0.id)?
         {
@@ -454,7 +546,73 @@ impl Device {
     pub fn present_surface(&self, context: &Context, surface: &mut Surface) -> Result<(), Error> {
         surface
             .0
-            .present(self.native_connection.egl_display, context.0.egl_context)
+            .present(self.egl_display, context.0.egl_context, &[])
+    }
+
+    /// Displays the contents of a widget surface on screen, telling the compositor that only the
+    /// given rectangles have changed since the surface was last presented.
+    ///
+    /// The rectangles are relative to the top left corner of the surface. An empty list damages
+    /// the whole surface. If the display supports neither `EGL_KHR_swap_buffers_with_damage` nor
+    /// `EGL_EXT_swap_buffers_with_damage`, this presents the whole surface like
+    /// `present_surface()`.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        surface
+            .0
+            .present(self.egl_display, context.0.egl_context, damage)
+    }
+
+    /// Returns how many frames old the contents of the back buffer of a widget surface are.
+    ///
+    /// An age of 1 means that the back buffer holds the contents that were presented last, 2
+    /// means the contents of the frame before that, and so on. An age of 0 means that the
+    /// contents are undefined, and the whole surface must be redrawn. Returns
+    /// `RequiredExtensionUnavailable` if the display supports neither `EGL_EXT_buffer_age` nor
+    /// `EGL_KHR_partial_update`, or `NoWidgetAttached` for generic surfaces.
+    pub fn surface_buffer_age(&self, context: &Context, surface: &Surface) -> Result<u32, Error> {
+        surface
+            .0
+            .buffer_age(self.egl_display, context.0.egl_context)
+    }
+
+    /// Tells the driver that only the given rectangles of a widget surface will be rendered to
+    /// before it's next presented, so that it can avoid loading the rest of the back buffer.
+    ///
+    /// This must be called at most once per frame, before rendering to the surface. The
+    /// rectangles are relative to the top left corner of the surface, and an empty list damages
+    /// the whole surface. Returns `RequiredExtensionUnavailable` if the display doesn't support
+    /// `EGL_KHR_partial_update`, in which case the whole surface can be rendered to as usual.
+    pub fn set_damage_region(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        damage: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        surface
+            .0
+            .set_damage_region(self.egl_display, context.0.egl_context, damage)
+    }
+
+    /// Sets the minimum number of display refreshes between presentations of a widget surface.
+    ///
+    /// An interval of 0 presents immediately without waiting for vertical sync, and 1 waits for
+    /// one refresh. The interval is kept while the surface is bound to and unbound from contexts.
+    /// Returns `UnsupportedSwapInterval` if the surface's EGL config doesn't support the interval,
+    /// or `NoWidgetAttached` for generic surfaces.
+    pub fn set_swap_interval(
+        &self,
+        context: &Context,
+        surface: &Surface,
+        interval: i32,
+    ) -> Result<(), Error> {
+        surface
+            .0
+            .set_swap_interval(self.egl_display, context.0.egl_context, interval)
     }
 
     /// Resizes a widget surface.
@@ -468,9 +626,117 @@ impl Device {
     }
 
     /// Returns a pointer to the underlying surface data for reading or writing by the CPU.
+    ///
+    /// A context created on this device must be current. The surface contents are read back with
+    /// that context, and any changes are written back with it when the guard is dropped.
+    ///
+    /// Returns `SurfaceDataInaccessible` if the surface was created with `SurfaceAccess::GPUOnly`,
+    /// `WidgetAttached` if it is a widget surface, or `NoCurrentContext` if no context on this
+    /// device is current.
     #[inline]
-    pub fn lock_surface_data<'s>(&self, _: &'s mut Surface) -> Result<SurfaceDataGuard<'s>, Error> {
-        Err(Error::Unimplemented)
+    pub fn lock_surface_data<'s>(
+        &self,
+        surface: &'s mut Surface,
+    ) -> Result<SurfaceDataGuard<'s>, Error> {
+        surface.0.lock_data(self.egl_display).map(SurfaceDataGuard)
+    }
+
+    /// Reads back a rectangle of pixels from a generic surface.
+    ///
+    /// The rectangle is relative to the top left corner of the surface, and the returned rows are
+    /// ordered from top to bottom in the requested pixel format.
+    ///
+    /// The supplied context must be the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned. Widget surfaces return a `WidgetAttached` error.
+    pub fn read_surface_pixels(
+        &self,
+        context: &mut Context,
+        surface: &Surface,
+        rect: Rect<i32>,
+        format: PixelFormat,
+    ) -> Result<Vec<u8>, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        surface
+            .0
+            .read_pixels(&context.1, context.0.id, rect, format)
+    }
+
+    /// Starts copying the contents of a generic surface into CPU-visible memory.
+    ///
+    /// This does not wait for the GPU. Use `poll_surface_readback()` to check whether the copy
+    /// has finished and `map_surface_readback()` to retrieve the pixels.
+    ///
+    /// The supplied context must be the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned. Widget surfaces return a `WidgetAttached` error.
+    pub fn begin_surface_readback(
+        &self,
+        context: &mut Context,
+        surface: &Surface,
+    ) -> Result<ReadbackHandle, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        surface.0.begin_readback(&context.1, context.0.id)
+    }
+
+    /// Returns true if the GPU has finished a readback, so that `map_surface_readback()` will not
+    /// block.
+    ///
+    /// The supplied context must be the context the readback was started with, or an
+    /// `IncompatibleContext` error is returned.
+    pub fn poll_surface_readback(
+        &self,
+        context: &Context,
+        readback: &ReadbackHandle,
+    ) -> Result<bool, Error> {
+        if readback.context_id != context.0.id {
+            return Err(Error::IncompatibleContext);
+        }
+        let _guard = self.temporarily_make_context_current(context)?;
+        Ok(readback.poll(&context.1))
+    }
+
+    /// Returns the pixels of a readback in RGBA format, with rows ordered from top to bottom, and
+    /// frees the readback.
+    ///
+    /// If the GPU has not yet finished the copy, this waits for it. The supplied context must be
+    /// the context the readback was started with, or an `IncompatibleContext` error is returned.
+    /// On failure, the readback is returned alongside the error, so that it can be mapped again
+    /// or destroyed.
+    pub fn map_surface_readback(
+        &self,
+        context: &mut Context,
+        readback: ReadbackHandle,
+    ) -> Result<Vec<u8>, (Error, ReadbackHandle)> {
+        if readback.context_id != context.0.id {
+            return Err((Error::IncompatibleContext, readback));
+        }
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, readback)),
+        };
+        readback.map(&context.1)
+    }
+
+    /// Frees a readback without mapping it.
+    ///
+    /// The supplied context must be the context the readback was started with, or an
+    /// `IncompatibleContext` error is returned alongside the readback.
+    ///
+    /// You must call either this method or `map_surface_readback()` to dispose of a readback.
+    /// Otherwise, a panic occurs in the `drop` method.
+    pub fn destroy_surface_readback(
+        &self,
+        context: &mut Context,
+        mut readback: ReadbackHandle,
+    ) -> Result<(), (Error, ReadbackHandle)> {
+        if readback.context_id != context.0.id {
+            return Err((Error::IncompatibleContext, readback));
+        }
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, readback)),
+        };
+        readback.destroy(&context.1);
+        Ok(())
     }
 
     /// Returns the OpenGL texture target needed to read from this surface texture.
@@ -498,4 +764,90 @@ impl Device {
     pub fn surface_texture_object(&self, surface_texture: &SurfaceTexture) -> Option<Texture> {
         surface_texture.0.texture_object
     }
+
+    /// Exports the contents of a generic surface as a Linux dma-buf, so that other processes or
+    /// APIs can use them without copying.
+    ///
+    /// The surface's contents are only guaranteed to be visible through the dma-buf once the
+    /// surface has been unbound from its context, which resolves multisampled surfaces and flushes
+    /// rendering.
+    ///
+    /// Widget surfaces return a `WidgetAttached` error. If the EGL implementation doesn't support
+    /// `EGL_MESA_image_dma_buf_export`, this returns `RequiredExtensionUnavailable`.
+    pub fn export_surface_dmabuf(&self, surface: &Surface) -> Result<DmaBufDescriptor, Error> {
+        surface.0.export_dmabuf(self.egl_display)
+    }
+
+    /// Creates a generic surface that renders into an existing Linux dma-buf, such as one exported
+    /// by another process.
+    ///
+    /// The buffer must be in a format that a `SurfaceFormat` corresponds to, or an
+    /// `UnsupportedSurfaceFormat` error is returned. The surface uses the depth, stencil, and
+    /// multisampling attributes of the supplied context. The descriptor's file descriptors are
+    /// duplicated, so the caller keeps ownership of them.
+    pub fn create_surface_from_dmabuf(
+        &self,
+        context: &Context,
+        surface_access: SurfaceAccess,
+        descriptor: &DmaBufDescriptor,
+    ) -> Result<Surface, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        let context_descriptor = self.context_descriptor(context);
+        let context_attributes = self.context_descriptor_attributes(&context_descriptor);
+
+        EGLBackedSurface::new_generic_from_dmabuf(
+            &context.1,
+            self.egl_display,
+            context.0.id,
+            &context_attributes,
+            surface_access,
+            descriptor,
+        )
+        .map(Surface)
+    }
+
+    /// Wraps an existing Linux dma-buf, such as a frame from a video decoder, in a surface texture
+    /// for use with the given context.
+    ///
+    /// Buffers with several planes, including YUV buffers, are supported as long as the driver can
+    /// sample them; otherwise an `UnsupportedSurfaceFormat` error is returned. Buffers that can
+    /// only be sampled as external textures are bound to `GL_TEXTURE_EXTERNAL_OES`, as reported by
+    /// `surface_texture_gl_texture_target()`. Use `dmabuf_formats()` to find out which formats the
+    /// driver accepts.
+    ///
+    /// Destroying the surface texture returns a surface that can't be rendered to, and which only
+    /// needs to be destroyed. The descriptor's file descriptors are duplicated, so the caller keeps
+    /// ownership of them.
+    pub fn create_surface_texture_from_dmabuf(
+        &self,
+        context: &mut Context,
+        descriptor: &DmaBufDescriptor,
+    ) -> Result<SurfaceTexture, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        EGLBackedSurface::new_texture_from_dmabuf(
+            &context.1,
+            self.egl_display,
+            context.0.id,
+            descriptor,
+        )
+        .map(SurfaceTexture)
+    }
+
+    /// Returns the dma-buf formats, and the modifiers for each format, that this device can
+    /// import.
+    ///
+    /// If the EGL implementation doesn't support `EGL_EXT_image_dma_buf_import_modifiers`, this
+    /// returns `RequiredExtensionUnavailable`.
+    pub fn dmabuf_formats(&self) -> Result<Vec<DmaBufFormat>, Error> {
+        unsafe { dmabuf::query_formats(self.egl_display) }
+    }
+
+    /// Returns the OpenGL texture target needed to read from the given surface texture.
+    ///
+    /// This is `GL_TEXTURE_EXTERNAL_OES` for surface textures imported from dma-bufs that the
+    /// driver can only sample as external textures, and `GL_TEXTURE_2D` otherwise.
+    #[inline]
+    pub fn surface_texture_gl_texture_target(&self, surface_texture: &SurfaceTexture) -> u32 {
+        surface_texture.0.texture_target
+    }
 }
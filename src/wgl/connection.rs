@@ -4,8 +4,7 @@
 
 use super::device::{Adapter, Device, NativeDevice};
 use super::surface::NativeWidget;
-use crate::Error;
-use crate::GLApi;
+use crate::{AdapterInfo, Error, GLApi};
 
 use euclid::default::Size2D;
 
@@ -76,6 +75,12 @@ impl Connection {
         self.create_low_power_adapter()
     }
 
+    /// Returns an empty list, since this backend can't enumerate adapters.
+    #[inline]
+    pub fn enumerate_adapters(&self) -> Vec<AdapterInfo<Adapter>> {
+        vec![]
+    }
+
     /// Opens a device.
     #[inline]
     pub fn create_device(&self, adapter: &Adapter) -> Result<Device, Error> {
@@ -91,6 +91,7 @@ pub struct ContextDescriptor {
     pub(crate) pixel_format: c_int,
     pub(crate) gl_version: GLVersion,
     pub(crate) compatibility_profile: bool,
+    pub(crate) debug: bool,
 }
 
 /// Represents an OpenGL rendering context.
@@ -129,6 +130,11 @@ pub(crate) enum ContextStatus {
 #[derive(Clone)]
 pub struct NativeContext(pub HGLRC);
 
+/// Represents a GPU fence.
+///
+/// Fences aren't supported on this backend, so values of this type can't be created.
+pub enum Fence {}
+
 thread_local! {
     pub(crate) static OPENGL_LIBRARY: HMODULE = {
         unsafe {
@@ -2,19 +2,22 @@
 //
 //! An implementation of the GPU device for Windows using the WGL API.
 
-use crate::context::{current_context_uses_compatibility_profile, ContextID, CREATE_CONTEXT_MUTEX};
+use crate::context::{current_context_is_debug, current_context_uses_compatibility_profile};
+use crate::context::{ContextID, CREATE_CONTEXT_MUTEX};
 use crate::error::WindowingApiError;
+use crate::readback::ReadbackHandle;
 use crate::renderbuffers::Renderbuffers;
 use crate::surface::Framebuffer;
 use crate::wgl::connection::Connection;
 use crate::wgl::context::{
-    Context, ContextDescriptor, ContextStatus, CurrentContextGuard, FramebufferGuard,
+    Context, ContextDescriptor, ContextStatus, CurrentContextGuard, Fence, FramebufferGuard,
     NativeContext, OPENGL_LIBRARY, WGL_EXTENSION_FUNCTIONS,
 };
 use crate::wgl::surface::{NativeWidget, Surface, SurfaceDataGuard, SurfaceTexture, Win32Objects};
-use crate::{gl, gl_utils, GLApi, Gl, SurfaceAccess, SurfaceType};
-use crate::{ContextAttributeFlags, ContextAttributes, Error, GLVersion, SurfaceInfo};
-use euclid::default::Size2D;
+use crate::{gl, gl_utils, GLApi, Gl, PixelFormat, SurfaceAccess, SurfaceType};
+use crate::{ContextAttributeFlags, ContextAttributes, ContextResetStatus, Error, GLVersion};
+use crate::{DepthFormat, SurfaceFormat, SurfaceInfo};
+use euclid::default::{Rect, Size2D};
 use glow::HasContext;
 use libc::c_uint;
 use log::warn;
@@ -25,6 +28,7 @@ use std::os::raw::{c_int, c_void};
 use std::ptr;
 use std::sync::mpsc::{self, Sender};
 use std::thread::{self, JoinHandle};
+use std::time::Duration;
 use winapi::shared::dxgi::IDXGIResource;
 use winapi::shared::dxgi::{IDXGIAdapter, IDXGIDevice};
 use winapi::shared::dxgiformat::DXGI_FORMAT_R8G8B8A8_UNORM;
@@ -71,9 +75,11 @@ const WGL_FULL_ACCELERATION_ARB: GLenum = 0x2027;
 const WGL_TYPE_RGBA_ARB: GLenum = 0x202b;
 const WGL_CONTEXT_MAJOR_VERSION_ARB: GLenum = 0x2091;
 const WGL_CONTEXT_MINOR_VERSION_ARB: GLenum = 0x2092;
+const WGL_CONTEXT_FLAGS_ARB: GLenum = 0x2094;
 const WGL_CONTEXT_PROFILE_MASK_ARB: GLenum = 0x9126;
 const WGL_CONTEXT_CORE_PROFILE_BIT_ARB: GLenum = 0x00000001;
 const WGL_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB: GLenum = 0x00000002;
+const WGL_CONTEXT_DEBUG_BIT_ARB: GLenum = 0x00000001;
 
 pub(crate) const HIDDEN_WINDOW_SIZE: c_int = 16;
 
@@ -295,6 +301,19 @@ impl Device {
         &self,
         attributes: &ContextAttributes,
     ) -> Result<ContextDescriptor, Error> {
+        // Surfaces on this backend are always RGBA8.
+        if attributes.surface_format != SurfaceFormat::RGBA8 {
+            return Err(Error::UnsupportedSurfaceFormat);
+        }
+
+        // Robust contexts are only supported on the EGL backends.
+        if attributes
+            .flags
+            .contains(ContextAttributeFlags::ROBUST_ACCESS)
+        {
+            return Err(Error::UnsupportedOnThisPlatform);
+        }
+
         let flags = attributes.flags;
         let alpha_bits = if flags.contains(ContextAttributeFlags::ALPHA) {
             8
@@ -312,6 +331,7 @@ impl Device {
             0
         };
         let compatibility_profile = flags.contains(ContextAttributeFlags::COMPATIBILITY_PROFILE);
+        let debug = flags.contains(ContextAttributeFlags::DEBUG);
 
         let attrib_i_list = [
             WGL_DRAW_TO_WINDOW_ARB as c_int,
@@ -362,6 +382,7 @@ impl Device {
                 pixel_format,
                 gl_version: attributes.version,
                 compatibility_profile,
+                debug,
             })
         }
     }
@@ -383,7 +404,7 @@ impl Device {
 
         let mut next_context_id = CREATE_CONTEXT_MUTEX.lock().unwrap();
         unsafe {
-            let (glrc, gl);
+            let (glrc, mut gl);
 
             // Get a suitable DC.
             let hidden_window = HiddenWindow::new();
@@ -400,6 +421,11 @@ impl Device {
                 } else {
                     WGL_CONTEXT_CORE_PROFILE_BIT_ARB
                 };
+                let context_flags = if descriptor.debug {
+                    WGL_CONTEXT_DEBUG_BIT_ARB
+                } else {
+                    0
+                };
                 let wgl_attributes = [
                     WGL_CONTEXT_MAJOR_VERSION_ARB as c_int,
                     descriptor.gl_version.major as c_int,
@@ -407,6 +433,8 @@ impl Device {
                     descriptor.gl_version.minor as c_int,
                     WGL_CONTEXT_PROFILE_MASK_ARB as c_int,
                     profile_mask as c_int,
+                    WGL_CONTEXT_FLAGS_ARB as c_int,
+                    context_flags as c_int,
                     0,
                 ];
                 glrc = wglCreateContextAttribsARB(
@@ -425,6 +453,9 @@ impl Device {
 
                 // Load the GL functions.
                 gl = Gl::from_loader_function(get_proc_address);
+                if descriptor.debug {
+                    gl_utils::install_debug_message_callback(&mut gl, *next_context_id);
+                }
             }
 
             // Create the initial context.
@@ -512,11 +543,13 @@ impl Device {
 
             let gl_version = GLVersion::current(&context.gl);
             let compatibility_profile = current_context_uses_compatibility_profile(&context.gl);
+            let debug = current_context_is_debug(&context.gl);
 
             ContextDescriptor {
                 pixel_format,
                 gl_version,
                 compatibility_profile,
+                debug,
             }
         }
     }
@@ -563,6 +596,9 @@ impl Device {
             let mut attributes = ContextAttributes {
                 version: context_descriptor.gl_version,
                 flags: ContextAttributeFlags::empty(),
+                samples: 0,
+                surface_format: SurfaceFormat::RGBA8,
+                depth_format: DepthFormat::Depth24,
             };
             if alpha_bits > 0 {
                 attributes.flags.insert(ContextAttributeFlags::ALPHA);
@@ -573,11 +609,50 @@ impl Device {
             if stencil_bits > 0 {
                 attributes.flags.insert(ContextAttributeFlags::STENCIL);
             }
+            if context_descriptor.debug {
+                attributes.flags.insert(ContextAttributeFlags::DEBUG);
+            }
 
             attributes
         }
     }
 
+    /// Returns the largest number of samples per pixel that generic surfaces created with this
+    /// context can use.
+    ///
+    /// Multisampled generic surfaces aren't supported on this backend, so this always returns 0.
+    #[inline]
+    pub fn max_surface_samples(&self, _: &Context) -> Result<u32, Error> {
+        Ok(0)
+    }
+
+    /// Returns whether the context has been lost because of a GPU reset, and if so, whether this
+    /// context caused it.
+    pub fn context_reset_status(&self, context: &Context) -> Result<ContextResetStatus, Error> {
+        let _guard = self.temporarily_make_context_current(context)?;
+        gl_utils::context_reset_status(&context.gl, get_proc_address)
+    }
+
+    /// Returns `RequiredExtensionUnavailable`, since fences aren't supported on this backend.
+    pub fn create_fence(&self, _: &Context) -> Result<Fence, Error> {
+        Err(Error::RequiredExtensionUnavailable)
+    }
+
+    /// Fences can't be created on this backend, so this can't be called.
+    pub fn client_wait_fence(&self, fence: &Fence, _: Option<Duration>) -> Result<bool, Error> {
+        match *fence {}
+    }
+
+    /// Fences can't be created on this backend, so this can't be called.
+    pub fn server_wait_fence(&self, _: &Context, fence: &Fence) -> Result<(), Error> {
+        match *fence {}
+    }
+
+    /// Fences can't be created on this backend, so this can't be called.
+    pub fn destroy_fence(&self, fence: &mut Fence) -> Result<(), Error> {
+        match *fence {}
+    }
+
     pub(crate) fn temporarily_bind_framebuffer<'a>(
         &self,
         context: &'a Context,
@@ -1221,6 +1296,130 @@ impl Device {
         Err(Error::Unimplemented)
     }
 
+    /// Reads back a rectangle of pixels from a generic surface.
+    ///
+    /// The rectangle is relative to the top left corner of the surface, and the returned rows are
+    /// ordered from top to bottom in the requested pixel format.
+    ///
+    /// The supplied context must be the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned. Widget surfaces return a `WidgetAttached` error.
+    pub fn read_surface_pixels(
+        &self,
+        context: &mut Context,
+        surface: &Surface,
+        rect: Rect<i32>,
+        format: PixelFormat,
+    ) -> Result<Vec<u8>, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let framebuffer_object = match surface.win32_objects {
+            Win32Objects::Texture { gl_framebuffer, .. } => gl_framebuffer,
+            Win32Objects::Widget { .. } => return Err(Error::WidgetAttached),
+        };
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        self.lock_surface(surface);
+        let result = gl_utils::read_framebuffer_pixels(
+            &context.gl,
+            framebuffer_object,
+            surface.size,
+            rect,
+            format,
+        );
+        self.unlock_surface(surface);
+        result
+    }
+
+    /// Starts copying the contents of a generic surface into CPU-visible memory.
+    ///
+    /// This does not wait for the GPU. Use `poll_surface_readback()` to check whether the copy
+    /// has finished and `map_surface_readback()` to retrieve the pixels.
+    ///
+    /// The supplied context must be the context the surface was created with, or an
+    /// `IncompatibleSurface` error is returned. Widget surfaces return a `WidgetAttached` error.
+    pub fn begin_surface_readback(
+        &self,
+        context: &mut Context,
+        surface: &Surface,
+    ) -> Result<ReadbackHandle, Error> {
+        if context.id != surface.context_id {
+            return Err(Error::IncompatibleSurface);
+        }
+        let framebuffer_object = match surface.win32_objects {
+            Win32Objects::Texture { gl_framebuffer, .. } => gl_framebuffer,
+            Win32Objects::Widget { .. } => return Err(Error::WidgetAttached),
+        };
+
+        let _guard = self.temporarily_make_context_current(context)?;
+        self.lock_surface(surface);
+        let result = ReadbackHandle::new(&context.gl, context.id, framebuffer_object, surface.size);
+        self.unlock_surface(surface);
+        result
+    }
+
+    /// Returns true if the GPU has finished a readback, so that `map_surface_readback()` will not
+    /// block.
+    ///
+    /// The supplied context must be the context the readback was started with, or an
+    /// `IncompatibleContext` error is returned.
+    pub fn poll_surface_readback(
+        &self,
+        context: &Context,
+        readback: &ReadbackHandle,
+    ) -> Result<bool, Error> {
+        if readback.context_id != context.id {
+            return Err(Error::IncompatibleContext);
+        }
+        let _guard = self.temporarily_make_context_current(context)?;
+        Ok(readback.poll(&context.gl))
+    }
+
+    /// Returns the pixels of a readback in RGBA format, with rows ordered from top to bottom, and
+    /// frees the readback.
+    ///
+    /// If the GPU has not yet finished the copy, this waits for it. The supplied context must be
+    /// the context the readback was started with, or an `IncompatibleContext` error is returned.
+    /// On failure, the readback is returned alongside the error, so that it can be mapped again
+    /// or destroyed.
+    pub fn map_surface_readback(
+        &self,
+        context: &mut Context,
+        readback: ReadbackHandle,
+    ) -> Result<Vec<u8>, (Error, ReadbackHandle)> {
+        if readback.context_id != context.id {
+            return Err((Error::IncompatibleContext, readback));
+        }
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, readback)),
+        };
+        readback.map(&context.gl)
+    }
+
+    /// Frees a readback without mapping it.
+    ///
+    /// The supplied context must be the context the readback was started with, or an
+    /// `IncompatibleContext` error is returned alongside the readback.
+    ///
+    /// You must call either this method or `map_surface_readback()` to dispose of a readback.
+    /// Otherwise, a panic occurs in the `drop` method.
+    pub fn destroy_surface_readback(
+        &self,
+        context: &mut Context,
+        mut readback: ReadbackHandle,
+    ) -> Result<(), (Error, ReadbackHandle)> {
+        if readback.context_id != context.id {
+            return Err((Error::IncompatibleContext, readback));
+        }
+        let _guard = match self.temporarily_make_context_current(context) {
+            Ok(guard) => guard,
+            Err(err) => return Err((err, readback)),
+        };
+        readback.destroy(&context.gl);
+        Ok(())
+    }
+
     /// Returns the OpenGL texture target needed to read from this surface texture.
     ///
     /// This will be `GL_TEXTURE_2D` or `GL_TEXTURE_RECTANGLE`, depending on platform.
@@ -1240,6 +1439,41 @@ impl Device {
         surface.present()
     }
 
+    /// Displays the contents of a widget surface on screen.
+    ///
+    /// This backend can't present only part of a surface, so the damaged rectangles are ignored
+    /// and the whole surface is presented like `present_surface()`.
+    pub fn present_surface_with_damage(
+        &self,
+        context: &Context,
+        surface: &mut Surface,
+        _: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        self.present_surface(context, surface)
+    }
+
+    /// Returns `Unimplemented`, since this backend can't query the age of a surface's back
+    /// buffer.
+    pub fn surface_buffer_age(&self, _: &Context, _: &Surface) -> Result<u32, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Returns `Unimplemented`, since this backend can't limit rendering to part of a surface.
+    /// The whole surface can be rendered to as usual.
+    pub fn set_damage_region(
+        &self,
+        _: &Context,
+        _: &mut Surface,
+        _: &[Rect<i32>],
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Returns `Unimplemented`, since this backend doesn't support changing the swap interval.
+    pub fn set_swap_interval(&self, _: &Context, _: &Surface, _: i32) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Resizes a widget surface.
     pub fn resize_surface(
         &self,
@@ -1261,12 +1495,20 @@ impl Device {
     pub fn surface_info(&self, surface: &Surface) -> SurfaceInfo {
         SurfaceInfo {
             size: surface.size,
+            valid_size: surface.size,
             id: surface.id(),
             context_id: surface.context_id,
             framebuffer_object: match surface.win32_objects {
                 Win32Objects::Texture { gl_framebuffer, .. } => gl_framebuffer,
                 Win32Objects::Widget { .. } => None,
             },
+            format: Some(SurfaceFormat::RGBA8),
+            depth_format: match surface.win32_objects {
+                Win32Objects::Texture {
+                    ref renderbuffers, ..
+                } => renderbuffers.depth_format(),
+                Win32Objects::Widget { .. } => None,
+            },
         }
     }
 
@@ -147,3 +147,18 @@ impl Surface {
 pub struct SurfaceDataGuard<'a> {
     phantom: PhantomData<&'a ()>,
 }
+
+// CPU access is not yet supported on this backend, so this guard is never constructed.
+impl<'a> SurfaceDataGuard<'a> {
+    /// Returns the number of bytes per row of the surface.
+    #[inline]
+    pub fn stride(&self) -> usize {
+        0
+    }
+
+    /// Returns a mutable slice of the pixel data in this surface.
+    #[inline]
+    pub fn data(&mut self) -> &mut [u8] {
+        &mut []
+    }
+}
@@ -2,8 +2,7 @@
 //
 //! A surface implementation using X11 surfaces backed by TextureImage.
 
-use crate::base::egl::surface::{EGLBackedSurface, EGLSurfaceTexture};
-use std::marker::PhantomData;
+use crate::base::egl::surface::{EGLBackedSurface, EGLSurfaceDataGuard, EGLSurfaceTexture};
 use x11_dl::xlib::Window;
 
 /// Represents a hardware buffer of pixels that can be rendered to via the CPU or GPU and either
@@ -46,6 +45,23 @@ pub struct NativeWidget {
 unsafe impl Send for Surface {}
 
 /// Represents the CPU view of the pixel data of this surface.
-pub struct SurfaceDataGuard<'a> {
-    phantom: PhantomData<&'a ()>,
+///
+/// The pixel data is a copy of the surface contents. Any changes made to it are written back to
+/// the surface when this guard is dropped.
+pub struct SurfaceDataGuard<'a>(pub(crate) EGLSurfaceDataGuard<'a>);
+
+impl<'a> SurfaceDataGuard<'a> {
+    /// Returns the number of bytes per row of the surface.
+    #[inline]
+    pub fn stride(&self) -> usize {
+        self.0.stride()
+    }
+
+    /// Returns a mutable slice of the pixel data in this surface, in RGBA format.
+    ///
+    /// Rows are stored top to bottom.
+    #[inline]
+    pub fn data(&mut self) -> &mut [u8] {
+        self.0.data()
+    }
 }